@@ -0,0 +1,302 @@
+// -----------------------------------------------------------------------------------------------
+// Module apsp
+//
+// All pairs shortest paths. Floyd-Warshall is simple and fine for small graphs (O(N^3) time,
+// O(N^2) memory), Johnson's algorithm re-weights the edges with Bellman-Ford so that it can run
+// Dijkstra from every node, which is faster on sparse graphs such as road networks. Both handle
+// negative edge weights and report a negative cycle instead of producing garbage distances.
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, Write};
+
+use crate::bellman_ford::relax_edges;
+use crate::graphtraits::{EdgeId, GraphView, NodeId, Path, valid_edges};
+use crate::toposort::Cycle;
+
+const INFINITY: i64 = i64::MAX;
+
+/// Distances between all pairs of nodes of a graph, including the shortest paths.
+pub struct DistanceMatrix {
+    nodes: Vec<NodeId>,
+    index: HashMap<NodeId, usize>,
+    /// row major, `dist[i * n + j]` is the distance from `nodes[i]` to `nodes[j]`
+    dist: Vec<i64>,
+    /// the last edge on the shortest path from `nodes[i]` to `nodes[j]`
+    last_edge: Vec<Option<EdgeId>>,
+}
+
+impl DistanceMatrix {
+    fn new(nodes: Vec<NodeId>) -> Self {
+        let n = nodes.len();
+        let index = nodes.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let mut dist = vec![INFINITY; n * n];
+        for i in 0..n {
+            dist[i * n + i] = 0;
+        }
+        DistanceMatrix {
+            nodes,
+            index,
+            dist,
+            last_edge: vec![None; n * n],
+        }
+    }
+
+    /// The nodes of the matrix (rows and columns), sorted by ID.
+    pub fn nodes(&self) -> &[NodeId] {
+        &self.nodes
+    }
+
+    /// The length of the shortest path from `from` to `to`, `None` if there is no path.
+    pub fn distance(&self, from: NodeId, to: NodeId) -> Option<i64> {
+        let n = self.nodes.len();
+        let d = self.dist[self.index.get(&from)? * n + self.index.get(&to)?];
+        (d != INFINITY).then_some(d)
+    }
+
+    /// Reconstructs the shortest path from `from` to `to` as a list of edges.
+    pub fn path<T, G: GraphView<T> + ?Sized>(
+        &self,
+        g: &G,
+        from: NodeId,
+        to: NodeId,
+    ) -> Option<Path> {
+        self.distance(from, to)?;
+        let n = self.nodes.len();
+        let row = self.index[&from] * n;
+        let mut path = Vec::new();
+        let mut node = to;
+        while node != from {
+            let edge_id = self.last_edge[row + self.index[&node]]?;
+            path.push(edge_id);
+            node = g.get_edge(edge_id)?.0;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Writes the matrix as CSV. The first row and column hold the node IDs, unreachable
+    /// pairs are left empty.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "node_id")?;
+        for id in self.nodes.iter() {
+            write!(writer, ",{}", id)?;
+        }
+        writeln!(writer)?;
+        let n = self.nodes.len();
+        for (i, id) in self.nodes.iter().enumerate() {
+            write!(writer, "{}", id)?;
+            for d in self.dist[i * n..(i + 1) * n].iter() {
+                if *d == INFINITY {
+                    write!(writer, ",")?;
+                } else {
+                    write!(writer, ",{}", d)?;
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the matrix as CSV string, see [`DistanceMatrix::write_csv`].
+    pub fn to_csv(&self) -> String {
+        let mut buffer = Vec::new();
+        self.write_csv(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+/// Bellman-Ford with a virtual source that has a 0 weight edge to every node. Returns the
+/// potential of every node or a negative cycle.
+fn potentials<T, G: GraphView<T> + ?Sized>(g: &G) -> Result<HashMap<NodeId, i64>, Cycle> {
+    // starting with 0 everywhere is the same as having relaxed the edges of the virtual source
+    let dist: HashMap<NodeId, i64> = g.node_ids().into_iter().map(|id| (id, 0)).collect();
+    relax_edges(g, dist).map(|(dist, _)| dist)
+}
+
+/// All pairs shortest paths with the Floyd-Warshall algorithm.
+///
+/// # Returns
+///
+/// The distance matrix or a negative cycle (then there is no shortest path between the nodes
+/// that can reach the cycle).
+pub fn floyd_warshall<T, G: GraphView<T> + ?Sized>(g: &G) -> Result<DistanceMatrix, Cycle> {
+    let mut m = DistanceMatrix::new(g.node_ids());
+    let n = m.nodes.len();
+    for (edge_id, from, to, weight) in valid_edges(g) {
+        let weight = weight as i64;
+        let cell = m.index[&from] * n + m.index[&to];
+        if weight < m.dist[cell] {
+            m.dist[cell] = weight;
+            m.last_edge[cell] = Some(edge_id);
+        }
+    }
+
+    for k in 0..n {
+        for i in 0..n {
+            let d_ik = m.dist[i * n + k];
+            if d_ik == INFINITY {
+                continue;
+            }
+            for j in 0..n {
+                let d_kj = m.dist[k * n + j];
+                if d_kj != INFINITY && d_ik + d_kj < m.dist[i * n + j] {
+                    m.dist[i * n + j] = d_ik + d_kj;
+                    m.last_edge[i * n + j] = m.last_edge[k * n + j];
+                }
+            }
+        }
+        // stop at the first negative cycle, further rounds would keep lowering the distances
+        // around it until they overflow
+        if (0..n).any(|i| m.dist[i * n + i] < 0) {
+            // let Bellman-Ford produce the cycle itself, the matrix does not track it
+            return Err(potentials(g).err().unwrap());
+        }
+    }
+    Ok(m)
+}
+
+/// All pairs shortest paths with Johnson's algorithm: O(N * E * log N), so for sparse graphs
+/// much faster than [`floyd_warshall`].
+///
+/// The edges are re-weighted with node potentials `h` from Bellman-Ford
+/// (`w'(u, v) = w(u, v) + h(u) - h(v) >= 0`), then Dijkstra runs from every node.
+pub fn johnson<T, G: GraphView<T> + ?Sized>(g: &G) -> Result<DistanceMatrix, Cycle> {
+    let h = potentials(g)?;
+    let mut m = DistanceMatrix::new(g.node_ids());
+    let n = m.nodes.len();
+
+    let mut adjacency: Vec<Vec<(EdgeId, usize, i64)>> = vec![Vec::new(); n];
+    for (edge_id, from, to, weight) in valid_edges(g) {
+        let reweighted = weight as i64 + h[&from] - h[&to];
+        adjacency[m.index[&from]].push((edge_id, m.index[&to], reweighted));
+    }
+
+    for source in 0..n {
+        let row = source * n;
+        let mut dist = vec![INFINITY; n];
+        dist[source] = 0;
+        let mut heap = BinaryHeap::from([Reverse((0i64, source))]);
+        while let Some(Reverse((d, node))) = heap.pop() {
+            if d > dist[node] {
+                continue;
+            }
+            for (edge_id, next, weight) in adjacency[node].iter() {
+                let nd = d + weight;
+                if nd < dist[*next] {
+                    dist[*next] = nd;
+                    m.last_edge[row + next] = Some(*edge_id);
+                    heap.push(Reverse((nd, *next)));
+                }
+            }
+        }
+        let h_source = h[&m.nodes[source]];
+        for target in 0..n {
+            if dist[target] != INFINITY {
+                m.dist[row + target] = dist[target] - h_source + h[&m.nodes[target]];
+            }
+        }
+    }
+    Ok(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphAlgo;
+    use crate::graphtraits::GraphCrud;
+
+    /// 1 -> 2 (4), 1 -> 3 (1), 3 -> 2 (-2), 2 -> 4 (1), 4 is a dead end, 5 is isolated
+    fn negative_weight_graph() -> Graph<i32> {
+        let mut g: Graph<i32> = Graph::new();
+        for v in 1..=5 {
+            g.set_node(v, v as NodeId);
+        }
+        g.new_edge(1, 2, 4);
+        g.new_edge(1, 3, 1);
+        g.new_edge(3, 2, -2);
+        g.new_edge(2, 4, 1);
+        g
+    }
+
+    #[test]
+    fn test_floyd_warshall_and_johnson_agree() {
+        let g = negative_weight_graph();
+        let fw = g.floyd_warshall().unwrap();
+        let jo = g.johnson().unwrap();
+        for from in g.node_ids() {
+            for to in g.node_ids() {
+                assert_eq!(fw.distance(from, to), jo.distance(from, to));
+                assert_eq!(fw.path(&g, from, to), jo.path(&g, from, to));
+            }
+        }
+        assert_eq!(fw.distance(1, 4), Some(0));
+        assert_eq!(fw.distance(4, 1), None);
+        assert_eq!(fw.distance(5, 5), Some(0));
+    }
+
+    #[test]
+    fn test_path_reconstruction() {
+        let g = negative_weight_graph();
+        let m = g.johnson().unwrap();
+        let path = m.path(&g, 1, 4).unwrap();
+        let nodes: Vec<NodeId> = path.iter().map(|e| g.get_edge(*e).unwrap().1).collect();
+        assert_eq!(nodes, vec![3, 2, 4]);
+        assert_eq!(m.path(&g, 2, 2), Some(vec![]));
+        assert_eq!(m.path(&g, 4, 1), None);
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let g = negative_weight_graph();
+        let csv = g.floyd_warshall().unwrap().to_csv();
+        let expected = "node_id,1,2,3,4,5\n\
+                        1,0,-1,1,0,\n\
+                        2,,0,,1,\n\
+                        3,,-2,0,-1,\n\
+                        4,,,,0,\n\
+                        5,,,,,0\n";
+        assert_eq!(csv, expected);
+    }
+
+    #[test]
+    fn test_negative_cycle() {
+        let mut g = negative_weight_graph();
+        let back = g.new_edge(4, 3, 0);
+        for result in [g.floyd_warshall(), g.johnson()] {
+            let cycle = result.err().unwrap();
+            let mut edges = cycle.edges.clone();
+            edges.sort_unstable();
+            // 3 -> 2 -> 4 -> 3 has weight -1
+            let mut expected: Vec<EdgeId> = vec![
+                g.find_edges_from(3).unwrap()[0],
+                g.find_edges_from(2).unwrap()[0],
+                back,
+            ];
+            expected.sort_unstable();
+            assert_eq!(edges, expected);
+            assert_eq!(cycle.nodes.len(), 3);
+            assert_eq!(g.get_edge(cycle.edges[0]).unwrap().0, cycle.nodes[0]);
+        }
+    }
+
+    #[test]
+    fn test_negative_cycles_do_not_overflow() {
+        // every pair of nodes is a negative cycle, the distances must not run away
+        let mut g: Graph<i32> = Graph::new();
+        let nodes: Vec<NodeId> = (0..80).map(|v| g.new_node(v)).collect();
+        for from in nodes.iter() {
+            for to in nodes.iter() {
+                if from != to {
+                    g.new_edge(*from, *to, -1_000_000_000);
+                }
+            }
+        }
+        for result in [g.floyd_warshall(), g.johnson()] {
+            let cycle = result.err().unwrap();
+            assert!(cycle.nodes.len() >= 2);
+        }
+    }
+}
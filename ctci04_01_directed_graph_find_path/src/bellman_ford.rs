@@ -0,0 +1,191 @@
+// -----------------------------------------------------------------------------------------------
+// Module bellman_ford
+//
+// Single source shortest paths with the Bellman-Ford algorithm. Slower than Dijkstra (O(N * E)),
+// but it works with negative edge weights and detects negative cycles, on which a shortest
+// path does not exist (every further round trip makes the path shorter).
+//
+
+use std::collections::HashMap;
+
+use crate::graphtraits::{EdgeId, GraphView, NodeId, Path, valid_edges};
+use crate::toposort::Cycle;
+
+pub type Distances = HashMap<NodeId, i64>;
+pub type Predecessors = HashMap<NodeId, EdgeId>;
+
+/// The result of a single source shortest path search: the distance to every reachable node and
+/// the edge over which it is reached (the predecessor tree).
+#[derive(Debug)]
+pub struct ShortestPathTree {
+    pub source: NodeId,
+    pub dist: Distances,
+    pub pred: Predecessors,
+}
+
+impl ShortestPathTree {
+    /// The length of the shortest path from the source to `to`, `None` if `to` is unreachable.
+    pub fn distance(&self, to: NodeId) -> Option<i64> {
+        self.dist.get(&to).copied()
+    }
+
+    /// Follows the predecessor tree back from `to` and returns the shortest path from the source.
+    pub fn path_to<T, G: GraphView<T> + ?Sized>(&self, g: &G, to: NodeId) -> Option<Path> {
+        self.dist.get(&to)?;
+        let mut path = Vec::new();
+        let mut node = to;
+        while node != self.source {
+            let edge_id = self.pred[&node];
+            path.push(edge_id);
+            node = g.get_edge(edge_id)?.0;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// The Bellman-Ford rounds, starting from the given tentative distances (nodes without an entry
+/// are at infinity). Also used by Johnson's algorithm, which starts with 0 for every node.
+///
+/// # Returns
+///
+/// The final distances and the predecessor edges, or a negative cycle reachable from one of the
+/// nodes that had an initial distance.
+pub(crate) fn relax_edges<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    mut dist: Distances,
+) -> Result<(Distances, Predecessors), Cycle> {
+    let edges = valid_edges(g);
+    let mut pred: Predecessors = HashMap::new();
+    let n = g.node_ids().len();
+    // N - 1 rounds are enough if there is no negative cycle, the N-th round checks for one
+    let mut last_relaxed = None;
+    for _ in 0..n {
+        last_relaxed = None;
+        for (edge_id, from, to, weight) in edges.iter() {
+            let Some(d) = dist.get(from) else {
+                continue;
+            };
+            let nd = d + *weight as i64;
+            if dist.get(to).is_none_or(|old| nd < *old) {
+                dist.insert(*to, nd);
+                pred.insert(*to, *edge_id);
+                last_relaxed = Some(*to);
+            }
+        }
+        if last_relaxed.is_none() {
+            break;
+        }
+    }
+    match last_relaxed {
+        // still relaxing in the last round, there must be a negative cycle
+        Some(node) => Err(negative_cycle(g, &pred, node, n + 1)),
+        None => Ok((dist, pred)),
+    }
+}
+
+/// Walks the predecessor edges back from `node` until a cycle is closed and returns it.
+/// After `n` steps back (the number of nodes) the walk is guaranteed to be on the cycle.
+fn negative_cycle<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    pred: &Predecessors,
+    node: NodeId,
+    n: usize,
+) -> Cycle {
+    let from_of = |id: NodeId| g.get_edge(pred[&id]).unwrap().0;
+    let mut start = node;
+    for _ in 0..n {
+        start = from_of(start);
+    }
+    let mut nodes = vec![start];
+    let mut edges = vec![pred[&start]];
+    let mut node = from_of(start);
+    while node != start {
+        nodes.push(node);
+        edges.push(pred[&node]);
+        node = from_of(node);
+    }
+    // the walk went against the edge direction: nodes[i] is the target of edges[i]
+    nodes.reverse();
+    edges.reverse();
+    nodes.rotate_right(1);
+    Cycle { nodes, edges }
+}
+
+/// Shortest paths from `source` to all nodes with the Bellman-Ford algorithm.
+///
+/// # Returns
+///
+/// * `Ok(tree)`   - distances and predecessor edges of all nodes reachable from `source`
+/// * `Err(cycle)` - a negative cycle reachable from `source`, `cycle.edges` is the evidence path.
+///   Negative cycles elsewhere in the graph do not matter.
+pub fn bellman_ford<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    source: NodeId,
+) -> Result<ShortestPathTree, Cycle> {
+    let mut dist = HashMap::new();
+    if g.get_node_val(source).is_some() {
+        dist.insert(source, 0);
+    }
+    let (dist, pred) = relax_edges(g, dist)?;
+    Ok(ShortestPathTree { source, dist, pred })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphAlgo;
+    use crate::graphtraits::GraphCrud;
+
+    #[test]
+    fn test_negative_weights() {
+        let mut g: Graph<&str> = Graph::new();
+        let s = g.new_node("s");
+        let a = g.new_node("a");
+        let b = g.new_node("b");
+        let c = g.new_node("c");
+        let unreachable = g.new_node("unreachable");
+        g.new_edge(s, a, 4);
+        let sb = g.new_edge(s, b, 5);
+        let ba = g.new_edge(b, a, -3);
+        let ac = g.new_edge(a, c, 2);
+        g.new_edge(unreachable, s, -10);
+
+        let tree = g.bellman_ford(s).unwrap();
+        assert_eq!(tree.distance(s), Some(0));
+        assert_eq!(tree.distance(a), Some(2));
+        assert_eq!(tree.distance(c), Some(4));
+        assert_eq!(tree.distance(unreachable), None);
+        assert_eq!(tree.pred[&a], ba);
+        assert_eq!(tree.path_to(&g, c), Some(vec![sb, ba, ac]));
+        assert_eq!(tree.path_to(&g, unreachable), None);
+    }
+
+    #[test]
+    fn test_negative_cycle_is_reported() {
+        let mut g: Graph<&str> = Graph::new();
+        let s = g.new_node("s");
+        let a = g.new_node("a");
+        let b = g.new_node("b");
+        let c = g.new_node("c");
+        g.new_edge(s, a, 1);
+        let ab = g.new_edge(a, b, 2);
+        let bc = g.new_edge(b, c, -4);
+        let ca = g.new_edge(c, a, 1);
+
+        let cycle = g.bellman_ford(s).unwrap_err();
+        let mut edges = cycle.edges.clone();
+        edges.sort_unstable();
+        assert_eq!(edges, vec![ab, bc, ca]);
+        for (i, edge_id) in cycle.edges.iter().enumerate() {
+            let edge = g.get_edge(*edge_id).unwrap();
+            assert_eq!(edge.0, cycle.nodes[i]);
+            assert_eq!(edge.1, cycle.nodes[(i + 1) % cycle.nodes.len()]);
+        }
+
+        // a node that cannot reach the cycle is not affected by it
+        let d = g.new_node("d");
+        assert!(g.bellman_ford(d).is_ok());
+    }
+}
@@ -0,0 +1,328 @@
+// -----------------------------------------------------------------------------------------------
+// Module centrality
+//
+// Node importance metrics. They work on any GraphView implementation and return a value per
+// node. On an OSM graph high betweenness marks the intersections most routes go through.
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::graphtraits::{GraphView, NodeId, valid_edges};
+
+/// Whether the metrics count hops or use the edge weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weighting {
+    /// Every edge counts as 1.
+    Unweighted,
+    /// The edge weights are used as distances (for degree: summed up, for PageRank: the share
+    /// of an edge among the out edges). Weights are expected to be positive.
+    Weighted,
+}
+
+/// PageRank stops when the ranks change by less than this in total ...
+const PAGERANK_TOLERANCE: f64 = 1e-10;
+/// ... or after this many iterations.
+const PAGERANK_MAX_ITERATIONS: usize = 100;
+
+/// The graph with dense node indices, built once per metric.
+struct DenseGraph {
+    nodes: Vec<NodeId>,
+    out: Vec<Vec<(usize, i64)>>,
+}
+
+impl DenseGraph {
+    fn new<T, G: GraphView<T> + ?Sized>(g: &G, weighting: Weighting) -> Self {
+        let nodes = g.node_ids();
+        let index: HashMap<NodeId, usize> =
+            nodes.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let mut out = vec![Vec::new(); nodes.len()];
+        for (_, from, to, weight) in valid_edges(g) {
+            let cost = match weighting {
+                Weighting::Unweighted => 1,
+                Weighting::Weighted => weight as i64,
+            };
+            out[index[&from]].push((index[&to], cost));
+        }
+        DenseGraph { nodes, out }
+    }
+
+    fn to_map(&self, values: Vec<f64>) -> HashMap<NodeId, f64> {
+        self.nodes.iter().copied().zip(values).collect()
+    }
+}
+
+/// The result of one single source search as needed by Brandes' algorithm.
+struct SearchResult {
+    /// nodes in the order in which they were settled, i.e. by increasing distance
+    order: Vec<usize>,
+    dist: Vec<Option<i64>>,
+    /// number of shortest paths from the source
+    sigma: Vec<f64>,
+    /// predecessors on shortest paths
+    pred: Vec<Vec<usize>>,
+}
+
+/// Dijkstra from `source` that counts the shortest paths to every node.
+fn single_source(g: &DenseGraph, source: usize) -> SearchResult {
+    let n = g.nodes.len();
+    let mut result = SearchResult {
+        order: Vec::new(),
+        dist: vec![None; n],
+        sigma: vec![0.0; n],
+        pred: vec![Vec::new(); n],
+    };
+    let mut settled = vec![false; n];
+    result.dist[source] = Some(0);
+    result.sigma[source] = 1.0;
+    let mut heap = BinaryHeap::from([Reverse((0i64, source))]);
+    while let Some(Reverse((d, node))) = heap.pop() {
+        if settled[node] {
+            continue;
+        }
+        settled[node] = true;
+        result.order.push(node);
+        for (next, cost) in g.out[node].iter() {
+            let nd = d + cost;
+            match result.dist[*next] {
+                Some(old) if nd > old => {}
+                Some(old) if nd == old => {
+                    result.sigma[*next] += result.sigma[node];
+                    result.pred[*next].push(node);
+                }
+                _ => {
+                    result.dist[*next] = Some(nd);
+                    result.sigma[*next] = result.sigma[node];
+                    result.pred[*next] = vec![node];
+                    heap.push(Reverse((nd, *next)));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// In degree of every node. Weighted, the weights of the in edges are summed up.
+pub fn in_degree_centrality<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    weighting: Weighting,
+) -> HashMap<NodeId, f64> {
+    let dense = DenseGraph::new(g, weighting);
+    let mut degree = vec![0.0; dense.nodes.len()];
+    for edges in dense.out.iter() {
+        for (to, cost) in edges {
+            degree[*to] += *cost as f64;
+        }
+    }
+    dense.to_map(degree)
+}
+
+/// Out degree of every node. Weighted, the weights of the out edges are summed up.
+pub fn out_degree_centrality<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    weighting: Weighting,
+) -> HashMap<NodeId, f64> {
+    let dense = DenseGraph::new(g, weighting);
+    let degree = dense
+        .out
+        .iter()
+        .map(|edges| edges.iter().map(|(_, cost)| *cost as f64).sum())
+        .collect();
+    dense.to_map(degree)
+}
+
+/// Closeness centrality based on the distances to all nodes reachable from a node.
+///
+/// Uses the Wasserman-Faust formula `(r - 1) / (n - 1) * (r - 1) / sum(d)` where `r` is the number
+/// of reachable nodes (including the node itself), so that nodes which only reach a small part
+/// of the graph do not get a high score. Nodes that reach nothing get 0.
+pub fn closeness_centrality<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    weighting: Weighting,
+) -> HashMap<NodeId, f64> {
+    let dense = DenseGraph::new(g, weighting);
+    let n = dense.nodes.len();
+    let closeness = (0..n)
+        .map(|source| {
+            let search = single_source(&dense, source);
+            let reached = search.order.len() - 1;
+            let total: i64 = search.dist.iter().flatten().sum();
+            if reached == 0 || total <= 0 {
+                return 0.0;
+            }
+            let reached = reached as f64;
+            (reached / (n - 1) as f64) * (reached / total as f64)
+        })
+        .collect();
+    dense.to_map(closeness)
+}
+
+/// Betweenness centrality with Brandes' algorithm: for every node the number of shortest paths
+/// between other nodes that pass through it (paths that tie share the credit). Not normalized.
+pub fn betweenness_centrality<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    weighting: Weighting,
+) -> HashMap<NodeId, f64> {
+    let dense = DenseGraph::new(g, weighting);
+    let n = dense.nodes.len();
+    let mut betweenness = vec![0.0; n];
+    for source in 0..n {
+        let search = single_source(&dense, source);
+        // accumulate the dependencies backwards, from the farthest node to the source
+        let mut delta = vec![0.0; n];
+        for node in search.order.iter().rev() {
+            for prev in search.pred[*node].iter() {
+                delta[*prev] += search.sigma[*prev] / search.sigma[*node] * (1.0 + delta[*node]);
+            }
+            if *node != source {
+                betweenness[*node] += delta[*node];
+            }
+        }
+    }
+    dense.to_map(betweenness)
+}
+
+/// PageRank with the given damping factor (usually 0.85).
+///
+/// Nodes without out edges distribute their rank evenly over all nodes. Weighted, a node passes
+/// its rank on in proportion to the weights of its out edges. The ranks add up to 1.
+pub fn pagerank<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    damping: f64,
+    weighting: Weighting,
+) -> HashMap<NodeId, f64> {
+    let dense = DenseGraph::new(g, weighting);
+    let n = dense.nodes.len();
+    if n == 0 {
+        return HashMap::new();
+    }
+    let out_total: Vec<f64> = dense
+        .out
+        .iter()
+        .map(|edges| edges.iter().map(|(_, cost)| *cost as f64).sum())
+        .collect();
+
+    let mut rank = vec![1.0 / n as f64; n];
+    for _ in 0..PAGERANK_MAX_ITERATIONS {
+        let dangling: f64 = (0..n)
+            .filter(|i| out_total[*i] <= 0.0)
+            .map(|i| rank[i])
+            .sum();
+        let base = (1.0 - damping) / n as f64 + damping * dangling / n as f64;
+        let mut next = vec![base; n];
+        for (node, edges) in dense.out.iter().enumerate() {
+            if out_total[node] <= 0.0 {
+                continue;
+            }
+            for (to, cost) in edges {
+                next[*to] += damping * rank[node] * (*cost as f64) / out_total[node];
+            }
+        }
+        let change: f64 = rank
+            .iter()
+            .zip(next.iter())
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        rank = next;
+        if change < PAGERANK_TOLERANCE {
+            break;
+        }
+    }
+    dense.to_map(rank)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphCrud;
+
+    /// A star: 1 is the hub with edges to and from 2, 3 and 4
+    fn star() -> Graph<i32> {
+        let mut g: Graph<i32> = Graph::new();
+        for v in 1..=4 {
+            g.set_node(v, v as NodeId);
+        }
+        for leaf in 2..=4 {
+            g.new_edge(1, leaf, leaf as i32);
+            g.new_edge(leaf, 1, 1);
+        }
+        g
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_degree() {
+        let g = star();
+        let out = out_degree_centrality(&g, Weighting::Unweighted);
+        assert_close(out[&1], 3.0);
+        assert_close(out[&2], 1.0);
+        let weighted_in = in_degree_centrality(&g, Weighting::Weighted);
+        assert_close(weighted_in[&1], 3.0);
+        assert_close(weighted_in[&4], 4.0);
+    }
+
+    #[test]
+    fn test_closeness() {
+        let g = star();
+        let closeness = closeness_centrality(&g, Weighting::Unweighted);
+        assert_close(closeness[&1], 1.0);
+        // a leaf reaches the hub in 1 and the other leaves in 2 hops
+        assert_close(closeness[&2], 3.0 / 5.0);
+        let weighted = closeness_centrality(&g, Weighting::Weighted);
+        assert_close(weighted[&1], 3.0 / 9.0);
+    }
+
+    #[test]
+    fn test_betweenness() {
+        let g = star();
+        let betweenness = betweenness_centrality(&g, Weighting::Unweighted);
+        // every one of the 6 leaf to leaf paths goes through the hub
+        assert_close(betweenness[&1], 6.0);
+        assert_close(betweenness[&2], 0.0);
+
+        // two equally short paths 1 -> 2 -> 4 and 1 -> 3 -> 4 share the credit
+        let mut g: Graph<i32> = Graph::new();
+        for v in 1..=4 {
+            g.set_node(v, v as NodeId);
+        }
+        g.new_edge(1, 2, 1);
+        g.new_edge(1, 3, 1);
+        g.new_edge(2, 4, 1);
+        g.new_edge(3, 4, 5);
+        let unweighted = betweenness_centrality(&g, Weighting::Unweighted);
+        assert_close(unweighted[&2], 0.5);
+        assert_close(unweighted[&3], 0.5);
+        let weighted = betweenness_centrality(&g, Weighting::Weighted);
+        assert_close(weighted[&2], 1.0);
+        assert_close(weighted[&3], 0.0);
+    }
+
+    #[test]
+    fn test_pagerank() {
+        let g = star();
+        let rank = pagerank(&g, 0.85, Weighting::Unweighted);
+        assert_close(rank.values().sum(), 1.0);
+        assert!(rank[&1] > rank[&2]);
+        assert_close(rank[&2], rank[&3]);
+        let weighted = pagerank(&g, 0.85, Weighting::Weighted);
+        assert!(weighted[&4] > weighted[&2]);
+
+        // a dangling node does not lose rank
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        g.new_edge(a, b, 1);
+        let rank = pagerank(&g, 0.85, Weighting::Unweighted);
+        assert_close(rank.values().sum(), 1.0);
+        assert!(rank[&b] > rank[&a]);
+    }
+}
@@ -0,0 +1,241 @@
+// -----------------------------------------------------------------------------------------------
+// Module conformance
+//
+// Tests written once against GraphCrud + GraphAlgo, which every graph implementation runs to make
+// sure the implementations are really replaceable. A backend runs them with
+//
+//     crate::conformance::conformance_tests!(Graph<i32>);
+//
+// inside its test module. IDs are only compared with IDs returned by the graph, the backends are
+// free in how they assign them.
+//
+
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, NodeId};
+
+/// The sample graph from main.rs.
+///
+/// # Returns
+///
+/// The graph, the node IDs of the values 1 to 6 and the edge IDs in the order of creation.
+pub fn sample_graph<G: GraphCrud<i32>>() -> (G, Vec<NodeId>, Vec<EdgeId>) {
+    let mut g = G::new();
+    let n: Vec<NodeId> = (1..=6).map(|v| g.new_node(v)).collect();
+    let edges = [
+        (0, 1, 1),
+        (1, 2, 2),
+        (2, 3, 3),
+        (2, 4, 4),
+        (3, 5, 5),
+        (2, 0, 6),
+        (0, 4, 7),
+        (1, 4, 8),
+        (4, 1, 9),
+        (5, 3, 10),
+    ];
+    let e = edges
+        .iter()
+        .map(|(from, to, weight)| g.new_edge(n[*from], n[*to], *weight))
+        .collect();
+    (g, n, e)
+}
+
+fn sorted(mut ids: Vec<EdgeId>) -> Vec<EdgeId> {
+    ids.sort_unstable();
+    ids
+}
+
+fn triplet<G: GraphCrud<i32>>(g: &G, edge_id: EdgeId) -> Option<(NodeId, NodeId, i32)> {
+    g.get_edge(edge_id).map(|e| (e.0, e.1, e.2))
+}
+
+pub fn check_node_crud<G: GraphCrud<i32>>() {
+    let mut g = G::new();
+    assert!(g.node_ids().is_empty());
+    let a = g.new_node(1);
+    let b = g.new_node(2);
+    assert_ne!(a, b);
+    assert_eq!(g.get_node_val(a), Some(&1));
+    assert_eq!(g.get_node_val(b), Some(&2));
+    assert_eq!(g.node_ids(), sorted(vec![a, b]));
+
+    g.set_node_val(a, 10);
+    assert_eq!(g.get_node_val(a), Some(&10));
+    assert_eq!(g.set_node(20, b), b);
+    assert_eq!(g.get_node_val(b), Some(&20));
+
+    assert!(g.del_node(a));
+    assert!(!g.del_node(a));
+    assert_eq!(g.get_node_val(a), None);
+    assert_eq!(g.node_ids(), vec![b]);
+    // updating a deleted node does not bring it back
+    g.set_node_val(a, 30);
+    assert_eq!(g.get_node_val(a), None);
+}
+
+pub fn check_new_node_after_set_node<G: GraphCrud<i32>>() {
+    let mut g = G::new();
+    let a = 2;
+    assert_eq!(g.set_node(500, a), a);
+    let b = g.new_node(1);
+    let c = g.new_node(2);
+    // new_node must not reuse the ID given to set_node
+    assert_ne!(b, a);
+    assert_ne!(c, a);
+    assert_eq!(g.get_node_val(a), Some(&500));
+    assert_eq!(g.get_node_val(b), Some(&1));
+    assert_eq!(g.get_node_val(c), Some(&2));
+    assert_eq!(g.node_ids(), sorted(vec![a, b, c]));
+}
+
+pub fn check_edge_crud<G: GraphCrud<i32>>() {
+    let mut g = G::new();
+    let a = g.new_node(1);
+    let b = g.new_node(2);
+    let c = g.new_node(3);
+    let ab = g.new_edge(a, b, 5);
+    let bc = g.new_edge(b, c, 6);
+    assert_ne!(ab, bc);
+    assert_eq!(triplet(&g, ab), Some((a, b, 5)));
+    assert_eq!(g.edge_ids(), sorted(vec![ab, bc]));
+
+    g.set_edge(ab, EdgeTriplet(a, c, 7));
+    assert_eq!(triplet(&g, ab), Some((a, c, 7)));
+    assert_eq!(g.find_edges_to(b), Some(vec![]));
+    assert_eq!(sorted(g.find_edges_to(c).unwrap()), sorted(vec![ab, bc]));
+
+    assert!(g.del_edge(bc));
+    assert!(!g.del_edge(bc));
+    assert_eq!(triplet(&g, bc), None);
+    assert_eq!(g.edge_ids(), vec![ab]);
+    assert_eq!(g.find_edges_from(b), Some(vec![]));
+    // deleting an edge keeps the nodes
+    assert_eq!(g.node_ids().len(), 3);
+}
+
+pub fn check_edges_before_nodes<G: GraphCrud<i32>>() {
+    let mut g = G::new();
+    let a = g.new_node(1);
+    let b = a + 1000;
+    let ab = g.new_edge(a, b, 5);
+    let bb = g.new_edge(b, b, 6);
+    assert_eq!(triplet(&g, ab), Some((a, b, 5)));
+    assert_eq!(triplet(&g, bb), Some((b, b, 6)));
+    assert_eq!(g.edge_ids(), sorted(vec![ab, bb]));
+    assert_eq!(g.set_node(2, b), b);
+    assert_eq!(g.find_edges_from(a), Some(vec![ab]));
+    assert_eq!(g.find_edges_from(b), Some(vec![bb]));
+    assert_eq!(sorted(g.find_edges_to(b).unwrap()), sorted(vec![ab, bb]));
+    assert!(g.del_node(b));
+    assert_eq!(g.edge_ids(), vec![]);
+}
+
+pub fn check_del_node_cascades<G: GraphCrud<i32>>() {
+    let (mut g, n, e) = sample_graph::<G>();
+    // node 3 has the edges 2->3, 3->4, 3->5 and 3->1
+    assert!(g.del_node(n[2]));
+    for edge_id in [e[1], e[2], e[3], e[5]] {
+        assert_eq!(triplet(&g, edge_id), None);
+    }
+    assert_eq!(g.edge_ids().len(), 6);
+    assert_eq!(sorted(g.find_edges_from(n[1]).unwrap()), sorted(vec![e[7]]));
+    assert_eq!(g.find_edges_to(n[3]), Some(vec![e[9]]));
+    assert_eq!(g.find_edges_to(n[0]), Some(vec![]));
+    assert!(
+        g.edge_ids()
+            .iter()
+            .all(|id| g.get_edge(*id).is_some_and(|t| t.0 != n[2] && t.1 != n[2]))
+    );
+}
+
+pub fn check_find_edges<G: GraphCrud<i32>>() {
+    let (g, n, e) = sample_graph::<G>();
+    assert_eq!(
+        sorted(g.find_edges_from(n[0]).unwrap()),
+        sorted(vec![e[0], e[6]])
+    );
+    assert_eq!(
+        sorted(g.find_edges_from(n[2]).unwrap()),
+        sorted(vec![e[2], e[3], e[5]])
+    );
+    assert_eq!(
+        sorted(g.find_edges_to(n[4]).unwrap()),
+        sorted(vec![e[3], e[6], e[7]])
+    );
+    assert_eq!(g.find_edges_to(n[0]), Some(vec![e[5]]));
+    for edge_id in g.find_edges_from(n[1]).unwrap() {
+        assert_eq!(g.get_edge(edge_id).unwrap().0, n[1]);
+    }
+    let missing = n.iter().max().unwrap() + e.iter().max().unwrap() + 1;
+    assert_eq!(g.find_edges_from(missing), None);
+    assert_eq!(g.find_edges_to(missing), None);
+}
+
+/// Path queries on the sample graph, `n` and `e` as returned by [`sample_graph`]. Takes the
+/// graph so that read-only backends can run it on a copy of the sample graph.
+pub fn check_sample_paths<G: GraphAlgo<i32>>(g: &G, n: &[NodeId], e: &[EdgeId]) {
+    assert!(g.path_exists(n[0], n[5]));
+    assert!(g.path_exists(n[4], n[0]));
+    assert!(g.path_exists(n[2], n[2]));
+    assert!(!g.path_exists(n[5], n[0]));
+    assert!(!g.path_exists(n[3], n[2]));
+
+    // 1 -> 2 -> 3 -> 4 -> 6 weighs 11, 1 -> 5 -> 2 -> 3 -> 4 -> 6 weighs 26
+    assert_eq!(
+        g.shortest_path(n[0], n[5]),
+        Some(vec![e[0], e[1], e[2], e[4]])
+    );
+    assert_eq!(g.shortest_path(n[4], n[0]), Some(vec![e[8], e[1], e[5]]));
+    assert_eq!(g.shortest_path(n[0], n[0]), Some(vec![]));
+    assert_eq!(g.shortest_path(n[5], n[0]), None);
+
+    let mut expected = vec![
+        vec![e[0], e[1], e[2], e[4]],
+        vec![e[6], e[8], e[1], e[2], e[4]],
+    ];
+    expected.sort();
+    assert_eq!(g.all_paths(n[0], n[5]), Some(expected));
+    assert_eq!(g.all_paths(n[5], n[0]), Some(vec![]));
+}
+
+/// Generates the conformance tests for a graph type with `i32` values.
+macro_rules! conformance_tests {
+    ($graph:ty) => {
+        #[test]
+        fn test_conformance_node_crud() {
+            $crate::conformance::check_node_crud::<$graph>();
+        }
+
+        #[test]
+        fn test_conformance_new_node_after_set_node() {
+            $crate::conformance::check_new_node_after_set_node::<$graph>();
+        }
+
+        #[test]
+        fn test_conformance_edge_crud() {
+            $crate::conformance::check_edge_crud::<$graph>();
+        }
+
+        #[test]
+        fn test_conformance_edges_before_nodes() {
+            $crate::conformance::check_edges_before_nodes::<$graph>();
+        }
+
+        #[test]
+        fn test_conformance_del_node_cascades() {
+            $crate::conformance::check_del_node_cascades::<$graph>();
+        }
+
+        #[test]
+        fn test_conformance_find_edges() {
+            $crate::conformance::check_find_edges::<$graph>();
+        }
+
+        #[test]
+        fn test_conformance_sample_paths() {
+            let (g, n, e) = $crate::conformance::sample_graph::<$graph>();
+            $crate::conformance::check_sample_paths(&g, &n, &e);
+        }
+    };
+}
+
+pub(crate) use conformance_tests;
@@ -0,0 +1,491 @@
+// -----------------------------------------------------------------------------------------------
+// Module contraction
+//
+// Contraction Hierarchies (CH) for fast shortest path queries on large road graphs.
+//
+// Preprocessing orders the nodes by importance and contracts them one after the other, least
+// important first. Contracting a node removes it from the remaining graph; for every pair of
+// neighbours u -> v -> w whose shortest connection ran through v a shortcut edge u -> w is
+// added. A small local Dijkstra (the witness search) finds out whether the shortcut is needed.
+// The order is chosen greedily by the edge difference (shortcuts added minus edges removed) plus
+// the number of already contracted neighbours, which keeps the contraction spread out evenly.
+//
+// A query runs Dijkstra from the source upwards (only to nodes contracted later) and from the
+// target upwards on the reversed edges. Both searches only see a tiny part of the graph, the
+// shortest path is the best meeting point. Shortcuts are unpacked into the original edges.
+//
+//   let ch = ContractionHierarchy::new(&osmgraph);
+//   let path = ch.shortest_path(from, to);
+//
+// Like Dijkstra this needs non negative edge weights. The weight of a path is always the one
+// Dijkstra finds, the edges are the same whenever the shortest path is unique.
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::graphtraits::{EdgeId, GraphView, NodeId};
+use crate::shortest_path::WeightedPath;
+
+/// A witness search gives up after settling this many nodes and the shortcut is added, which is
+/// never wrong but can add shortcuts that are not needed.
+const WITNESS_SETTLE_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Copy)]
+enum ArcKind {
+    Original(EdgeId),
+    /// The two arcs that the shortcut replaces.
+    Shortcut(usize, usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Arc {
+    from: usize,
+    to: usize,
+    weight: i64,
+    kind: ArcKind,
+}
+
+pub struct ContractionHierarchy {
+    /// dense index -> node ID, sorted
+    node_ids: Vec<NodeId>,
+    node_index: HashMap<NodeId, usize>,
+    /// dense index -> position in the contraction order
+    rank: Vec<usize>,
+    /// the original edges (at most one per pair of nodes) followed by the shortcuts
+    arcs: Vec<Arc>,
+    shortcut_count: usize,
+    /// arcs from a node to higher ranked nodes, used by the forward search
+    up: Vec<Vec<usize>>,
+    /// arcs into a node from higher ranked nodes, used by the backward search
+    down: Vec<Vec<usize>>,
+}
+
+/// The graph while it is being contracted.
+struct Contraction {
+    arcs: Vec<Arc>,
+    out_arcs: Vec<Vec<usize>>,
+    in_arcs: Vec<Vec<usize>>,
+    contracted: Vec<bool>,
+    contracted_neighbours: Vec<i64>,
+}
+
+impl Contraction {
+    fn add_arc(&mut self, arc: Arc) {
+        let index = self.arcs.len();
+        self.out_arcs[arc.from].push(index);
+        self.in_arcs[arc.to].push(index);
+        self.arcs.push(arc);
+    }
+
+    /// Dijkstra from `from` on the remaining graph without `skip`, up to distance `limit`.
+    fn witness_search(&self, from: usize, skip: usize, limit: i64) -> HashMap<usize, i64> {
+        let mut dist: HashMap<usize, i64> = HashMap::from([(from, 0)]);
+        let mut heap = BinaryHeap::from([Reverse((0i64, from))]);
+        let mut settled = 0;
+        while let Some(Reverse((d, node))) = heap.pop() {
+            if d > dist[&node] {
+                continue;
+            }
+            settled += 1;
+            if settled > WITNESS_SETTLE_LIMIT {
+                break;
+            }
+            for arc_index in self.out_arcs[node].iter() {
+                let arc = &self.arcs[*arc_index];
+                if arc.to == skip || self.contracted[arc.to] {
+                    continue;
+                }
+                let nd = d + arc.weight;
+                if nd <= limit && dist.get(&arc.to).is_none_or(|old| nd < *old) {
+                    dist.insert(arc.to, nd);
+                    heap.push(Reverse((nd, arc.to)));
+                }
+            }
+        }
+        dist
+    }
+
+    /// The shortcuts that contracting `node` needs, as (in arc, out arc) pairs.
+    fn shortcuts(&self, node: usize) -> Vec<(usize, usize)> {
+        let outgoing: Vec<usize> = self.out_arcs[node]
+            .iter()
+            .copied()
+            .filter(|a| !self.contracted[self.arcs[*a].to] && self.arcs[*a].to != node)
+            .collect();
+        let mut result = Vec::new();
+        if outgoing.is_empty() {
+            return result;
+        }
+        let max_out = outgoing.iter().map(|a| self.arcs[*a].weight).max().unwrap();
+        for in_arc in self.in_arcs[node].iter().copied() {
+            let u = self.arcs[in_arc].from;
+            if self.contracted[u] || u == node {
+                continue;
+            }
+            let via = self.arcs[in_arc].weight;
+            let witnesses = self.witness_search(u, node, via + max_out);
+            for out_arc in outgoing.iter().copied() {
+                let w = self.arcs[out_arc].to;
+                if w == u {
+                    continue;
+                }
+                let weight = via + self.arcs[out_arc].weight;
+                // a witness path that is not longer makes the shortcut unnecessary
+                if witnesses.get(&w).is_none_or(|d| *d > weight) {
+                    result.push((in_arc, out_arc));
+                }
+            }
+        }
+        result
+    }
+
+    fn degree(&self, node: usize) -> i64 {
+        let active = |arcs: &Vec<usize>, end: fn(&Arc) -> usize| {
+            arcs.iter()
+                .filter(|a| !self.contracted[end(&self.arcs[**a])])
+                .count() as i64
+        };
+        active(&self.out_arcs[node], |a| a.to) + active(&self.in_arcs[node], |a| a.from)
+    }
+
+    fn priority(&self, node: usize) -> i64 {
+        self.shortcuts(node).len() as i64 - self.degree(node) + self.contracted_neighbours[node]
+    }
+
+    fn contract(&mut self, node: usize) {
+        for (in_arc, out_arc) in self.shortcuts(node) {
+            self.add_arc(Arc {
+                from: self.arcs[in_arc].from,
+                to: self.arcs[out_arc].to,
+                weight: self.arcs[in_arc].weight + self.arcs[out_arc].weight,
+                kind: ArcKind::Shortcut(in_arc, out_arc),
+            });
+        }
+        self.contracted[node] = true;
+        let neighbours: Vec<usize> = self.out_arcs[node]
+            .iter()
+            .map(|a| self.arcs[*a].to)
+            .chain(self.in_arcs[node].iter().map(|a| self.arcs[*a].from))
+            .collect();
+        for neighbour in neighbours {
+            self.contracted_neighbours[neighbour] += 1;
+        }
+    }
+}
+
+impl ContractionHierarchy {
+    /// Preprocesses any graph. Parallel edges are reduced to the lightest one (the lowest edge
+    /// ID among equally heavy ones, like Dijkstra), self loops and edges between nodes that do
+    /// not exist are ignored.
+    pub fn new<T, G: GraphView<T> + ?Sized>(g: &G) -> Self {
+        let node_ids = g.node_ids();
+        let node_index: HashMap<NodeId, usize> = node_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+        let n = node_ids.len();
+
+        let mut lightest: HashMap<(usize, usize), (i64, EdgeId)> = HashMap::new();
+        for edge_id in g.edge_ids() {
+            let Some(edge) = g.get_edge(edge_id) else {
+                continue;
+            };
+            let (Some(from), Some(to)) = (node_index.get(&edge.0), node_index.get(&edge.1)) else {
+                continue;
+            };
+            if from == to {
+                continue;
+            }
+            let candidate = (edge.2 as i64, edge_id);
+            lightest
+                .entry((*from, *to))
+                .and_modify(|old| *old = (*old).min(candidate))
+                .or_insert(candidate);
+        }
+        let mut originals: Vec<Arc> = lightest
+            .into_iter()
+            .map(|((from, to), (weight, edge_id))| Arc {
+                from,
+                to,
+                weight,
+                kind: ArcKind::Original(edge_id),
+            })
+            .collect();
+        originals.sort_unstable_by_key(|arc| match arc.kind {
+            ArcKind::Original(edge_id) => edge_id,
+            ArcKind::Shortcut(..) => unreachable!(),
+        });
+
+        let mut contraction = Contraction {
+            arcs: Vec::with_capacity(originals.len() * 2),
+            out_arcs: vec![Vec::new(); n],
+            in_arcs: vec![Vec::new(); n],
+            contracted: vec![false; n],
+            contracted_neighbours: vec![0; n],
+        };
+        let original_count = originals.len();
+        for arc in originals {
+            contraction.add_arc(arc);
+        }
+
+        // lazy updates: a node's priority is recomputed when it comes out of the queue, if it
+        // got worse than the next one it goes back in
+        let mut queue: BinaryHeap<Reverse<(i64, usize)>> = (0..n)
+            .map(|node| Reverse((contraction.priority(node), node)))
+            .collect();
+        let mut rank = vec![0; n];
+        let mut next_rank = 0;
+        while let Some(Reverse((_, node))) = queue.pop() {
+            let priority = contraction.priority(node);
+            if let Some(Reverse((next, _))) = queue.peek()
+                && priority > *next
+            {
+                queue.push(Reverse((priority, node)));
+                continue;
+            }
+            contraction.contract(node);
+            rank[node] = next_rank;
+            next_rank += 1;
+        }
+
+        let arcs = contraction.arcs;
+        let mut up = vec![Vec::new(); n];
+        let mut down = vec![Vec::new(); n];
+        for (index, arc) in arcs.iter().enumerate() {
+            if rank[arc.from] < rank[arc.to] {
+                up[arc.from].push(index);
+            } else {
+                down[arc.to].push(index);
+            }
+        }
+        ContractionHierarchy {
+            node_ids,
+            node_index,
+            rank,
+            shortcut_count: arcs.len() - original_count,
+            arcs,
+            up,
+            down,
+        }
+    }
+
+    /// The number of shortcut edges added by the preprocessing.
+    pub fn shortcut_count(&self) -> usize {
+        self.shortcut_count
+    }
+
+    /// The node IDs in the order in which they were contracted, least important first.
+    pub fn order(&self) -> Vec<NodeId> {
+        let mut order = vec![0; self.node_ids.len()];
+        for (index, rank) in self.rank.iter().enumerate() {
+            order[*rank] = self.node_ids[index];
+        }
+        order
+    }
+
+    /// Dijkstra from `start` on the upward arcs (`up`) or on the reversed downward arcs.
+    fn upward_search(
+        &self,
+        start: usize,
+        forward: bool,
+    ) -> (HashMap<usize, i64>, HashMap<usize, usize>) {
+        let mut dist: HashMap<usize, i64> = HashMap::from([(start, 0)]);
+        let mut pred: HashMap<usize, usize> = HashMap::new();
+        let mut heap = BinaryHeap::from([Reverse((0i64, start))]);
+        while let Some(Reverse((d, node))) = heap.pop() {
+            if d > dist[&node] {
+                continue;
+            }
+            let arcs = if forward {
+                &self.up[node]
+            } else {
+                &self.down[node]
+            };
+            for arc_index in arcs.iter() {
+                let arc = &self.arcs[*arc_index];
+                let next = if forward { arc.to } else { arc.from };
+                let nd = d + arc.weight;
+                let better = match dist.get(&next) {
+                    None => true,
+                    Some(old) => nd < *old || (nd == *old && *arc_index < pred[&next]),
+                };
+                if better {
+                    dist.insert(next, nd);
+                    pred.insert(next, *arc_index);
+                    heap.push(Reverse((nd, next)));
+                }
+            }
+        }
+        (dist, pred)
+    }
+
+    /// Appends the original edges of an arc to `path`.
+    fn unpack(&self, arc_index: usize, path: &mut Vec<EdgeId>) {
+        let mut stack = vec![arc_index];
+        while let Some(arc_index) = stack.pop() {
+            match self.arcs[arc_index].kind {
+                ArcKind::Original(edge_id) => path.push(edge_id),
+                ArcKind::Shortcut(first, second) => {
+                    stack.push(second);
+                    stack.push(first);
+                }
+            }
+        }
+    }
+
+    /// The shortest path from `from` to `to` in the original graph.
+    ///
+    /// # Returns
+    ///
+    /// The path with the original edge IDs or `None` if `to` cannot be reached or one of the
+    /// nodes does not exist. The path from a node to itself is the empty path.
+    pub fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<WeightedPath> {
+        let source = *self.node_index.get(&from)?;
+        let target = *self.node_index.get(&to)?;
+        let (forward_dist, forward_pred) = self.upward_search(source, true);
+        let (backward_dist, backward_pred) = self.upward_search(target, false);
+
+        let (weight, meet) = forward_dist
+            .iter()
+            .filter_map(|(node, d)| Some((d + backward_dist.get(node)?, *node)))
+            .min()?;
+
+        let mut up_arcs = Vec::new();
+        let mut node = meet;
+        while node != source {
+            let arc_index = forward_pred[&node];
+            up_arcs.push(arc_index);
+            node = self.arcs[arc_index].from;
+        }
+        let mut edges = Vec::new();
+        for arc_index in up_arcs.into_iter().rev() {
+            self.unpack(arc_index, &mut edges);
+        }
+        let mut node = meet;
+        while node != target {
+            let arc_index = backward_pred[&node];
+            self.unpack(arc_index, &mut edges);
+            node = self.arcs[arc_index].to;
+        }
+        Some(WeightedPath { weight, edges })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::conformance::sample_graph;
+    use crate::generators::{Rng, erdos_renyi, grid};
+    use crate::graph::Graph;
+    use crate::osmgraph::{OsmNode, create_osm_graph, parse_osm};
+    use crate::shortest_path::dijkstra;
+
+    /// Checks that the path is connected, runs from `from` to `to` and has the given weight.
+    fn check_path<T, G: GraphView<T>>(g: &G, from: NodeId, to: NodeId, path: &WeightedPath) {
+        let mut node = from;
+        let mut weight = 0;
+        for edge_id in path.edges.iter() {
+            let edge = g.get_edge(*edge_id).unwrap();
+            assert_eq!(edge.0, node);
+            node = edge.1;
+            weight += edge.2 as i64;
+        }
+        assert_eq!(node, to);
+        assert_eq!(weight, path.weight);
+    }
+
+    /// Compares the CH with Dijkstra for the given pairs of nodes.
+    fn compare<T: Clone>(g: &Graph<T>, pairs: &[(NodeId, NodeId)], same_edges: bool) {
+        let ch = ContractionHierarchy::new(g);
+        let csr = g.freeze();
+        for (from, to) in pairs.iter().copied() {
+            let expected = dijkstra(&csr, from, to);
+            let found = ch.shortest_path(from, to);
+            assert_eq!(
+                found.as_ref().map(|p| p.weight),
+                expected.as_ref().map(|p| p.weight),
+                "{} -> {}",
+                from,
+                to
+            );
+            if let Some(path) = found {
+                check_path(g, from, to, &path);
+                if same_edges {
+                    assert_eq!(path, expected.unwrap());
+                }
+            }
+        }
+    }
+
+    fn all_pairs<T, G: GraphView<T>>(g: &G) -> Vec<(NodeId, NodeId)> {
+        let nodes = g.node_ids();
+        nodes
+            .iter()
+            .flat_map(|from| nodes.iter().map(|to| (*from, *to)))
+            .collect()
+    }
+
+    fn random_pairs<T, G: GraphView<T>>(
+        g: &G,
+        count: usize,
+        rng: &mut Rng,
+    ) -> Vec<(NodeId, NodeId)> {
+        let nodes = g.node_ids();
+        (0..count)
+            .map(|_| (nodes[rng.below(nodes.len())], nodes[rng.below(nodes.len())]))
+            .collect()
+    }
+
+    #[test]
+    fn test_sample_graph() {
+        let (g, n, e) = sample_graph::<Graph<i32>>();
+        let ch = ContractionHierarchy::new(&g);
+        let path = ch.shortest_path(n[0], n[5]).unwrap();
+        assert_eq!(path.edges, vec![e[0], e[1], e[2], e[4]]);
+        assert_eq!(path.weight, 11);
+        assert_eq!(ch.shortest_path(n[3], n[3]).unwrap().edges, vec![]);
+        assert_eq!(ch.shortest_path(n[5], n[0]), None);
+        assert_eq!(ch.shortest_path(n[0], 1000), None);
+
+        let mut order = ch.order();
+        order.sort_unstable();
+        assert_eq!(order, g.node_ids());
+        // 1 -> 5 directly and 1 -> 2 -> 3 -> 5 both weigh 7
+        compare(&g, &all_pairs(&g), false);
+    }
+
+    #[test]
+    fn test_osm() {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm(&mut reader, &mut g);
+        compare(&g, &all_pairs(&g), true);
+    }
+
+    #[test]
+    fn test_grids() {
+        for seed in 0..5 {
+            let mut rng = Rng::new(seed);
+            let g: Graph<usize> = grid(12, 9, 1..=20, &mut rng, |i| i);
+            let ch = ContractionHierarchy::new(&g);
+            assert!(ch.shortcut_count() > 0);
+            compare(&g, &random_pairs(&g, 500, &mut rng), false);
+        }
+    }
+
+    #[test]
+    fn test_random_graphs() {
+        for seed in 0..20 {
+            let mut rng = Rng::new(seed);
+            let g: Graph<usize> = erdos_renyi(40, 0.08, 0..=10, &mut rng, |i| i);
+            compare(&g, &random_pairs(&g, 300, &mut rng), false);
+        }
+    }
+}
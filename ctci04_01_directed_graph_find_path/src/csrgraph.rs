@@ -0,0 +1,332 @@
+// -----------------------------------------------------------------------------------------------
+// Module csrgraph
+//
+// A read-only graph in compressed sparse row (CSR) layout. The nodes are renumbered to dense
+// indices 0..N, the edges are sorted by their source node so that the out edges of a node are a
+// contiguous slice of the edge arrays. Finding the out edges of a node is O(1) plus the size of
+// the result, compared to O(E) in `graph::Graph`, and the whole graph lives in a handful of
+// flat vectors without per node allocations. It implements GraphView and GraphAlgo but not
+// GraphCrud, changes go to the source graph which is then frozen again.
+//
+// A CsrGraph is created by freezing a mutable graph once it has been loaded:
+//
+//   let csr = osmgraph.freeze();
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use crate::graph::Graph;
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphAlgo, GraphView, NodeId, Path};
+use crate::shortest_path::all_simple_paths;
+
+pub struct CsrGraph<T> {
+    /// dense index -> node ID, sorted
+    node_ids: Vec<NodeId>,
+    /// node ID -> dense index
+    node_index: HashMap<NodeId, usize>,
+    values: Vec<T>,
+
+    /// the out edges of node `i` are the edge slots `out_offsets[i]..out_offsets[i + 1]`
+    out_offsets: Vec<usize>,
+    /// the in edges of node `i` are `in_edges[in_offsets[i]..in_offsets[i + 1]]`
+    in_offsets: Vec<usize>,
+    in_edges: Vec<usize>,
+
+    /// edge slot -> edge ID, the edge arrays are sorted by source node
+    edge_ids: Vec<EdgeId>,
+    edge_index: HashMap<EdgeId, usize>,
+    edge_from: Vec<usize>,
+    edge_to: Vec<usize>,
+    edge_weight: Vec<i32>,
+}
+
+impl<T: Clone> CsrGraph<T> {
+    /// Builds a CSR graph from any graph. Node and edge IDs are kept, edges between nodes that
+    /// do not exist are dropped.
+    pub fn freeze<G: GraphView<T> + ?Sized>(g: &G) -> Self {
+        let node_ids = g.node_ids();
+        let node_index: HashMap<NodeId, usize> = node_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+        let values: Vec<T> = node_ids
+            .iter()
+            .map(|id| g.get_node_val(*id).unwrap().clone())
+            .collect();
+
+        let mut edges: Vec<(usize, usize, i32, EdgeId)> = g
+            .edge_ids()
+            .into_iter()
+            .filter_map(|edge_id| {
+                let edge = g.get_edge(edge_id)?;
+                Some((
+                    *node_index.get(&edge.0)?,
+                    *node_index.get(&edge.1)?,
+                    edge.2,
+                    edge_id,
+                ))
+            })
+            .collect();
+        edges.sort_unstable_by_key(|(from, _, _, edge_id)| (*from, *edge_id));
+
+        let n = node_ids.len();
+        let mut out_offsets = vec![0; n + 1];
+        let mut in_offsets = vec![0; n + 1];
+        for (from, to, _, _) in edges.iter() {
+            out_offsets[from + 1] += 1;
+            in_offsets[to + 1] += 1;
+        }
+        for i in 0..n {
+            out_offsets[i + 1] += out_offsets[i];
+            in_offsets[i + 1] += in_offsets[i];
+        }
+        let mut in_edges = vec![0; edges.len()];
+        let mut fill = in_offsets.clone();
+        for (slot, (_, to, _, _)) in edges.iter().enumerate() {
+            in_edges[fill[*to]] = slot;
+            fill[*to] += 1;
+        }
+
+        CsrGraph {
+            node_ids,
+            node_index,
+            values,
+            out_offsets,
+            in_offsets,
+            in_edges,
+            edge_index: edges
+                .iter()
+                .enumerate()
+                .map(|(slot, e)| (e.3, slot))
+                .collect(),
+            edge_ids: edges.iter().map(|e| e.3).collect(),
+            edge_from: edges.iter().map(|e| e.0).collect(),
+            edge_to: edges.iter().map(|e| e.1).collect(),
+            edge_weight: edges.iter().map(|e| e.2).collect(),
+        }
+    }
+}
+
+impl<T: Clone> Graph<T> {
+    /// Turns the graph into a read-only CSR graph, see [`CsrGraph::freeze`].
+    pub fn freeze(&self) -> CsrGraph<T> {
+        CsrGraph::freeze(self)
+    }
+}
+
+impl<T> CsrGraph<T> {
+    pub fn node_count(&self) -> usize {
+        self.node_ids.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edge_ids.len()
+    }
+
+    /// The out edges of a node as (edge ID, target node, weight), without allocating.
+    pub fn successors(&self, node: NodeId) -> impl Iterator<Item = (EdgeId, NodeId, i32)> + '_ {
+        let range = match self.node_index.get(&node) {
+            Some(i) => self.out_offsets[*i]..self.out_offsets[*i + 1],
+            None => 0..0,
+        };
+        range.map(|slot| {
+            (
+                self.edge_ids[slot],
+                self.node_ids[self.edge_to[slot]],
+                self.edge_weight[slot],
+            )
+        })
+    }
+
+    /// Dijkstra on the dense arrays, returns the edge slots of the path.
+    fn dense_dijkstra(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let n = self.node_ids.len();
+        let mut dist = vec![i64::MAX; n];
+        let mut via = vec![usize::MAX; n];
+        dist[from] = 0;
+        let mut heap = BinaryHeap::from([Reverse((0i64, from))]);
+        while let Some(Reverse((d, node))) = heap.pop() {
+            if node == to {
+                break;
+            }
+            if d > dist[node] {
+                continue;
+            }
+            for slot in self.out_offsets[node]..self.out_offsets[node + 1] {
+                let next = self.edge_to[slot];
+                let nd = d + self.edge_weight[slot] as i64;
+                if nd < dist[next] {
+                    dist[next] = nd;
+                    via[next] = slot;
+                    heap.push(Reverse((nd, next)));
+                }
+            }
+        }
+        if dist[to] == i64::MAX {
+            return None;
+        }
+        let mut slots = Vec::new();
+        let mut node = to;
+        while node != from {
+            slots.push(via[node]);
+            node = self.edge_from[via[node]];
+        }
+        slots.reverse();
+        Some(slots)
+    }
+}
+
+impl<T> GraphView<T> for CsrGraph<T> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        self.node_index.get(&nodeid).map(|i| &self.values[*i])
+    }
+
+    fn get_edge(&self, edge: EdgeId) -> Option<EdgeTriplet> {
+        self.edge_index.get(&edge).map(|slot| {
+            EdgeTriplet(
+                self.node_ids[self.edge_from[*slot]],
+                self.node_ids[self.edge_to[*slot]],
+                self.edge_weight[*slot],
+            )
+        })
+    }
+
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        let i = *self.node_index.get(&from)?;
+        Some(self.edge_ids[self.out_offsets[i]..self.out_offsets[i + 1]].to_vec())
+    }
+
+    fn find_edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        let i = *self.node_index.get(&to)?;
+        Some(
+            self.in_edges[self.in_offsets[i]..self.in_offsets[i + 1]]
+                .iter()
+                .map(|slot| self.edge_ids[*slot])
+                .collect(),
+        )
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        self.node_ids.clone()
+    }
+
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        let mut ids = self.edge_ids.clone();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+impl<T> GraphAlgo<T> for CsrGraph<T> {
+    fn path_exists(&self, from: NodeId, to: NodeId) -> bool {
+        let (Some(from), Some(to)) = (self.node_index.get(&from), self.node_index.get(&to)) else {
+            return false;
+        };
+        let mut visited = vec![false; self.node_ids.len()];
+        visited[*from] = true;
+        let mut queue = VecDeque::from([*from]);
+        while let Some(node) = queue.pop_front() {
+            if node == *to {
+                return true;
+            }
+            for slot in self.out_offsets[node]..self.out_offsets[node + 1] {
+                let next = self.edge_to[slot];
+                if !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        false
+    }
+
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        let from = *self.node_index.get(&from)?;
+        let to = *self.node_index.get(&to)?;
+        let slots = self.dense_dijkstra(from, to)?;
+        Some(slots.iter().map(|slot| self.edge_ids[*slot]).collect())
+    }
+
+    fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
+        all_simple_paths(self, from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance;
+    use crate::graphtraits::GraphCrud;
+    use crate::shortest_path::dijkstra;
+
+    /// A graph with sparse, OSM like node IDs
+    fn sparse_graph() -> Graph<&'static str> {
+        let mut g: Graph<&str> = Graph::new();
+        g.set_node("a", 8945281129);
+        g.set_node("b", 622597649);
+        g.set_node("c", 12307697010);
+        g.set_node("d", 4);
+        g.new_edge(8945281129, 622597649, 10);
+        g.new_edge(622597649, 12307697010, 5);
+        g.new_edge(8945281129, 12307697010, 20);
+        g.new_edge(12307697010, 4, 1);
+        g.new_edge(4, 8945281129, 7);
+        g
+    }
+
+    fn sorted(mut ids: Vec<EdgeId>) -> Vec<EdgeId> {
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn test_freeze_keeps_ids() {
+        let g = sparse_graph();
+        let csr = g.freeze();
+        assert_eq!(csr.node_count(), 4);
+        assert_eq!(csr.edge_count(), 5);
+        assert_eq!(csr.node_ids(), g.node_ids());
+        assert_eq!(csr.edge_ids(), g.edge_ids());
+        for node in g.node_ids() {
+            assert_eq!(csr.get_node_val(node), g.get_node_val(node));
+            assert_eq!(
+                sorted(csr.find_edges_from(node).unwrap()),
+                sorted(g.find_edges_from(node).unwrap())
+            );
+            assert_eq!(
+                sorted(csr.find_edges_to(node).unwrap()),
+                sorted(g.find_edges_to(node).unwrap())
+            );
+        }
+        for edge_id in g.edge_ids() {
+            let (a, b) = (csr.get_edge(edge_id).unwrap(), g.get_edge(edge_id).unwrap());
+            assert_eq!((a.0, a.1, a.2), (b.0, b.1, b.2));
+        }
+        assert!(csr.get_node_val(5).is_none());
+        assert!(csr.find_edges_from(5).is_none());
+    }
+
+    #[test]
+    fn test_routing() {
+        let g = sparse_graph();
+        let csr = g.freeze();
+        let path = csr.shortest_path(8945281129, 4).unwrap();
+        assert_eq!(path, dijkstra(&g, 8945281129, 4).unwrap().edges);
+        assert_eq!(path.len(), 3);
+        assert!(csr.path_exists(4, 622597649));
+        assert_eq!(csr.all_paths(8945281129, 12307697010).unwrap().len(), 2);
+        // the generic algorithms work on the CSR graph as well
+        assert_eq!(csr.strongly_connected_components().len(), 1);
+        let successors: Vec<(EdgeId, NodeId, i32)> = csr.successors(12307697010).collect();
+        assert_eq!(successors.len(), 1);
+        assert_eq!(successors[0].1, 4);
+    }
+
+    #[test]
+    fn test_conformance_sample_paths() {
+        let (g, n, e) = conformance::sample_graph::<Graph<i32>>();
+        conformance::check_sample_paths(&g.freeze(), &n, &e);
+    }
+}
@@ -0,0 +1,248 @@
+// -----------------------------------------------------------------------------------------------
+// Module differential
+//
+// Randomized differential tests of the path algorithms. On thousands of generated graphs the DFS
+// in `path_exists`, BFS, Dijkstra, A* and Bellman-Ford have to agree on whether and how far a
+// node can be reached. A graph on which they disagree is shrunk by removing edges and nodes and
+// lowering weights as long as the check still fails, and reported together with its seed.
+//
+
+use crate::bellman_ford::bellman_ford;
+use crate::generators::{Rng, erdos_renyi, grid, scale_free};
+use crate::graph::Graph;
+use crate::graphtraits::{EdgeId, GraphAlgo, GraphCrud, GraphView, NodeId};
+use crate::shortest_path::{astar, bfs, dijkstra};
+
+const SEEDS: u64 = 1000;
+const PAIRS_PER_GRAPH: usize = 10;
+
+/// The heuristic of nodes that cannot reach the target. Large, but the sums with the distances
+/// must not overflow.
+const UNREACHABLE: i64 = i64::MAX / 4;
+
+/// A graph as plain data together with the pair of nodes that is checked, so it can be shrunk.
+#[derive(Debug, Clone)]
+struct Case {
+    nodes: Vec<NodeId>,
+    edges: Vec<(NodeId, NodeId, i32)>,
+    from: NodeId,
+    to: NodeId,
+}
+
+impl Case {
+    fn new<G: GraphView<usize>>(g: &G, from: NodeId, to: NodeId) -> Self {
+        let edges = g
+            .edge_ids()
+            .iter()
+            .filter_map(|id| g.get_edge(*id))
+            .map(|e| (e.0, e.1, e.2))
+            .collect();
+        Case {
+            nodes: g.node_ids(),
+            edges,
+            from,
+            to,
+        }
+    }
+
+    fn build(&self, reversed: bool) -> Graph<usize> {
+        let mut g = Graph::new();
+        for node in self.nodes.iter() {
+            g.set_node(*node, *node);
+        }
+        for (from, to, weight) in self.edges.iter() {
+            match reversed {
+                false => g.new_edge(*from, *to, *weight),
+                true => g.new_edge(*to, *from, *weight),
+            };
+        }
+        g
+    }
+}
+
+/// Returns the weight of a path if it is a connected path from `from` to `to`.
+fn path_weight(g: &Graph<usize>, from: NodeId, to: NodeId, path: &[EdgeId]) -> Option<i64> {
+    let mut node = from;
+    let mut weight = 0;
+    for edge_id in path {
+        let edge = g.get_edge(*edge_id)?;
+        if edge.0 != node {
+            return None;
+        }
+        node = edge.1;
+        weight += edge.2 as i64;
+    }
+    (node == to).then_some(weight)
+}
+
+fn check(case: &Case) -> Result<(), String> {
+    let g = case.build(false);
+    let (from, to) = (case.from, case.to);
+
+    let dfs = g.path_exists(from, to);
+    let bfs = bfs(&g, from, to);
+    let dijkstra = dijkstra(&g, from, to);
+    // half the exact distance to the target is a consistent heuristic
+    let to_target = bellman_ford(&case.build(true), to).map_err(|c| format!("cycle {:?}", c))?;
+    let astar = astar(&g, from, to, |node| {
+        to_target.distance(node).map_or(UNREACHABLE, |d| d / 2)
+    });
+    let distance = bellman_ford(&g, from)
+        .map_err(|c| format!("cycle {:?}", c))?
+        .distance(to);
+
+    let found = [bfs.is_some(), dijkstra.is_some(), astar.is_some()];
+    if found.iter().any(|f| *f != dfs) || distance.is_some() != dfs {
+        return Err(format!(
+            "reachability differs: dfs {}, bfs/dijkstra/astar {:?}, bellman-ford {:?}",
+            dfs, found, distance
+        ));
+    }
+    let (Some(bfs), Some(dijkstra), Some(astar), Some(distance)) = (bfs, dijkstra, astar, distance)
+    else {
+        return Ok(());
+    };
+
+    let weights = [
+        Some(dijkstra.weight),
+        path_weight(&g, from, to, &dijkstra.edges),
+        Some(astar.weight),
+        path_weight(&g, from, to, &astar.edges),
+    ];
+    if weights.iter().any(|w| *w != Some(distance)) {
+        return Err(format!(
+            "distance {} but dijkstra/its path/astar/its path give {:?}",
+            distance, weights
+        ));
+    }
+    match path_weight(&g, from, to, &bfs) {
+        Some(_) if bfs.len() <= dijkstra.edges.len() => Ok(()),
+        _ => Err(format!(
+            "bfs path {:?} is invalid or longer than {:?}",
+            bfs, dijkstra.edges
+        )),
+    }
+}
+
+/// Makes a failing case as small as possible, so that `check` still fails.
+fn shrink(mut case: Case, check: impl Fn(&Case) -> Result<(), String>) -> Case {
+    loop {
+        let mut progress = false;
+        let mut i = 0;
+        while i < case.edges.len() {
+            let mut smaller = case.clone();
+            smaller.edges.remove(i);
+            if check(&smaller).is_err() {
+                case = smaller;
+                progress = true;
+            } else {
+                i += 1;
+            }
+        }
+        let mut i = 0;
+        while i < case.nodes.len() {
+            let node = case.nodes[i];
+            if node == case.from || node == case.to {
+                i += 1;
+                continue;
+            }
+            let mut smaller = case.clone();
+            smaller.nodes.remove(i);
+            smaller.edges.retain(|e| e.0 != node && e.1 != node);
+            if check(&smaller).is_err() {
+                case = smaller;
+                progress = true;
+            } else {
+                i += 1;
+            }
+        }
+        for i in 0..case.edges.len() {
+            if case.edges[i].2 == 1 {
+                continue;
+            }
+            let mut smaller = case.clone();
+            smaller.edges[i].2 = 1;
+            if check(&smaller).is_err() {
+                case = smaller;
+                progress = true;
+            }
+        }
+        if !progress {
+            return case;
+        }
+    }
+}
+
+/// Checks random pairs of nodes on graphs generated for all seeds.
+fn run(generator: &str, generate: impl Fn(&mut Rng) -> Graph<usize>) {
+    for seed in 0..SEEDS {
+        let mut rng = Rng::new(seed);
+        let g = generate(&mut rng);
+        let nodes = g.node_ids();
+        for _ in 0..PAIRS_PER_GRAPH {
+            let from = nodes[rng.below(nodes.len())];
+            let to = nodes[rng.below(nodes.len())];
+            let case = Case::new(&g, from, to);
+            if let Err(error) = check(&case) {
+                let minimal = shrink(case, check);
+                panic!(
+                    "{} graph with seed {} fails: {}\nminimal graph: {:?}\nerror on it: {}",
+                    generator,
+                    seed,
+                    error,
+                    minimal,
+                    check(&minimal).unwrap_err()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erdos_renyi_graphs() {
+        run("Erdős–Rényi", |rng| {
+            let n = 1 + rng.below(15);
+            let p = rng.below(40) as f64 / 100.0;
+            erdos_renyi(n, p, 0..=20, rng, |i| i)
+        });
+    }
+
+    #[test]
+    fn test_grid_graphs() {
+        run("grid", |rng| {
+            let (width, height) = (1 + rng.below(5), 1 + rng.below(5));
+            grid(width, height, 1..=9, rng, |i| i)
+        });
+    }
+
+    #[test]
+    fn test_scale_free_graphs() {
+        run("scale-free", |rng| {
+            let n = 1 + rng.below(20);
+            scale_free(n, 1 + rng.below(3), 1..=20, rng, |i| i)
+        });
+    }
+
+    #[test]
+    fn test_shrink() {
+        // pretend that paths with more than 2 edges are a bug
+        let property = |case: &Case| match bfs(&case.build(false), case.from, case.to) {
+            Some(path) if path.len() > 2 => Err(format!("{} edges", path.len())),
+            _ => Ok(()),
+        };
+        let mut rng = Rng::new(5);
+        let g: Graph<usize> = grid(5, 5, 2..=9, &mut rng, |i| i);
+        let nodes = g.node_ids();
+        let case = Case::new(&g, nodes[0], nodes[24]);
+        assert!(property(&case).is_err());
+        let minimal = shrink(case, property);
+        // corner to corner takes 8 edges in a grid
+        assert_eq!(minimal.nodes.len(), 9);
+        assert_eq!(minimal.edges.len(), 8);
+        assert!(minimal.edges.iter().all(|e| e.2 == 1));
+    }
+}
@@ -0,0 +1,778 @@
+// -----------------------------------------------------------------------------------------------
+// Module diskgraph
+//
+// A graph that lives in memory mapped files, for graphs that do not fit into RAM. The operating
+// system pages the parts of the graph in and out that are needed, the process itself only holds
+// the file mappings.
+//
+// A graph is a directory with four files, each one a growable array of fixed size records behind
+// a small header:
+//
+//   nodes.bin   node ID, head of the out edge list, head of the in edge list, alive flag
+//   values.bin  the node values, one per node record
+//   edges.bin   from, to, weight, next out edge, next in edge, alive flag; edge ID = index + 1
+//   index.bin   hash table (open addressing) from node ID to node record
+//
+// The edges of a node form linked lists through the edge records, so adding an edge never moves
+// other records. Deleted records are only marked dead, the files do not shrink.
+//
+// An edge to a node that does not exist yet is linked into the lists of a dead record for that
+// node, so the index finds the waiting edges of every missing node. Creating the node revives the
+// record together with its lists.
+//
+// Because `get_node_val` hands out references into the mapping, node values have to be plain
+// data, see DiskValue. The OSM import writes straight into a DiskGraph:
+//
+//   let mut g: DiskGraph<OsmNode> = DiskGraph::create("berlin.graph")?;
+//   parse_osm(&mut reader, &mut g);
+//
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap2::MmapMut;
+
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, GraphView, NodeId, Path};
+use crate::osmgraph::OsmNode;
+use crate::shortest_path::{all_simple_paths, dijkstra};
+
+/// Types that can be stored in a DiskGraph and read back from the mapped file in place.
+///
+/// # Safety
+///
+/// Implementors must be plain data: `Copy`, without pointers or references, and with a fixed
+/// layout (a primitive or `#[repr(C)]`), so that a value written to the file by one run is still
+/// a valid value when the file is mapped again by another.
+pub unsafe trait DiskValue: Copy + 'static {}
+
+unsafe impl DiskValue for i32 {}
+unsafe impl DiskValue for i64 {}
+unsafe impl DiskValue for u32 {}
+unsafe impl DiskValue for u64 {}
+unsafe impl DiskValue for usize {}
+unsafe impl DiskValue for f64 {}
+unsafe impl DiskValue for OsmNode {}
+
+/// Marks the end of an edge list and missing node records.
+const NIL: u64 = u64::MAX;
+const MAGIC: u64 = u64::from_le_bytes(*b"GRAPHDSK");
+/// The header holds magic, record count, record size and one value for the user of the file,
+/// it is padded to 64 bytes so that the records are aligned.
+const HEADER: usize = 64;
+const MIN_CAPACITY: usize = 16;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NodeRecord {
+    id: u64,
+    first_out: u64,
+    first_in: u64,
+    alive: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct EdgeRecord {
+    from: u64,
+    to: u64,
+    weight: i64,
+    next_out: u64,
+    next_in: u64,
+    alive: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IndexEntry {
+    /// node ID + 1, 0 marks an empty entry
+    key: u64,
+    record: u64,
+}
+
+unsafe impl DiskValue for NodeRecord {}
+unsafe impl DiskValue for EdgeRecord {}
+unsafe impl DiskValue for IndexEntry {}
+
+/// A growable array of records in a memory mapped file.
+///
+/// The records are only accessed through raw pointers into the mapping, never through byte
+/// slices, since padding bytes of the values may be uninitialized.
+struct MmapVec<R> {
+    file: File,
+    map: MmapMut,
+    len: usize,
+    capacity: usize,
+    _records: PhantomData<R>,
+}
+
+impl<R: DiskValue> MmapVec<R> {
+    fn create(path: &FsPath, capacity: usize) -> io::Result<Self> {
+        assert!(align_of::<R>() <= HEADER);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let capacity = capacity.max(MIN_CAPACITY);
+        file.set_len((HEADER + capacity * size_of::<R>()) as u64)?;
+        let mut vec = MmapVec {
+            map: unsafe { MmapMut::map_mut(&file)? },
+            file,
+            len: 0,
+            capacity,
+            _records: PhantomData,
+        };
+        vec.write_header(0, MAGIC);
+        vec.write_header(1, 0);
+        vec.write_header(2, size_of::<R>() as u64);
+        vec.write_header(3, 0);
+        Ok(vec)
+    }
+
+    fn open(path: &FsPath) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let size = file.metadata()?.len() as usize;
+        if size < HEADER {
+            return Err(invalid_data(path, "file too short"));
+        }
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let mut vec = MmapVec {
+            map,
+            file,
+            len: 0,
+            capacity: (size - HEADER) / size_of::<R>(),
+            _records: PhantomData,
+        };
+        if vec.header(0) != MAGIC || vec.header(2) != size_of::<R>() as u64 {
+            return Err(invalid_data(path, "not a graph file or wrong record type"));
+        }
+        vec.len = vec.header(1) as usize;
+        if vec.len > vec.capacity {
+            return Err(invalid_data(
+                path,
+                "record count beyond the end of the file",
+            ));
+        }
+        Ok(vec)
+    }
+
+    fn header(&self, field: usize) -> u64 {
+        unsafe { (self.map.as_ptr() as *const u64).add(field).read() }
+    }
+
+    fn write_header(&mut self, field: usize, value: u64) {
+        unsafe { (self.map.as_mut_ptr() as *mut u64).add(field).write(value) }
+    }
+
+    /// The value the user of the file keeps in the header.
+    fn aux(&self) -> u64 {
+        self.header(3)
+    }
+
+    fn set_aux(&mut self, value: u64) {
+        self.write_header(3, value);
+    }
+
+    fn records(&self) -> *const R {
+        unsafe { self.map.as_ptr().add(HEADER) as *const R }
+    }
+
+    fn get(&self, i: usize) -> &R {
+        assert!(i < self.len, "record {} out of bounds", i);
+        unsafe { &*self.records().add(i) }
+    }
+
+    fn set(&mut self, i: usize, record: R) {
+        assert!(i < self.len, "record {} out of bounds", i);
+        unsafe {
+            (self.map.as_mut_ptr().add(HEADER) as *mut R)
+                .add(i)
+                .write(record)
+        }
+    }
+
+    fn push(&mut self, record: R) -> usize {
+        let i = self.push_unset();
+        self.set(i, record);
+        i
+    }
+
+    /// Appends a record without writing it. It must be set before it is read.
+    fn push_unset(&mut self) -> usize {
+        if self.len == self.capacity {
+            self.reserve(self.capacity * 2)
+                .expect("cannot grow graph file");
+        }
+        self.len += 1;
+        self.write_header(1, self.len as u64);
+        self.len - 1
+    }
+
+    fn reserve(&mut self, capacity: usize) -> io::Result<()> {
+        if capacity <= self.capacity {
+            return Ok(());
+        }
+        self.map.flush()?;
+        self.file
+            .set_len((HEADER + capacity * size_of::<R>()) as u64)?;
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+}
+
+fn invalid_data(path: &FsPath, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+/// The hash table from node ID to node record. Entries are never removed, a deleted node keeps
+/// its record (marked dead) and gets it back when the ID is used again.
+struct IdIndex {
+    table: MmapVec<IndexEntry>,
+    path: PathBuf,
+}
+
+impl IdIndex {
+    fn create(path: PathBuf, capacity: usize) -> io::Result<Self> {
+        let mut table = MmapVec::create(&path, capacity.next_power_of_two())?;
+        for _ in 0..table.capacity {
+            table.push(IndexEntry { key: 0, record: 0 });
+        }
+        Ok(IdIndex { table, path })
+    }
+
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let table = MmapVec::open(&path)?;
+        if !table.len.is_power_of_two() {
+            return Err(invalid_data(&path, "hash table size is not a power of two"));
+        }
+        Ok(IdIndex { table, path })
+    }
+
+    /// The number of used entries is kept in the header.
+    fn used(&self) -> usize {
+        self.table.aux() as usize
+    }
+
+    /// The position of `id` in the table, or of the empty entry where it belongs.
+    fn position(&self, id: NodeId) -> usize {
+        let mask = self.table.len - 1;
+        let mut i = ((id as u64).wrapping_mul(0x9e3779b97f4a7c15) >> 17) as usize & mask;
+        loop {
+            let entry = self.table.get(i);
+            if entry.key == 0 || entry.key == id as u64 + 1 {
+                return i;
+            }
+            i = (i + 1) & mask;
+        }
+    }
+
+    fn get(&self, id: NodeId) -> Option<usize> {
+        let entry = self.table.get(self.position(id));
+        (entry.key != 0).then_some(entry.record as usize)
+    }
+
+    fn insert(&mut self, id: NodeId, record: usize) {
+        if (self.used() + 1) * 10 > self.table.len * 7 {
+            self.grow().expect("cannot grow node index");
+        }
+        let i = self.position(id);
+        if self.table.get(i).key == 0 {
+            self.table.set_aux(self.used() as u64 + 1);
+        }
+        self.table.set(
+            i,
+            IndexEntry {
+                key: id as u64 + 1,
+                record: record as u64,
+            },
+        );
+    }
+
+    /// Rehashes into a table of twice the size, written next to the old one and renamed.
+    fn grow(&mut self) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut bigger = IdIndex::create(tmp.clone(), self.table.len * 2)?;
+        for i in 0..self.table.len {
+            let entry = *self.table.get(i);
+            if entry.key != 0 {
+                bigger.insert(entry.key as usize - 1, entry.record as usize);
+            }
+        }
+        bigger.table.flush()?;
+        fs::rename(&tmp, &self.path)?;
+        bigger.path = self.path.clone();
+        *self = bigger;
+        Ok(())
+    }
+}
+
+/// Temporary graphs created by `GraphCrud::new` get numbered directories.
+static TEMPORARY_GRAPHS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct DiskGraph<T> {
+    dir: PathBuf,
+    temporary: bool,
+    nodes: MmapVec<NodeRecord>,
+    values: MmapVec<T>,
+    edges: MmapVec<EdgeRecord>,
+    index: IdIndex,
+}
+
+impl<T: DiskValue> DiskGraph<T> {
+    /// Creates an empty graph in directory `dir`, existing graph files in it are overwritten.
+    pub fn create<P: AsRef<FsPath>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(DiskGraph {
+            nodes: MmapVec::create(&dir.join("nodes.bin"), 0)?,
+            values: MmapVec::create(&dir.join("values.bin"), 0)?,
+            edges: MmapVec::create(&dir.join("edges.bin"), 0)?,
+            index: IdIndex::create(dir.join("index.bin"), MIN_CAPACITY)?,
+            dir,
+            temporary: false,
+        })
+    }
+
+    /// Opens a graph created earlier with [`DiskGraph::create`].
+    pub fn open<P: AsRef<FsPath>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let graph = DiskGraph {
+            nodes: MmapVec::open(&dir.join("nodes.bin"))?,
+            values: MmapVec::open(&dir.join("values.bin"))?,
+            edges: MmapVec::open(&dir.join("edges.bin"))?,
+            index: IdIndex::open(dir.join("index.bin"))?,
+            dir,
+            temporary: false,
+        };
+        if graph.nodes.len != graph.values.len {
+            return Err(invalid_data(&graph.dir, "node and value files differ"));
+        }
+        Ok(graph)
+    }
+
+    /// Writes all changes to disk. Dropping the graph does this as well, ignoring errors.
+    pub fn flush(&self) -> io::Result<()> {
+        self.nodes.flush()?;
+        self.values.flush()?;
+        self.edges.flush()?;
+        self.index.table.flush()
+    }
+
+    /// The directory the graph is stored in.
+    pub fn dir(&self) -> &FsPath {
+        &self.dir
+    }
+
+    /// The record of a node that exists.
+    fn record(&self, id: NodeId) -> Option<usize> {
+        let record = self.index.get(id)?;
+        (self.nodes.get(record).alive != 0).then_some(record)
+    }
+
+    fn edge(&self, id: EdgeId) -> Option<EdgeRecord> {
+        if id == 0 || id > self.edges.len {
+            return None;
+        }
+        let edge = *self.edges.get(id - 1);
+        (edge.alive != 0).then_some(edge)
+    }
+
+    /// The record of a node, a new dead one if the ID has never been used. The value of such a
+    /// record is only written when the node is created.
+    fn record_or_placeholder(&mut self, id: NodeId) -> usize {
+        if let Some(record) = self.index.get(id) {
+            return record;
+        }
+        let record = self.nodes.push(NodeRecord {
+            id: id as u64,
+            first_out: NIL,
+            first_in: NIL,
+            alive: 0,
+        });
+        self.values.push_unset();
+        self.index.insert(id, record);
+        record
+    }
+
+    /// Puts an edge at the front of the out list of `from` and the in list of `to`. The lists of
+    /// a node that does not exist yet are kept in its dead record until the node is created.
+    fn link(&mut self, slot: usize) {
+        let edge = *self.edges.get(slot);
+        let from = self.record_or_placeholder(edge.from as NodeId);
+        self.link_end(slot, from, true);
+        let to = self.record_or_placeholder(edge.to as NodeId);
+        self.link_end(slot, to, false);
+    }
+
+    fn link_end(&mut self, slot: usize, record: usize, outgoing: bool) {
+        let mut edge = *self.edges.get(slot);
+        let mut node = *self.nodes.get(record);
+        if outgoing {
+            edge.next_out = node.first_out;
+            node.first_out = slot as u64;
+        } else {
+            edge.next_in = node.first_in;
+            node.first_in = slot as u64;
+        }
+        self.nodes.set(record, node);
+        self.edges.set(slot, edge);
+    }
+
+    /// Removes an edge from the lists it is in.
+    fn unlink(&mut self, slot: usize) {
+        let edge = *self.edges.get(slot);
+        if let Some(from) = self.index.get(edge.from as NodeId) {
+            let mut node = *self.nodes.get(from);
+            if node.first_out == slot as u64 {
+                node.first_out = edge.next_out;
+                self.nodes.set(from, node);
+            } else {
+                let mut prev = node.first_out;
+                while prev != NIL {
+                    let mut prev_edge = *self.edges.get(prev as usize);
+                    if prev_edge.next_out == slot as u64 {
+                        prev_edge.next_out = edge.next_out;
+                        self.edges.set(prev as usize, prev_edge);
+                        break;
+                    }
+                    prev = prev_edge.next_out;
+                }
+            }
+        }
+        if let Some(to) = self.index.get(edge.to as NodeId) {
+            let mut node = *self.nodes.get(to);
+            if node.first_in == slot as u64 {
+                node.first_in = edge.next_in;
+                self.nodes.set(to, node);
+            } else {
+                let mut prev = node.first_in;
+                while prev != NIL {
+                    let mut prev_edge = *self.edges.get(prev as usize);
+                    if prev_edge.next_in == slot as u64 {
+                        prev_edge.next_in = edge.next_in;
+                        self.edges.set(prev as usize, prev_edge);
+                        break;
+                    }
+                    prev = prev_edge.next_in;
+                }
+            }
+        }
+    }
+
+    /// Follows an edge list, `outgoing` selects the out or in list. The edges come in the order
+    /// in which they were added.
+    fn edge_list(&self, first: u64, outgoing: bool) -> Vec<EdgeId> {
+        let mut result = Vec::new();
+        let mut slot = first;
+        while slot != NIL {
+            result.push(slot as usize + 1);
+            let edge = self.edges.get(slot as usize);
+            slot = if outgoing {
+                edge.next_out
+            } else {
+                edge.next_in
+            };
+        }
+        result.reverse();
+        result
+    }
+}
+
+impl<T> Drop for DiskGraph<T> {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_dir_all(&self.dir);
+        } else {
+            let _ = self.nodes.map.flush();
+            let _ = self.values.map.flush();
+            let _ = self.edges.map.flush();
+            let _ = self.index.table.map.flush();
+        }
+    }
+}
+
+impl<T: DiskValue> GraphView<T> for DiskGraph<T> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        self.record(nodeid).map(|record| self.values.get(record))
+    }
+
+    fn get_edge(&self, edge: EdgeId) -> Option<EdgeTriplet> {
+        self.edge(edge)
+            .map(|e| EdgeTriplet(e.from as NodeId, e.to as NodeId, e.weight as i32))
+    }
+
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        let record = self.record(from)?;
+        Some(self.edge_list(self.nodes.get(record).first_out, true))
+    }
+
+    fn find_edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        let record = self.record(to)?;
+        Some(self.edge_list(self.nodes.get(record).first_in, false))
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = (0..self.nodes.len)
+            .map(|i| self.nodes.get(i))
+            .filter(|node| node.alive != 0)
+            .map(|node| node.id as NodeId)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        (0..self.edges.len)
+            .filter(|i| self.edges.get(*i).alive != 0)
+            .map(|i| i + 1)
+            .collect()
+    }
+}
+
+impl<T: DiskValue> GraphCrud<T> for DiskGraph<T> {
+    /// A graph in a temporary directory that is removed again when the graph is dropped.
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "diskgraph-{}-{}",
+            std::process::id(),
+            TEMPORARY_GRAPHS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut graph = DiskGraph::create(dir).expect("cannot create temporary graph");
+        graph.temporary = true;
+        graph
+    }
+
+    fn new_node(&mut self, val: T) -> NodeId {
+        // the header value of the node file is the highest node ID used so far
+        let id = self.nodes.aux() as NodeId + 1;
+        self.set_node(val, id)
+    }
+
+    fn set_node(&mut self, val: T, id: NodeId) -> NodeId {
+        if id as u64 > self.nodes.aux() {
+            self.nodes.set_aux(id as u64);
+        }
+        match self.index.get(id) {
+            Some(record) => {
+                // a dead record keeps the edges that were added while the node did not exist
+                let mut node = *self.nodes.get(record);
+                self.values.set(record, val);
+                node.alive = 1;
+                self.nodes.set(record, node);
+            }
+            None => {
+                let record = self.nodes.push(NodeRecord {
+                    id: id as u64,
+                    first_out: NIL,
+                    first_in: NIL,
+                    alive: 1,
+                });
+                self.values.push(val);
+                self.index.insert(id, record);
+            }
+        }
+        id
+    }
+
+    fn del_node(&mut self, nodeid: NodeId) -> bool {
+        let Some(record) = self.record(nodeid) else {
+            return false;
+        };
+        let node = *self.nodes.get(record);
+        let mut edges = self.edge_list(node.first_out, true);
+        edges.extend(self.edge_list(node.first_in, false));
+        for edge_id in edges {
+            self.del_edge(edge_id);
+        }
+        let mut node = *self.nodes.get(record);
+        node.alive = 0;
+        self.nodes.set(record, node);
+        true
+    }
+
+    fn set_node_val(&mut self, nodeid: NodeId, val: T) {
+        if let Some(record) = self.record(nodeid) {
+            self.values.set(record, val);
+        }
+    }
+
+    fn new_edge(&mut self, from: NodeId, to: NodeId, weight: i32) -> EdgeId {
+        let slot = self.edges.push(EdgeRecord {
+            from: from as u64,
+            to: to as u64,
+            weight: weight as i64,
+            next_out: NIL,
+            next_in: NIL,
+            alive: 1,
+        });
+        self.link(slot);
+        slot + 1
+    }
+
+    fn del_edge(&mut self, edge: EdgeId) -> bool {
+        let Some(mut record) = self.edge(edge) else {
+            return false;
+        };
+        self.unlink(edge - 1);
+        record.alive = 0;
+        self.edges.set(edge - 1, record);
+        true
+    }
+
+    fn set_edge(&mut self, edge: EdgeId, edge_data: EdgeTriplet) {
+        let Some(mut record) = self.edge(edge) else {
+            return;
+        };
+        self.unlink(edge - 1);
+        record.from = edge_data.0 as u64;
+        record.to = edge_data.1 as u64;
+        record.weight = edge_data.2 as i64;
+        record.next_out = NIL;
+        record.next_in = NIL;
+        self.edges.set(edge - 1, record);
+        self.link(edge - 1);
+    }
+}
+
+impl<T: DiskValue> GraphAlgo<T> for DiskGraph<T> {
+    fn path_exists(&self, from: NodeId, to: NodeId) -> bool {
+        crate::shortest_path::bfs(self, from, to).is_some()
+    }
+
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        dijkstra(self, from, to).map(|path| path.edges)
+    }
+
+    fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
+        all_simple_paths(self, from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::graph::Graph;
+    use crate::osmgraph::{OsmTags, parse_osm, parse_osm_with_tags};
+
+    crate::conformance::conformance_tests!(DiskGraph<i32>);
+
+    #[test]
+    fn test_reopen() {
+        let dir =
+            std::env::temp_dir().join(format!("diskgraph-test-reopen-{}", std::process::id()));
+        let (a, b, ab) = {
+            let mut g: DiskGraph<i64> = DiskGraph::create(&dir).unwrap();
+            let a = g.new_node(10);
+            let b = g.set_node(20, 8945281129);
+            let ab = g.new_edge(a, b, 7);
+            // enough nodes to grow all files and the index a few times
+            for v in 0..1000 {
+                let n = g.new_node(v);
+                g.new_edge(b, n, 1);
+            }
+            g.del_node(a);
+            g.flush().unwrap();
+            (a, b, ab)
+        };
+        let g: DiskGraph<i64> = DiskGraph::open(&dir).unwrap();
+        assert_eq!(g.node_ids().len(), 1001);
+        assert_eq!(g.get_node_val(a), None);
+        assert_eq!(g.get_node_val(b), Some(&20));
+        assert_eq!(g.get_edge(ab), None);
+        assert_eq!(g.find_edges_from(b).unwrap().len(), 1000);
+        assert_eq!(g.find_edges_to(b), Some(vec![]));
+        drop(g);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(DiskGraph::<i32>::open(&dir).is_err());
+    }
+
+    #[test]
+    fn test_edges_before_nodes() {
+        let mut g: DiskGraph<i32> = DiskGraph::new();
+        let a = g.new_node(1);
+        let ab = g.new_edge(a, 100, 1);
+        let bb = g.new_edge(100, 100, 2);
+        let bc = g.new_edge(100, 200, 3);
+        assert_eq!(g.find_edges_from(a), Some(vec![ab]));
+        assert_eq!(g.find_edges_to(100), None);
+
+        g.set_node(2, 100);
+        assert_eq!(g.find_edges_to(100), Some(vec![ab, bb]));
+        assert_eq!(g.find_edges_from(100), Some(vec![bb, bc]));
+        assert!(g.path_exists(a, 100));
+        // deleting node 100 also deletes 100 -> 200, whose end at node 200 was still waiting
+        g.del_edge(bb);
+        g.del_node(100);
+        assert_eq!(g.get_edge(bc), None);
+        g.set_node(3, 200);
+        assert_eq!(g.find_edges_to(200), Some(vec![]));
+
+        // the waiting edges are kept in a dead record of the missing node
+        let cd = g.new_edge(200, 300, 4);
+        let record = g.index.get(300).unwrap();
+        assert_eq!(g.nodes.get(record).alive, 0);
+        assert_eq!(g.edge_list(g.nodes.get(record).first_in, false), vec![cd]);
+        assert_eq!(g.node_ids(), vec![a, 200]);
+        assert!(g.del_edge(cd));
+        assert_eq!(g.nodes.get(record).first_in, NIL);
+    }
+
+    #[test]
+    fn test_osm_import() {
+        let mut disk: DiskGraph<OsmNode> = DiskGraph::new();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm(&mut reader, &mut disk);
+        let mut mem: Graph<OsmNode> = Graph::new();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm(&mut reader, &mut mem);
+
+        // test.osm has 73 node elements, three of them twice
+        assert_eq!(disk.node_ids().len(), 70);
+        assert_eq!(disk.node_ids(), mem.node_ids());
+        for id in mem.node_ids() {
+            let (d, m) = (
+                disk.get_node_val(id).unwrap(),
+                mem.get_node_val(id).unwrap(),
+            );
+            assert_eq!(
+                (d.id, d.lat, d.lon, d.version),
+                (m.id, m.lat, m.lon, m.version)
+            );
+        }
+        let edges = |g: &dyn GraphView<OsmNode>| {
+            let mut edges: Vec<EdgeTriplet> = g
+                .edge_ids()
+                .into_iter()
+                .map(|id| g.get_edge(id).unwrap())
+                .collect();
+            edges.sort_by_key(|e| (e.0, e.1, e.2));
+            edges
+        };
+        assert_eq!(edges(&disk), edges(&mem));
+        // without the tags the import builds the same graph
+        let mut tagged: Graph<OsmNode> = Graph::new();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm_with_tags(&mut reader, &mut tagged, &mut OsmTags::default());
+        assert_eq!(edges(&tagged), edges(&mem));
+        let path = disk.shortest_path(8945281129, 12307697010);
+        assert_eq!(path.is_some(), mem.path_exists(8945281129, 12307697010));
+    }
+}
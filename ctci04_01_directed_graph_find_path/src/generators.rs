@@ -0,0 +1,209 @@
+// -----------------------------------------------------------------------------------------------
+// Module generators
+//
+// Random graphs for tests and benchmarks: Erdős–Rényi, grids (road network like) and scale-free
+// graphs (a few hubs with many edges). The graphs only depend on the seed of the random number
+// generator, so a failing test can be reproduced from its seed.
+//
+// The node values are produced by a closure that gets the index of the node (0 .. n), for grids
+// the index is `y * width + x`.
+//
+
+use std::ops::RangeInclusive;
+
+use crate::graphtraits::{GraphCrud, NodeId};
+
+/// A small pseudo random number generator (SplitMix64). Good enough for generating test data, not
+/// for anything that needs real randomness.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, `n` must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// `true` with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64) / ((1u64 << 53) as f64) < p
+    }
+
+    /// A number in the given range.
+    pub fn weight(&mut self, weights: &RangeInclusive<i32>) -> i32 {
+        let span = (*weights.end() as i64 - *weights.start() as i64 + 1) as u64;
+        (*weights.start() as i64 + (self.next_u64() % span) as i64) as i32
+    }
+}
+
+fn new_nodes<T, G: GraphCrud<T>>(
+    g: &mut G,
+    n: usize,
+    mut value: impl FnMut(usize) -> T,
+) -> Vec<NodeId> {
+    (0..n).map(|i| g.new_node(value(i))).collect()
+}
+
+/// Directed Erdős–Rényi graph G(n, p): every ordered pair of different nodes gets an edge with
+/// probability `p`.
+pub fn erdos_renyi<T, G: GraphCrud<T>>(
+    n: usize,
+    p: f64,
+    weights: RangeInclusive<i32>,
+    rng: &mut Rng,
+    value: impl FnMut(usize) -> T,
+) -> G {
+    let mut g = G::new();
+    let nodes = new_nodes(&mut g, n, value);
+    for from in nodes.iter() {
+        for to in nodes.iter() {
+            if from != to && rng.chance(p) {
+                g.new_edge(*from, *to, rng.weight(&weights));
+            }
+        }
+    }
+    g
+}
+
+/// A `width` x `height` grid where every node has edges in both directions to its horizontal and
+/// vertical neighbours. Both directions get their own random weight.
+pub fn grid<T, G: GraphCrud<T>>(
+    width: usize,
+    height: usize,
+    weights: RangeInclusive<i32>,
+    rng: &mut Rng,
+    value: impl FnMut(usize) -> T,
+) -> G {
+    let mut g = G::new();
+    let nodes = new_nodes(&mut g, width * height, value);
+    for y in 0..height {
+        for x in 0..width {
+            let node = nodes[y * width + x];
+            let mut neighbours = Vec::new();
+            if x + 1 < width {
+                neighbours.push(nodes[y * width + x + 1]);
+            }
+            if y + 1 < height {
+                neighbours.push(nodes[(y + 1) * width + x]);
+            }
+            for next in neighbours {
+                g.new_edge(node, next, rng.weight(&weights));
+                g.new_edge(next, node, rng.weight(&weights));
+            }
+        }
+    }
+    g
+}
+
+/// Scale-free graph by preferential attachment (Barabási–Albert): every new node is attached to
+/// `m` different existing nodes, picked with a probability that grows with their degree. The edge
+/// from the new node to the old one always exists, the edge back with probability 1/2.
+pub fn scale_free<T, G: GraphCrud<T>>(
+    n: usize,
+    m: usize,
+    weights: RangeInclusive<i32>,
+    rng: &mut Rng,
+    value: impl FnMut(usize) -> T,
+) -> G {
+    let mut g = G::new();
+    let nodes = new_nodes(&mut g, n, value);
+    // every node appears once plus once per edge, picking from it prefers high degrees
+    let mut ends: Vec<usize> = Vec::new();
+    for i in 0..n {
+        let mut targets: Vec<usize> = Vec::new();
+        while targets.len() < m.min(i) {
+            let target = ends[rng.below(ends.len())];
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        for target in targets {
+            g.new_edge(nodes[i], nodes[target], rng.weight(&weights));
+            if rng.chance(0.5) {
+                g.new_edge(nodes[target], nodes[i], rng.weight(&weights));
+            }
+            ends.push(i);
+            ends.push(target);
+        }
+        ends.push(i);
+    }
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphView;
+
+    fn edges(g: &Graph<usize>) -> Vec<(NodeId, NodeId, i32)> {
+        g.edge_ids()
+            .iter()
+            .map(|id| g.get_edge(*id).unwrap())
+            .map(|e| (e.0, e.1, e.2))
+            .collect()
+    }
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let xs: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        let ys: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+        let weights = -2..=3;
+        assert!((0..1000).all(|_| weights.contains(&a.weight(&weights))));
+        assert!((0..1000).all(|_| a.below(7) < 7));
+    }
+
+    #[test]
+    fn test_erdos_renyi() {
+        let g: Graph<usize> = erdos_renyi(10, 0.0, 1..=5, &mut Rng::new(1), |i| i);
+        assert_eq!(g.node_ids().len(), 10);
+        assert!(g.edge_ids().is_empty());
+        let g: Graph<usize> = erdos_renyi(10, 1.0, 1..=5, &mut Rng::new(1), |i| i);
+        assert_eq!(g.edge_ids().len(), 90);
+        let a: Graph<usize> = erdos_renyi(10, 0.3, 1..=5, &mut Rng::new(7), |i| i);
+        let b: Graph<usize> = erdos_renyi(10, 0.3, 1..=5, &mut Rng::new(7), |i| i);
+        assert_eq!(edges(&a), edges(&b));
+    }
+
+    #[test]
+    fn test_grid() {
+        let g: Graph<usize> = grid(4, 3, 1..=1, &mut Rng::new(1), |i| i);
+        assert_eq!(g.node_ids().len(), 12);
+        // 3 horizontal neighbours per row, 4 vertical ones between two rows, both directions
+        assert_eq!(g.edge_ids().len(), 2 * (3 * 3 + 4 * 2));
+        let corner = g.node_ids()[0];
+        assert_eq!(g.find_edges_from(corner).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_scale_free() {
+        let g: Graph<usize> = scale_free(200, 2, 1..=5, &mut Rng::new(3), |i| i);
+        let forward = edges(&g).iter().filter(|e| e.0 > e.1).count();
+        assert_eq!(forward, 1 + 2 * 198);
+        // preferential attachment makes the oldest nodes hubs
+        let degree = |node: NodeId| {
+            g.find_edges_from(node).unwrap().len() + g.find_edges_to(node).unwrap().len()
+        };
+        let nodes = g.node_ids();
+        let max_degree = nodes.iter().map(|n| degree(*n)).max().unwrap();
+        assert!(max_degree > 20, "max degree {}", max_degree);
+    }
+}
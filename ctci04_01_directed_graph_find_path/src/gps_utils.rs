@@ -16,6 +16,8 @@
 /// # Example
 ///
 /// ```
+/// use ctci04_01_directed_graph_find_path::gps_utils::calculate_distance;
+///
 /// let distance = calculate_distance(52.5200, 13.4050, 48.8566, 2.3522);
 /// println!("Distance: {} meters", distance);
 /// ```
@@ -1,9 +1,13 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::graphtraits::{EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, GraphDisplay, NodeId, Path};
+use crate::graphtraits::{
+    EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, GraphDisplay, GraphView, NodeId, Path,
+};
+use crate::shortest_path::{all_simple_paths, dijkstra};
 
 pub struct Graph<T> {
-    last_id: NodeId,
+    last_node_id: NodeId,
+    last_edge_id: EdgeId,
     nodes: HashMap<NodeId, Node<T>>,
     edges: HashMap<EdgeId, Edge>,
 }
@@ -18,10 +22,85 @@ struct Edge {
     weight: i32,
 }
 
+impl<T> Graph<T> {
+    /// Makes room for at least the given number of additional nodes and edges.
+    pub(crate) fn reserve(&mut self, nodes: usize, edges: usize) {
+        self.nodes.reserve(nodes);
+        self.edges.reserve(edges);
+    }
+
+    /// Inserts an edge with a given ID, used to bring back deleted edges.
+    pub(crate) fn restore_edge(&mut self, id: EdgeId, edge: EdgeTriplet) {
+        self.last_edge_id = self.last_edge_id.max(id);
+        self.edges.insert(
+            id,
+            Edge {
+                from: edge.0,
+                to: edge.1,
+                weight: edge.2,
+            },
+        );
+    }
+}
+
+impl<T> GraphView<T> for Graph<T> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        let x = self.nodes.get(&nodeid);
+        match x {
+            Some(node) => Some(&node.value),
+            None => None,
+        }
+    }
+
+    fn get_edge(&self, edgeid: EdgeId) -> Option<EdgeTriplet> {
+        let entry = self.edges.get(&edgeid);
+        entry.map(|e| EdgeTriplet(e.from, e.to, e.weight))
+    }
+
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        if self.nodes.contains_key(&from) {
+            let out_edge: Vec<EdgeId> = self
+                .edges
+                .iter()
+                .filter(|e| e.1.from == from)
+                .map(|e| *e.0)
+                .collect();
+            return Some(out_edge);
+        }
+        None
+    }
+
+    fn find_edges_to(&self, to: EdgeId) -> Option<Vec<EdgeId>> {
+        if self.nodes.contains_key(&to) {
+            let out_edge: Vec<EdgeId> = self
+                .edges
+                .iter()
+                .filter(|e| e.1.to == to)
+                .map(|e| *e.0)
+                .collect();
+            return Some(out_edge);
+        }
+        None
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self.nodes.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        let mut ids: Vec<EdgeId> = self.edges.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
 impl<T> GraphCrud<T> for Graph<T> {
     fn new() -> Self {
         Graph {
-            last_id: 0,
+            last_node_id: 0,
+            last_edge_id: 0,
             nodes: HashMap::new(),
             edges: HashMap::new(),
         }
@@ -29,14 +108,16 @@ impl<T> GraphCrud<T> for Graph<T> {
 
     fn new_node(&mut self, value: T) -> NodeId {
         let n = Node { value };
-        self.last_id += 1;
-        self.nodes.insert(self.last_id, n);
-        self.last_id
+        self.last_node_id += 1;
+        self.nodes.insert(self.last_node_id, n);
+        self.last_node_id
     }
 
     fn set_node(&mut self, value: T, id: NodeId) -> NodeId {
         let n = Node { value };
         self.nodes.insert(id, n); // will overwrite old value thee was one
+        // new_node must not hand out this ID later
+        self.last_node_id = self.last_node_id.max(id);
         id
     }
 
@@ -57,33 +138,21 @@ impl<T> GraphCrud<T> for Graph<T> {
         false
     }
 
-    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
-        let x = self.nodes.get(&nodeid);
-        match x {
-            Some(node) => Some(&node.value),
-            None => None,
-        }
-    }
-
     fn set_node_val(&mut self, nodeid: NodeId, val: T) {
         self.nodes.entry(nodeid).and_modify(|node| node.value = val);
     }
 
     fn new_edge(&mut self, from: NodeId, to: NodeId, weight: i32) -> EdgeId {
-        self.last_id += 1;
-        self.edges.insert(self.last_id, Edge { from, to, weight });
-        self.last_id
+        self.last_edge_id += 1;
+        self.edges
+            .insert(self.last_edge_id, Edge { from, to, weight });
+        self.last_edge_id
     }
 
     fn del_edge(&mut self, edgeid: EdgeId) -> bool {
         self.edges.remove(&edgeid).is_some()
     }
 
-    fn get_edge(&self, edgeid: EdgeId) -> Option<EdgeTriplet> {
-        let entry = self.edges.get(&edgeid);
-        entry.map(|e| EdgeTriplet(e.from, e.to, e.weight))
-    }
-
     fn set_edge(&mut self, edgeid: EdgeId, edge_data: EdgeTriplet) {
         self.edges.entry(edgeid).and_modify(|e| {
             e.from = edge_data.0;
@@ -91,32 +160,6 @@ impl<T> GraphCrud<T> for Graph<T> {
             e.weight = edge_data.2
         });
     }
-
-    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
-        if self.nodes.contains_key(&from) {
-            let out_edge: Vec<EdgeId> = self
-                .edges
-                .iter()
-                .filter(|e| e.1.from == from)
-                .map(|e| *e.0)
-                .collect();
-            return Some(out_edge);
-        }
-        None
-    }
-
-    fn find_edges_to(&self, to: EdgeId) -> Option<Vec<EdgeId>> {
-        if self.nodes.contains_key(&to) {
-            let out_edge: Vec<EdgeId> = self
-                .edges
-                .iter()
-                .filter(|e| e.1.to == to)
-                .map(|e| *e.0)
-                .collect();
-            return Some(out_edge);
-        }
-        None
-    }
 }
 
 impl<T: std::fmt::Display> GraphDisplay<T> for Graph<T> {
@@ -186,10 +229,11 @@ fn find_directed_path<T>(
     }
     visited_nodes.insert(start_node);
     path.push(start_node);
-    let n1 = &g.nodes[&start_node];
-    for n in n1.neighbors.iter() {
-        let result = find_directed_path(g, *n, end_node, visited_nodes, path);
-        if result == true {
+    let out_edges = g.find_edges_from(start_node).unwrap_or_default();
+    for edge_id in out_edges.iter() {
+        let n = g.edges[edge_id].to;
+        let result = find_directed_path(g, n, end_node, visited_nodes, path);
+        if result {
             return true;
         } else {
             path.pop();
@@ -203,17 +247,42 @@ impl<T> GraphAlgo<T> for Graph<T> {
         let mut visited_nodes = HashSet::new();
         let mut path = Vec::new();
 
-        let found_path = find_directed_path(self, from, to, &mut visited_nodes, &mut path);
-
-        println!("Result: found a path: {} path is {:?}", found_path, path);
-        found_path
+        find_directed_path(self, from, to, &mut visited_nodes, &mut path)
     }
 
     fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
-        todo!()
+        dijkstra(self, from, to).map(|path| path.edges)
     }
 
     fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
-        todo!()
+        all_simple_paths(self, from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::conformance::conformance_tests!(Graph<i32>);
+
+    #[test]
+    fn test_new_node_after_set_node() {
+        let mut g: Graph<i32> = Graph::new();
+        g.set_node(1, 500);
+        assert_eq!(g.new_node(2), 501);
+        assert_eq!(g.get_node_val(500), Some(&1));
+        // lower IDs do not move the counter back
+        g.set_node(3, 7);
+        assert_eq!(g.new_node(4), 502);
+    }
+
+    #[test]
+    fn test_node_and_edge_ids_are_separate() {
+        // OSM imports set large node IDs, edges are still numbered from 1
+        let mut g: Graph<i32> = Graph::new();
+        g.set_node(1, 8945281129);
+        g.set_node(2, 8945281164);
+        assert_eq!(g.new_edge(8945281129, 8945281164, 1), 1);
+        assert_eq!(g.new_node(3), 8945281165);
     }
 }
@@ -1,29 +1,52 @@
 use std::fmt::Display;
 
+use crate::apsp::{self, DistanceMatrix};
+use crate::bellman_ford::{self, ShortestPathTree};
+use crate::graph::Graph;
+use crate::maxflow::{self, MaxFlow};
+use crate::scc::{self, Condensation};
+use crate::shortest_path::{self, WeightedPath};
+use crate::spanning_tree::{self, SpanningTree};
+use crate::subgraph;
+use crate::toposort::{self, Cycle};
+
 pub type NodeId = usize;
 pub type EdgeId = usize;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct EdgeTriplet(pub NodeId, pub NodeId, pub i32);
 
 pub type Path = Vec<EdgeId>;
 
-pub trait GraphCrud<T> {
+/// Reading a graph. Read-only graphs and views only implement this, see [`GraphCrud`] for the
+/// mutable ones.
+pub trait GraphView<T> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T>;
+    fn get_edge(&self, edge: EdgeId) -> Option<EdgeTriplet>;
+
+    fn find_edges_from(&self, from: EdgeId) -> Option<Vec<EdgeId>>;
+    fn find_edges_to(&self, to: EdgeId) -> Option<Vec<EdgeId>>;
+
+    fn node_ids(&self) -> Vec<NodeId>;
+    fn edge_ids(&self) -> Vec<EdgeId>;
+}
+
+pub trait GraphCrud<T>: GraphView<T> {
     fn new() -> Self
     where
         Self: Sized;
     fn new_node(&mut self, val: T) -> NodeId;
+    /// Creates the node with the given ID or replaces its value. Returns `id`.
     fn set_node(&mut self, val: T, id: NodeId) -> NodeId;
     fn del_node(&mut self, nodeid: NodeId) -> bool;
-    fn get_node_val(&self, nodeid: NodeId) -> Option<&T>;
     fn set_node_val(&mut self, nodeid: NodeId, val: T);
 
+    /// Adds an edge. The nodes do not have to exist yet: the edge is stored right away (see
+    /// `get_edge` and `edge_ids`) and is listed by `find_edges_from` and `find_edges_to` at the
+    /// latest once both of its nodes exist.
     fn new_edge(&mut self, from: NodeId, to: NodeId, weight: i32) -> EdgeId;
     fn del_edge(&mut self, edge: EdgeId) -> bool;
-    fn get_edge(&self, edge: EdgeId) -> Option<EdgeTriplet>;
     fn set_edge(&mut self, edge: EdgeId, edge_data: EdgeTriplet);
-
-    fn find_edges_from(&self, from: EdgeId) -> Option<Vec<EdgeId>>;
-    fn find_edges_to(&self, to: EdgeId) -> Option<Vec<EdgeId>>;
 }
 
 pub trait GraphDisplay<T: Display> {
@@ -33,8 +56,95 @@ pub trait GraphDisplay<T: Display> {
     fn path_to_string(&self, path: &Path) -> Option<String>;
 }
 
-pub trait GraphAlgo<T> {
+pub trait GraphAlgo<T>: GraphView<T> {
     fn path_exists(&self, from: NodeId, to: NodeId) -> bool;
     fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path>;
     fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>>;
+
+    /// Up to `k` loopless paths ordered by weight, see [`shortest_path::k_shortest_paths`].
+    fn k_shortest_paths(&self, from: NodeId, to: NodeId, k: usize) -> Vec<WeightedPath> {
+        shortest_path::k_shortest_paths(self, from, to, k)
+    }
+
+    /// Single source shortest paths with negative weights, see [`bellman_ford::bellman_ford`].
+    fn bellman_ford(&self, source: NodeId) -> Result<ShortestPathTree, Cycle> {
+        bellman_ford::bellman_ford(self, source)
+    }
+
+    /// All pairs shortest paths, see [`apsp::floyd_warshall`].
+    fn floyd_warshall(&self) -> Result<DistanceMatrix, Cycle> {
+        apsp::floyd_warshall(self)
+    }
+
+    /// All pairs shortest paths for sparse graphs, see [`apsp::johnson`].
+    fn johnson(&self) -> Result<DistanceMatrix, Cycle> {
+        apsp::johnson(self)
+    }
+
+    /// Maximum flow and minimum cut with the weights as capacities, see [`maxflow::max_flow`].
+    fn max_flow(&self, source: NodeId, sink: NodeId) -> Option<MaxFlow> {
+        maxflow::max_flow(self, source, sink)
+    }
+
+    /// Strongly connected components (Tarjan), see [`scc::tarjan_scc`].
+    fn strongly_connected_components(&self) -> Vec<Vec<NodeId>> {
+        scc::tarjan_scc(self)
+    }
+
+    /// Collapses every strongly connected component into one node, see [`scc::condensation`].
+    fn condensation(&self) -> Condensation {
+        scc::condensation(self)
+    }
+
+    /// Copy of the largest strongly connected component, see [`scc::largest_scc_subgraph`].
+    fn largest_scc_subgraph(&self) -> Graph<T>
+    where
+        T: Clone,
+    {
+        scc::largest_scc_subgraph(self)
+    }
+
+    /// Copy of the given nodes and the edges between them, see [`subgraph::induced_subgraph`].
+    fn induced_subgraph(&self, node_ids: &[NodeId]) -> Graph<T>
+    where
+        T: Clone,
+    {
+        subgraph::induced_subgraph(self, node_ids)
+    }
+
+    /// Topological order of all nodes or a cycle, see [`toposort::topological_sort`].
+    fn topological_sort(&self) -> Result<Vec<NodeId>, Cycle> {
+        toposort::topological_sort(self)
+    }
+
+    /// Whether the graph has a directed cycle, see [`toposort::has_cycle`].
+    fn has_cycle(&self) -> bool {
+        toposort::has_cycle(self)
+    }
+
+    /// Minimum spanning forest of the undirected view, see [`spanning_tree::kruskal_mst`].
+    fn kruskal_mst(&self) -> SpanningTree {
+        spanning_tree::kruskal_mst(self)
+    }
+
+    /// Minimum spanning forest of the undirected view, see [`spanning_tree::prim_mst`].
+    fn prim_mst(&self) -> SpanningTree {
+        spanning_tree::prim_mst(self)
+    }
+
+    /// Minimum spanning arborescence, see [`spanning_tree::min_arborescence`].
+    fn min_arborescence(&self, root: NodeId) -> Option<SpanningTree> {
+        spanning_tree::min_arborescence(self, root)
+    }
+}
+
+/// Returns (edge id, from, to, weight) for all edges of the graph that connect two existing nodes.
+pub(crate) fn valid_edges<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+) -> Vec<(EdgeId, NodeId, NodeId, i32)> {
+    g.edge_ids()
+        .into_iter()
+        .filter_map(|edge_id| g.get_edge(edge_id).map(|e| (edge_id, e.0, e.1, e.2)))
+        .filter(|(_, from, to, _)| g.get_node_val(*from).is_some() && g.get_node_val(*to).is_some())
+        .collect()
 }
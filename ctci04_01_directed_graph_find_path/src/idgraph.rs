@@ -1,88 +1,533 @@
-use std::collections::HashSet;
+// -----------------------------------------------------------------------------------------------
+// Module idgraph
+//
+// This module keeps nodes and edges in Vec arenas and refers to them by index.
+//
+// IDs are generational indices: the lower 32 bits are the slot in the arena, the upper 32 bits
+// the generation of the slot. Deleting a node or edge frees its slot for reuse and bumps the
+// generation, so an old ID that still points to the slot is detected as stale instead of silently
+// returning the new occupant.
+//
+// `new_node` assigns such an ID. A node created by `set_node` keeps the ID of the caller, e.g. an
+// OSM node ID, which is mapped to the arena ID of its slot; `new_node` skips arena IDs that are
+// already taken this way. Edges to nodes that do not exist yet are indexed by the missing node
+// and linked when it is created.
+//
 
-struct Node {
-    value: i32,
-    neighbors: Vec<usize>,
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use crate::graphtraits::{
+    EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, GraphDisplay, GraphView, NodeId, Path,
+};
+use crate::shortest_path::{all_simple_paths, dijkstra};
+
+const SLOT_BITS: u32 = 32;
+const SLOT_MASK: usize = (1 << SLOT_BITS) - 1;
+
+struct Slot<V> {
+    generation: u32,
+    entry: Option<V>,
 }
 
-struct Graph {
-    nodes: Vec<Node>,
+/// A Vec of slots with a free list and generational IDs.
+struct Arena<V> {
+    slots: Vec<Slot<V>>,
+    free: Vec<usize>,
 }
 
-fn new_node(g: &mut Graph, value: i32) -> usize {
-    let n = Node {
-        value,
-        neighbors: vec![],
-    };
-    g.nodes.push(n);
+impl<V> Arena<V> {
+    fn new() -> Self {
+        Arena {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn id(slot: usize, generation: u32) -> usize {
+        ((generation as usize) << SLOT_BITS) | slot
+    }
+
+    fn insert(&mut self, value: V) -> usize {
+        match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot].entry = Some(value);
+                Self::id(slot, self.slots[slot].generation)
+            }
+            None => {
+                // generations start at 1, so 0 is never a valid ID
+                self.slots.push(Slot {
+                    generation: 1,
+                    entry: Some(value),
+                });
+                Self::id(self.slots.len() - 1, 1)
+            }
+        }
+    }
 
-    g.nodes.len() - 1
+    /// Returns the slot of a live ID, `None` for unknown and stale IDs.
+    fn slot(&self, id: usize) -> Option<usize> {
+        let slot = id & SLOT_MASK;
+        let entry = self.slots.get(slot)?;
+        (entry.generation as usize == id >> SLOT_BITS && entry.entry.is_some()).then_some(slot)
+    }
+
+    fn get(&self, id: usize) -> Option<&V> {
+        self.slots[self.slot(id)?].entry.as_ref()
+    }
+
+    fn get_mut(&mut self, id: usize) -> Option<&mut V> {
+        let slot = self.slot(id)?;
+        self.slots[slot].entry.as_mut()
+    }
+
+    fn remove(&mut self, id: usize) -> Option<V> {
+        let slot = self.slot(id)?;
+        let value = self.slots[slot].entry.take();
+        self.slots[slot].generation = self.slots[slot].generation.wrapping_add(1).max(1);
+        self.free.push(slot);
+        value
+    }
+
+    fn ids(&self) -> Vec<usize> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.entry.is_some())
+            .map(|(slot, s)| Self::id(slot, s.generation))
+            .collect()
+    }
 }
 
-fn new_graph() -> Graph {
-    Graph { nodes: vec![] }
+struct Node<T> {
+    /// the ID seen by the caller, the arena ID unless the node was created by `set_node`
+    id: NodeId,
+    value: T,
+    out_edges: Vec<EdgeId>,
+    in_edges: Vec<EdgeId>,
 }
 
-fn add_edge(g: &mut Graph, node1: usize, node2: usize) {
-    let n1 = &mut g.nodes[node1];
-    n1.neighbors.push(node2);
+struct Edge {
+    from: NodeId,
+    to: NodeId,
+    weight: i32,
 }
 
-fn get_neighbors(g: &Graph, n: usize) -> Vec<i32> {
-    let mut result = Vec::new();
-    let node = &g.nodes[n];
-    let cn = &node.neighbors;
-    for n in cn {
-        result.push(g.nodes[*n].value);
+pub struct Graph<T> {
+    nodes: Arena<Node<T>>,
+    edges: Arena<Edge>,
+    /// arena IDs of the nodes created by `set_node`
+    external: HashMap<NodeId, usize>,
+    /// edges to or from nodes that do not exist, by the missing node
+    dangling: HashMap<NodeId, Vec<EdgeId>>,
+}
+
+impl<T> Graph<T> {
+    /// The arena ID of a node.
+    fn key(&self, id: NodeId) -> Option<usize> {
+        match self.external.get(&id) {
+            Some(key) => Some(*key),
+            None => self.nodes.get(id).is_some_and(|n| n.id == id).then_some(id),
+        }
+    }
+
+    fn node(&self, id: NodeId) -> Option<&Node<T>> {
+        self.nodes.get(self.key(id)?)
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node<T>> {
+        let key = self.key(id)?;
+        self.nodes.get_mut(key)
+    }
+
+    fn insert_node(&mut self, id: Option<NodeId>, value: T) -> NodeId {
+        let mut node = Node {
+            id: 0,
+            value,
+            out_edges: Vec::new(),
+            in_edges: Vec::new(),
+        };
+        let mut key = self.nodes.insert(node);
+        if id.is_none() {
+            // another node may use the arena ID as its external ID, try the next generation
+            while self.external.contains_key(&key) {
+                node = self.nodes.remove(key).unwrap();
+                key = self.nodes.insert(node);
+            }
+        }
+        let id = id.unwrap_or(key);
+        if id != key {
+            self.external.insert(id, key);
+        }
+        self.nodes.get_mut(key).unwrap().id = id;
+        // link the edges that were waiting for this node, a self loop is listed twice
+        let mut waiting = self.dangling.remove(&id).unwrap_or_default();
+        waiting.sort_unstable();
+        waiting.dedup();
+        for edge_id in waiting {
+            let edge = self.edges.get(edge_id).unwrap();
+            let (from, to) = (edge.from, edge.to);
+            let node = self.nodes.get_mut(key).unwrap();
+            if from == id {
+                node.out_edges.push(edge_id);
+            }
+            if to == id {
+                node.in_edges.push(edge_id);
+            }
+        }
+        id
+    }
+
+    fn link(&mut self, edge_id: EdgeId, from: NodeId, to: NodeId) {
+        match self.node_mut(from) {
+            Some(node) => node.out_edges.push(edge_id),
+            None => self.dangling.entry(from).or_default().push(edge_id),
+        }
+        match self.node_mut(to) {
+            Some(node) => node.in_edges.push(edge_id),
+            None => self.dangling.entry(to).or_default().push(edge_id),
+        }
+    }
+
+    fn unlink(&mut self, edge_id: EdgeId, from: NodeId, to: NodeId) {
+        match self.node_mut(from) {
+            Some(node) => node.out_edges.retain(|e| *e != edge_id),
+            None => self.forget_dangling(edge_id, from),
+        }
+        match self.node_mut(to) {
+            Some(node) => node.in_edges.retain(|e| *e != edge_id),
+            None => self.forget_dangling(edge_id, to),
+        }
+    }
+
+    fn forget_dangling(&mut self, edge_id: EdgeId, missing: NodeId) {
+        if let Some(edges) = self.dangling.get_mut(&missing) {
+            edges.retain(|e| *e != edge_id);
+            if edges.is_empty() {
+                self.dangling.remove(&missing);
+            }
+        }
     }
-    result
 }
 
-fn get_value(g: &Graph, n: usize) -> i32 {
-    g.nodes[n].value
+impl<T> GraphView<T> for Graph<T> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        self.node(nodeid).map(|node| &node.value)
+    }
+
+    fn get_edge(&self, edgeid: EdgeId) -> Option<EdgeTriplet> {
+        self.edges
+            .get(edgeid)
+            .map(|e| EdgeTriplet(e.from, e.to, e.weight))
+    }
+
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        self.node(from).map(|node| node.out_edges.clone())
+    }
+
+    fn find_edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        self.node(to).map(|node| node.in_edges.clone())
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self
+            .nodes
+            .ids()
+            .into_iter()
+            .map(|key| self.nodes.get(key).unwrap().id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        let mut ids = self.edges.ids();
+        ids.sort_unstable();
+        ids
+    }
 }
 
-fn print_nodes(g: &Graph) {
-    for (n, _) in g.nodes.iter().enumerate() {
-        let v = get_value(g, n);
-        let cn = get_neighbors(g, n);
-        println!("v: {}, neighbors : {:?}", v, cn);
+impl<T> GraphCrud<T> for Graph<T> {
+    fn new() -> Self {
+        Graph {
+            nodes: Arena::new(),
+            edges: Arena::new(),
+            external: HashMap::new(),
+            dangling: HashMap::new(),
+        }
+    }
+
+    fn new_node(&mut self, value: T) -> NodeId {
+        self.insert_node(None, value)
+    }
+
+    fn set_node(&mut self, value: T, id: NodeId) -> NodeId {
+        match self.node_mut(id) {
+            Some(node) => {
+                node.value = value;
+                id
+            }
+            None => self.insert_node(Some(id), value),
+        }
+    }
+
+    fn del_node(&mut self, nodeid: NodeId) -> bool {
+        let Some(key) = self.key(nodeid) else {
+            return false;
+        };
+        let node = self.nodes.remove(key).unwrap();
+        self.external.remove(&nodeid);
+        for edge_id in node.out_edges.iter().chain(node.in_edges.iter()) {
+            if let Some(edge) = self.edges.remove(*edge_id) {
+                self.unlink(*edge_id, edge.from, edge.to);
+            }
+        }
+        true
+    }
+
+    fn set_node_val(&mut self, nodeid: NodeId, val: T) {
+        if let Some(node) = self.node_mut(nodeid) {
+            node.value = val;
+        }
+    }
+
+    fn new_edge(&mut self, from: NodeId, to: NodeId, weight: i32) -> EdgeId {
+        let edge_id = self.edges.insert(Edge { from, to, weight });
+        self.link(edge_id, from, to);
+        edge_id
+    }
+
+    fn del_edge(&mut self, edgeid: EdgeId) -> bool {
+        match self.edges.remove(edgeid) {
+            Some(edge) => {
+                self.unlink(edgeid, edge.from, edge.to);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_edge(&mut self, edgeid: EdgeId, edge_data: EdgeTriplet) {
+        let Some(edge) = self.edges.get_mut(edgeid) else {
+            return;
+        };
+        let (old_from, old_to) = (edge.from, edge.to);
+        edge.from = edge_data.0;
+        edge.to = edge_data.1;
+        edge.weight = edge_data.2;
+        self.unlink(edgeid, old_from, old_to);
+        self.link(edgeid, edge_data.0, edge_data.1);
     }
 }
 
-fn find_path(g: &Graph, start_node: usize, end_node: usize) -> bool {
-    let mut visited_nodes = HashSet::new();
-    let mut path = Vec::new();
+impl<T: Display> GraphDisplay<T> for Graph<T> {
+    fn to_string(&self) -> String {
+        let nodes: Vec<String> = self
+            .node_ids()
+            .iter()
+            .filter_map(|id| self.node_to_string(*id))
+            .collect();
+        let edges: Vec<String> = self
+            .edge_ids()
+            .iter()
+            .filter_map(|id| self.edge_to_string(*id))
+            .collect();
+        format!(
+            "{{ \"nodes\": [{}], \"edges\": [{}] }}",
+            nodes.join(", "),
+            edges.join(", ")
+        )
+    }
 
-    let found_path = find_directed_path(g, start_node, end_node, &mut visited_nodes, &mut path);
+    fn node_to_string(&self, node_id: NodeId) -> Option<String> {
+        let node = self.node(node_id)?;
+        Some(format!(
+            "{{ \"nodeid\": {}, \"value\": \"{}\" }}",
+            node_id, node.value
+        ))
+    }
 
-    println!("Result: found a path: {} path is {:?}", found_path, path);
-    found_path
+    fn edge_to_string(&self, edge_id: EdgeId) -> Option<String> {
+        let edge = self.edges.get(edge_id)?;
+        Some(format!(
+            "{{ \"edge_id\": {}, \"from\": {}, \"to\": {}, \"weight\": {} }}",
+            edge_id, edge.from, edge.to, edge.weight
+        ))
+    }
+
+    fn path_to_string(&self, path: &Path) -> Option<String> {
+        let edges: Option<Vec<String>> = path.iter().map(|e| self.edge_to_string(*e)).collect();
+        Some(format!("[{}]", edges?.join(", ")))
+    }
 }
 
-fn find_directed_path(
-    g: &Graph,
-    start_node: usize,
-    end_node: usize,
-    visited_nodes: &mut HashSet<usize>,
-    path: &mut Vec<usize>,
+fn find_directed_path<T>(
+    g: &Graph<T>,
+    start_node: NodeId,
+    end_node: NodeId,
+    visited_nodes: &mut HashSet<NodeId>,
 ) -> bool {
     if start_node == end_node {
         return true;
     }
-    if visited_nodes.contains(&start_node) {
+    if !visited_nodes.insert(start_node) {
         return false;
     }
-    visited_nodes.insert(start_node);
-    path.push(start_node);
-    let n1 = &g.nodes[start_node];
-    for n in n1.neighbors.iter() {
-        let result = find_directed_path(g, *n, end_node, visited_nodes, path);
-        if result == true {
-            return true;
-        } else {
-            path.pop();
+    let Some(node) = g.node(start_node) else {
+        return false;
+    };
+    node.out_edges.iter().any(|edge_id| {
+        let next = g.edges.get(*edge_id).unwrap().to;
+        find_directed_path(g, next, end_node, visited_nodes)
+    })
+}
+
+impl<T> GraphAlgo<T> for Graph<T> {
+    fn path_exists(&self, from: NodeId, to: NodeId) -> bool {
+        if self.node(to).is_none() {
+            return false;
+        }
+        let mut visited_nodes = HashSet::new();
+        find_directed_path(self, from, to, &mut visited_nodes)
+    }
+
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        dijkstra(self, from, to).map(|path| path.edges)
+    }
+
+    fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
+        all_simple_paths(self, from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::graph;
+    use crate::osmgraph::{OsmNode, parse_osm};
+
+    crate::conformance::conformance_tests!(Graph<i32>);
+
+    #[test]
+    fn test_slots_are_reused() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        assert!(g.del_node(a));
+        let c = g.new_node(3);
+        // same slot, new generation
+        assert_eq!(a & SLOT_MASK, c & SLOT_MASK);
+        assert_ne!(a, c);
+        assert_eq!(g.nodes.slots.len(), 2);
+        assert_eq!(g.node_ids(), vec![b, c]);
+    }
+
+    #[test]
+    fn test_stale_ids_are_detected() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        let ab = g.new_edge(a, b, 5);
+        assert!(g.del_node(a));
+        let c = g.new_node(3);
+        let cb = g.new_edge(c, b, 6);
+
+        assert_eq!(g.get_node_val(a), None);
+        assert_eq!(g.get_node_val(c), Some(&3));
+        assert!(g.get_edge(ab).is_none());
+        assert!(!g.del_edge(ab));
+        assert!(!g.del_node(a));
+        g.set_node_val(a, 100);
+        assert_eq!(g.get_node_val(c), Some(&3));
+        assert_eq!(g.find_edges_to(b), Some(vec![cb]));
+        assert_eq!(g.find_edges_from(a), None);
+    }
+
+    #[test]
+    fn test_set_node_keeps_the_id() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        assert_eq!(g.set_node(10, a), a);
+        assert_eq!(g.get_node_val(a), Some(&10));
+        assert_eq!(g.set_node(2, 8945281129), 8945281129);
+        assert_eq!(g.get_node_val(8945281129), Some(&2));
+        // the arena ID behind the node is not a second name for it
+        let key = g.external[&8945281129];
+        assert_eq!(g.get_node_val(key), None);
+        assert_eq!(g.node_ids(), vec![a, 8945281129]);
+        assert!(g.del_node(8945281129));
+        assert_eq!(g.node_ids(), vec![a]);
+    }
+
+    #[test]
+    fn test_new_node_skips_external_ids() {
+        let mut g: Graph<i32> = Graph::new();
+        // the arena ID of the second slot, the node itself gets the first slot
+        let next = Arena::<Node<i32>>::id(1, 1);
+        assert_eq!(g.set_node(1, next), next);
+        let b = g.new_node(2);
+        assert_eq!(b, Arena::<Node<i32>>::id(1, 2));
+        assert_eq!(g.get_node_val(next), Some(&1));
+        assert_eq!(g.get_node_val(b), Some(&2));
+    }
+
+    #[test]
+    fn test_edges_before_nodes() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let ab = g.new_edge(a, 100, 1);
+        let bb = g.new_edge(100, 100, 2);
+        let bc = g.new_edge(100, 200, 3);
+        assert_eq!(g.find_edges_from(a), Some(vec![ab]));
+        assert_eq!(g.find_edges_to(100), None);
+
+        assert_eq!(g.set_node(2, 100), 100);
+        assert_eq!(g.find_edges_to(100), Some(vec![ab, bb]));
+        assert_eq!(g.find_edges_from(100), Some(vec![bb, bc]));
+        assert!(g.path_exists(a, 100));
+        // deleting node 100 also deletes 100 -> 200, whose end at node 200 was still waiting
+        assert!(g.del_node(100));
+        assert_eq!(g.get_edge(bc), None);
+        g.set_node(3, 200);
+        assert_eq!(g.find_edges_to(200), Some(vec![]));
+        assert!(g.dangling.is_empty());
+    }
+
+    #[test]
+    fn test_osm_import() {
+        let mut arena: Graph<OsmNode> = Graph::new();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm(&mut reader, &mut arena);
+        let mut mem: graph::Graph<OsmNode> = graph::Graph::new();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm(&mut reader, &mut mem);
+
+        assert_eq!(arena.node_ids(), mem.node_ids());
+        for id in mem.node_ids() {
+            let mut from: Vec<NodeId> = arena
+                .find_edges_from(id)
+                .unwrap()
+                .into_iter()
+                .map(|e| arena.get_edge(e).unwrap().1)
+                .collect();
+            let mut expected: Vec<NodeId> = mem
+                .find_edges_from(id)
+                .unwrap()
+                .into_iter()
+                .map(|e| mem.get_edge(e).unwrap().1)
+                .collect();
+            from.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(from, expected);
         }
+        assert_eq!(
+            arena.shortest_path(8945281129, 12307697010).is_some(),
+            mem.path_exists(8945281129, 12307697010)
+        );
     }
-    false
 }
@@ -0,0 +1,326 @@
+// -----------------------------------------------------------------------------------------------
+// Module isochrone
+//
+// The area that can be reached from a start node within a budget, for service area analysis.
+// The cost of an edge comes from a function, so the budget can be a distance in meters
+// (`distance_cost`) or anything else that can be derived from an edge, e.g. the travel time in
+// seconds from the speed tags (`traveltime::Router::isochrone`). Besides the reachable nodes the
+// result has the convex hull of their positions, and it can be exported as GeoJSON:
+//
+//   let area = isochrone(&osmgraph, start, 500.0, distance_cost).unwrap();
+//   fs::write("area.geojson", area.to_geojson())?;
+//
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphView, NodeId};
+use crate::osmgraph::OsmNode;
+
+/// A node within the budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReachedNode {
+    pub id: NodeId,
+    pub lat: f64,
+    pub lon: f64,
+    /// the cost of the cheapest way from the start
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Isochrone {
+    pub start: NodeId,
+    pub budget: f64,
+    /// sorted by node ID
+    pub nodes: Vec<ReachedNode>,
+    /// the convex hull of the reached nodes as (lon, lat), counter clockwise and not closed
+    pub hull: Vec<(f64, f64)>,
+}
+
+/// A cost for the priority queue, the costs are never NaN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cost(f64);
+
+impl Eq for Cost {}
+
+impl PartialOrd for Cost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// The length of an edge in meters, the OSM import stores the distance in centimeters.
+pub fn distance_cost(_edge_id: EdgeId, edge: &EdgeTriplet) -> Option<f64> {
+    Some(edge.2 as f64 / 100.0)
+}
+
+/// All nodes that can be reached from `start` with a total cost of at most `budget`.
+///
+/// # Arguments
+///
+/// * `g` - the OSM graph
+/// * `start` - the start node
+/// * `budget` - the maximum cost, in the unit of `cost`, a finite number
+/// * `cost` - the cost of an edge, must not be negative. `None` means the edge cannot be used.
+///
+/// # Returns
+///
+/// The reached nodes and their hull, or `None` if `start` does not exist or the budget is infinite
+/// or NaN, which GeoJSON could not represent.
+pub fn isochrone<G, C>(g: &G, start: NodeId, budget: f64, cost: C) -> Option<Isochrone>
+where
+    G: GraphView<OsmNode> + ?Sized,
+    C: Fn(EdgeId, &EdgeTriplet) -> Option<f64>,
+{
+    if !budget.is_finite() {
+        return None;
+    }
+    g.get_node_val(start)?;
+    let mut best: HashMap<NodeId, f64> = HashMap::from([(start, 0.0)]);
+    let mut heap = BinaryHeap::from([Reverse((Cost(0.0), start))]);
+    while let Some(Reverse((Cost(c), node))) = heap.pop() {
+        if c > best[&node] {
+            continue;
+        }
+        for edge_id in g.find_edges_from(node).unwrap_or_default() {
+            let Some(edge) = g.get_edge(edge_id) else {
+                continue;
+            };
+            if g.get_node_val(edge.1).is_none() {
+                continue;
+            }
+            let Some(edge_cost) = cost(edge_id, &edge) else {
+                continue;
+            };
+            let next = c + edge_cost;
+            if next <= budget && best.get(&edge.1).is_none_or(|old| next < *old) {
+                best.insert(edge.1, next);
+                heap.push(Reverse((Cost(next), edge.1)));
+            }
+        }
+    }
+
+    let mut nodes: Vec<ReachedNode> = best
+        .into_iter()
+        .map(|(id, cost)| {
+            let node = g.get_node_val(id).unwrap();
+            ReachedNode {
+                id,
+                lat: node.lat,
+                lon: node.lon,
+                cost,
+            }
+        })
+        .collect();
+    nodes.sort_unstable_by_key(|node| node.id);
+    let points: Vec<(f64, f64)> = nodes.iter().map(|node| (node.lon, node.lat)).collect();
+    Some(Isochrone {
+        start,
+        budget,
+        hull: convex_hull(&points),
+        nodes,
+    })
+}
+
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+/// The convex hull of a set of points (Andrew's monotone chain).
+///
+/// # Returns
+///
+/// The corners counter clockwise starting with the lowest x, without collinear points. Less than
+/// three points if all points lie on a line.
+pub fn convex_hull(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut points = points.to_vec();
+    points.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(points.len() + 1);
+    // lower hull left to right, then upper hull right to left
+    for pass in [points.clone(), points.iter().rev().copied().collect()] {
+        let base = hull.len();
+        for p in pass {
+            while hull.len() >= base + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // the last point is the first one of the other half
+        hull.pop();
+    }
+    if hull.len() < 3 {
+        // all points on a line: the two ends
+        hull.truncate(2);
+    }
+    hull
+}
+
+fn position(p: &(f64, f64)) -> String {
+    format!("[{}, {}]", p.0, p.1)
+}
+
+impl Isochrone {
+    /// The IDs of the reached nodes.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        self.nodes.iter().map(|node| node.id).collect()
+    }
+
+    /// A GeoJSON FeatureCollection with the hull (a Polygon, or a LineString or Point if the
+    /// nodes do not span an area) and the reached nodes as Points with their cost.
+    pub fn to_geojson(&self) -> String {
+        let geometry = match self.hull.len() {
+            0 => "null".to_string(),
+            1 => format!(
+                "{{\"type\": \"Point\", \"coordinates\": {}}}",
+                position(&self.hull[0])
+            ),
+            2 => format!(
+                "{{\"type\": \"LineString\", \"coordinates\": [{}, {}]}}",
+                position(&self.hull[0]),
+                position(&self.hull[1])
+            ),
+            _ => {
+                let ring: Vec<String> = self
+                    .hull
+                    .iter()
+                    .chain(self.hull.first())
+                    .map(position)
+                    .collect();
+                format!(
+                    "{{\"type\": \"Polygon\", \"coordinates\": [[{}]]}}",
+                    ring.join(", ")
+                )
+            }
+        };
+        let mut features = vec![format!(
+            "{{\"type\": \"Feature\", \"geometry\": {}, \"properties\": {{\"start\": {}, \"budget\": {}, \"nodes\": {}}}}}",
+            geometry,
+            self.start,
+            self.budget,
+            self.nodes.len()
+        )];
+        for node in self.nodes.iter() {
+            features.push(format!(
+                "{{\"type\": \"Feature\", \"geometry\": {{\"type\": \"Point\", \"coordinates\": {}}}, \"properties\": {{\"id\": {}, \"cost\": {}}}}}",
+                position(&(node.lon, node.lat)),
+                node.id,
+                node.cost
+            ));
+        }
+        format!(
+            "{{\"type\": \"FeatureCollection\", \"features\": [{}]}}",
+            features.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphCrud;
+    use crate::osmgraph::{create_osm_graph, parse_osm};
+    use crate::shortest_path::dijkstra;
+
+    fn node(id: NodeId, lat: f64, lon: f64) -> OsmNode {
+        OsmNode {
+            id,
+            lat,
+            lon,
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn test_convex_hull() {
+        let square = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+        let mut points = square.to_vec();
+        points.extend([(1.0, 1.0), (1.0, 0.0), (0.5, 1.5), (2.0, 2.0)]);
+        assert_eq!(convex_hull(&points), square.to_vec());
+        assert_eq!(
+            convex_hull(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]),
+            vec![(0.0, 0.0), (2.0, 2.0)]
+        );
+        assert_eq!(convex_hull(&[(1.0, 1.0), (1.0, 1.0)]), vec![(1.0, 1.0)]);
+        assert!(convex_hull(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_budget() {
+        // 1 -> 2 -> 3 -> 4 with 100 m each, 1 -> 5 with 250 m and a closed road 2 -> 6
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        for (id, lat, lon) in [
+            (1, 0.0, 0.0),
+            (2, 0.0, 1.0),
+            (3, 0.0, 2.0),
+            (4, 0.0, 3.0),
+            (5, 1.0, 0.0),
+            (6, 1.0, 1.0),
+        ] {
+            g.set_node(node(id, lat, lon), id);
+        }
+        g.new_edge(1, 2, 10000);
+        g.new_edge(2, 3, 10000);
+        g.new_edge(3, 4, 10000);
+        g.new_edge(1, 5, 25000);
+        let closed = g.new_edge(2, 6, 100);
+        let cost = |edge_id: EdgeId, edge: &EdgeTriplet| {
+            (edge_id != closed).then(|| distance_cost(edge_id, edge))?
+        };
+
+        let area = isochrone(&g, 1, 250.0, cost).unwrap();
+        assert_eq!(area.node_ids(), vec![1, 2, 3, 5]);
+        assert_eq!(area.nodes[2].cost, 200.0);
+        assert_eq!(area.hull, vec![(0.0, 0.0), (2.0, 0.0), (0.0, 1.0)]);
+
+        let area = isochrone(&g, 1, 0.0, distance_cost).unwrap();
+        assert_eq!(area.node_ids(), vec![1]);
+        assert!(area.to_geojson().contains("\"type\": \"Point\""));
+        assert_eq!(isochrone(&g, 7, 100.0, distance_cost), None);
+        assert_eq!(isochrone(&g, 1, f64::INFINITY, distance_cost), None);
+        assert_eq!(isochrone(&g, 1, f64::NAN, distance_cost), None);
+    }
+
+    #[test]
+    fn test_osm() {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm(&mut reader, &mut g);
+        let start = 8945281129;
+        let area = isochrone(&g, start, 100.0, distance_cost).unwrap();
+        assert!(area.nodes.len() > 3);
+        // the same nodes that Dijkstra finds within 100 m
+        for id in g.node_ids() {
+            let within = dijkstra(&g, start, id).is_some_and(|p| p.weight <= 10000);
+            assert_eq!(area.node_ids().contains(&id), within, "node {}", id);
+        }
+
+        let geojson = area.to_geojson();
+        assert!(geojson.starts_with("{\"type\": \"FeatureCollection\""));
+        assert!(geojson.contains("\"type\": \"Polygon\""));
+        assert_eq!(
+            geojson.matches("\"type\": \"Feature\"").count(),
+            area.nodes.len() + 1
+        );
+        // the ring is closed
+        let first = position(&area.hull[0]);
+        assert_eq!(geojson.matches(first.as_str()).count(), 3);
+    }
+}
@@ -0,0 +1,475 @@
+// -----------------------------------------------------------------------------------------------
+// Module journal
+//
+// An optional change journal for interactive editing. JournaledGraph wraps a graph::Graph and
+// implements GraphCrud itself: every operation is recorded so that it can be undone and redone,
+// and observers are told about every node and edge that is added, removed or changed (also by
+// undo and redo), e.g. to invalidate cached routes.
+//
+//   let mut g = JournaledGraph::new(osmgraph);
+//   g.subscribe(|event| cache.invalidate(event));
+//   g.del_node(id);
+//   g.undo();
+//
+// Undo restores deleted nodes and edges with their old IDs.
+//
+
+use crate::graph::Graph;
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, GraphView, NodeId, Path};
+use crate::shortest_path::{all_simple_paths, dijkstra};
+
+/// One recorded operation with everything needed to revert it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    NodeAdded {
+        id: NodeId,
+        value: T,
+    },
+    /// Deleting a node deletes its edges as well, they are restored together with the node.
+    NodeRemoved {
+        id: NodeId,
+        value: T,
+        edges: Vec<(EdgeId, EdgeTriplet)>,
+    },
+    NodeChanged {
+        id: NodeId,
+        old: T,
+        new: T,
+    },
+    EdgeAdded {
+        id: EdgeId,
+        edge: EdgeTriplet,
+    },
+    EdgeRemoved {
+        id: EdgeId,
+        edge: EdgeTriplet,
+    },
+    EdgeChanged {
+        id: EdgeId,
+        old: EdgeTriplet,
+        new: EdgeTriplet,
+    },
+}
+
+/// What observers are told about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphEvent {
+    NodeAdded(NodeId),
+    NodeRemoved(NodeId),
+    NodeChanged(NodeId),
+    EdgeAdded(EdgeId),
+    EdgeRemoved(EdgeId),
+    EdgeChanged(EdgeId),
+}
+
+type Observer = Box<dyn FnMut(&GraphEvent)>;
+
+pub struct JournaledGraph<T> {
+    graph: Graph<T>,
+    undo_stack: Vec<Change<T>>,
+    redo_stack: Vec<Change<T>>,
+    observers: Vec<(usize, Observer)>,
+    last_observer: usize,
+}
+
+impl<T: Clone> JournaledGraph<T> {
+    /// Starts recording the changes of `graph`, the journal starts empty.
+    pub fn new(graph: Graph<T>) -> Self {
+        JournaledGraph {
+            graph,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            observers: Vec::new(),
+            last_observer: 0,
+        }
+    }
+
+    /// The wrapped graph, e.g. to run algorithms that need a `Graph<T>`.
+    pub fn graph(&self) -> &Graph<T> {
+        &self.graph
+    }
+
+    /// Stops recording and returns the graph.
+    pub fn into_inner(self) -> Graph<T> {
+        self.graph
+    }
+
+    /// The recorded changes that can be undone, the most recent one last.
+    pub fn history(&self) -> &[Change<T>] {
+        &self.undo_stack
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Forgets all recorded changes, the graph stays as it is.
+    pub fn clear_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    /// Registers an observer that is called for every event.
+    ///
+    /// # Returns
+    ///
+    /// An ID for [`JournaledGraph::unsubscribe`].
+    pub fn subscribe(&mut self, observer: impl FnMut(&GraphEvent) + 'static) -> usize {
+        self.last_observer += 1;
+        self.observers
+            .push((self.last_observer, Box::new(observer)));
+        self.last_observer
+    }
+
+    /// Removes an observer, returns false if there is none with this ID.
+    pub fn unsubscribe(&mut self, observer_id: usize) -> bool {
+        let count = self.observers.len();
+        self.observers.retain(|(id, _)| *id != observer_id);
+        self.observers.len() != count
+    }
+
+    /// Reverts the most recent change.
+    ///
+    /// # Returns
+    ///
+    /// `false` if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(change) = self.undo_stack.pop() else {
+            return false;
+        };
+        self.apply(&change, true);
+        self.redo_stack.push(change);
+        true
+    }
+
+    /// Repeats the most recently undone change.
+    ///
+    /// # Returns
+    ///
+    /// `false` if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(change) = self.redo_stack.pop() else {
+            return false;
+        };
+        self.apply(&change, false);
+        self.undo_stack.push(change);
+        true
+    }
+
+    /// Records a change that has already been made to the graph.
+    fn record(&mut self, change: Change<T>) {
+        self.notify(&change, false);
+        self.undo_stack.push(change);
+        self.redo_stack.clear();
+    }
+
+    /// Makes (or with `revert` reverts) a change on the graph and tells the observers.
+    fn apply(&mut self, change: &Change<T>, revert: bool) {
+        let g = &mut self.graph;
+        match (change, revert) {
+            (Change::NodeAdded { id, value }, false) => {
+                g.set_node(value.clone(), *id);
+            }
+            (Change::NodeAdded { id, .. }, true) => {
+                g.del_node(*id);
+            }
+            (Change::NodeRemoved { id, .. }, false) => {
+                g.del_node(*id);
+            }
+            (Change::NodeRemoved { id, value, edges }, true) => {
+                g.set_node(value.clone(), *id);
+                for (edge_id, edge) in edges {
+                    g.restore_edge(*edge_id, *edge);
+                }
+            }
+            (Change::NodeChanged { id, old, new }, _) => {
+                g.set_node_val(*id, if revert { old } else { new }.clone());
+            }
+            (Change::EdgeAdded { id, edge }, false) | (Change::EdgeRemoved { id, edge }, true) => {
+                g.restore_edge(*id, *edge);
+            }
+            (Change::EdgeAdded { id, .. }, true) | (Change::EdgeRemoved { id, .. }, false) => {
+                g.del_edge(*id);
+            }
+            (Change::EdgeChanged { id, old, new }, _) => {
+                g.set_edge(*id, if revert { *old } else { *new });
+            }
+        }
+        self.notify(change, revert);
+    }
+
+    fn notify(&mut self, change: &Change<T>, revert: bool) {
+        let events = match (change, revert) {
+            (Change::NodeAdded { id, .. }, false) => vec![GraphEvent::NodeAdded(*id)],
+            (Change::NodeAdded { id, .. }, true) => vec![GraphEvent::NodeRemoved(*id)],
+            (Change::NodeRemoved { id, edges, .. }, false) => edges
+                .iter()
+                .map(|(edge_id, _)| GraphEvent::EdgeRemoved(*edge_id))
+                .chain([GraphEvent::NodeRemoved(*id)])
+                .collect(),
+            (Change::NodeRemoved { id, edges, .. }, true) => [GraphEvent::NodeAdded(*id)]
+                .into_iter()
+                .chain(
+                    edges
+                        .iter()
+                        .map(|(edge_id, _)| GraphEvent::EdgeAdded(*edge_id)),
+                )
+                .collect(),
+            (Change::NodeChanged { id, .. }, _) => vec![GraphEvent::NodeChanged(*id)],
+            (Change::EdgeAdded { id, .. }, false) | (Change::EdgeRemoved { id, .. }, true) => {
+                vec![GraphEvent::EdgeAdded(*id)]
+            }
+            (Change::EdgeAdded { id, .. }, true) | (Change::EdgeRemoved { id, .. }, false) => {
+                vec![GraphEvent::EdgeRemoved(*id)]
+            }
+            (Change::EdgeChanged { id, .. }, _) => vec![GraphEvent::EdgeChanged(*id)],
+        };
+        for event in events.iter() {
+            for (_, observer) in self.observers.iter_mut() {
+                observer(event);
+            }
+        }
+    }
+}
+
+impl<T: Clone> GraphView<T> for JournaledGraph<T> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        self.graph.get_node_val(nodeid)
+    }
+
+    fn get_edge(&self, edge: EdgeId) -> Option<EdgeTriplet> {
+        self.graph.get_edge(edge)
+    }
+
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        self.graph.find_edges_from(from)
+    }
+
+    fn find_edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        self.graph.find_edges_to(to)
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        self.graph.node_ids()
+    }
+
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        self.graph.edge_ids()
+    }
+}
+
+impl<T: Clone> GraphCrud<T> for JournaledGraph<T> {
+    fn new() -> Self {
+        JournaledGraph::new(Graph::new())
+    }
+
+    fn new_node(&mut self, val: T) -> NodeId {
+        let id = self.graph.new_node(val.clone());
+        self.record(Change::NodeAdded { id, value: val });
+        id
+    }
+
+    fn set_node(&mut self, val: T, id: NodeId) -> NodeId {
+        let change = match self.graph.get_node_val(id).cloned() {
+            Some(old) => Change::NodeChanged {
+                id,
+                old,
+                new: val.clone(),
+            },
+            None => Change::NodeAdded {
+                id,
+                value: val.clone(),
+            },
+        };
+        self.graph.set_node(val, id);
+        self.record(change);
+        id
+    }
+
+    fn del_node(&mut self, nodeid: NodeId) -> bool {
+        let Some(value) = self.graph.get_node_val(nodeid).cloned() else {
+            return false;
+        };
+        let mut edge_ids = self.graph.find_edges_from(nodeid).unwrap_or_default();
+        edge_ids.extend(self.graph.find_edges_to(nodeid).unwrap_or_default());
+        edge_ids.sort_unstable();
+        edge_ids.dedup();
+        let edges = edge_ids
+            .into_iter()
+            .filter_map(|edge_id| Some((edge_id, self.graph.get_edge(edge_id)?)))
+            .collect();
+        self.graph.del_node(nodeid);
+        self.record(Change::NodeRemoved {
+            id: nodeid,
+            value,
+            edges,
+        });
+        true
+    }
+
+    fn set_node_val(&mut self, nodeid: NodeId, val: T) {
+        let Some(old) = self.graph.get_node_val(nodeid).cloned() else {
+            return;
+        };
+        self.graph.set_node_val(nodeid, val.clone());
+        self.record(Change::NodeChanged {
+            id: nodeid,
+            old,
+            new: val,
+        });
+    }
+
+    fn new_edge(&mut self, from: NodeId, to: NodeId, weight: i32) -> EdgeId {
+        let id = self.graph.new_edge(from, to, weight);
+        self.record(Change::EdgeAdded {
+            id,
+            edge: EdgeTriplet(from, to, weight),
+        });
+        id
+    }
+
+    fn del_edge(&mut self, edge: EdgeId) -> bool {
+        let Some(old) = self.graph.get_edge(edge) else {
+            return false;
+        };
+        self.graph.del_edge(edge);
+        self.record(Change::EdgeRemoved {
+            id: edge,
+            edge: old,
+        });
+        true
+    }
+
+    fn set_edge(&mut self, edge: EdgeId, edge_data: EdgeTriplet) {
+        let Some(old) = self.graph.get_edge(edge) else {
+            return;
+        };
+        self.graph.set_edge(edge, edge_data);
+        self.record(Change::EdgeChanged {
+            id: edge,
+            old,
+            new: edge_data,
+        });
+    }
+}
+
+impl<T: Clone> GraphAlgo<T> for JournaledGraph<T> {
+    fn path_exists(&self, from: NodeId, to: NodeId) -> bool {
+        self.graph.path_exists(from, to)
+    }
+
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        dijkstra(self, from, to).map(|path| path.edges)
+    }
+
+    fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
+        all_simple_paths(self, from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::conformance::sample_graph;
+
+    crate::conformance::conformance_tests!(JournaledGraph<i32>);
+
+    type Snapshot = (Vec<(NodeId, i32)>, Vec<(EdgeId, EdgeTriplet)>);
+
+    fn snapshot(g: &JournaledGraph<i32>) -> Snapshot {
+        let nodes = g
+            .node_ids()
+            .into_iter()
+            .map(|id| (id, *g.get_node_val(id).unwrap()))
+            .collect();
+        let edges = g
+            .edge_ids()
+            .into_iter()
+            .map(|id| (id, g.get_edge(id).unwrap()))
+            .collect();
+        (nodes, edges)
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let (graph, n, e) = sample_graph::<Graph<i32>>();
+        let mut g = JournaledGraph::new(graph);
+        let before = snapshot(&g);
+        assert!(!g.can_undo());
+
+        g.set_node_val(n[0], 10);
+        g.set_edge(e[0], EdgeTriplet(n[0], n[5], 2));
+        assert!(g.del_node(n[2]));
+        let added = g.new_node(7);
+        g.new_edge(added, n[0], 3);
+        assert!(g.del_edge(e[6]));
+        assert_eq!(g.history().len(), 6);
+        let after = snapshot(&g);
+
+        while g.undo() {}
+        assert_eq!(snapshot(&g), before);
+        // the deleted node comes back with its old edge IDs
+        assert!(g.shortest_path(n[0], n[5]).is_some());
+        assert_eq!(g.find_edges_to(n[0]), Some(vec![e[5]]));
+
+        while g.redo() {}
+        assert_eq!(snapshot(&g), after);
+
+        // a new change drops what could be redone
+        g.undo();
+        assert!(g.can_redo());
+        g.new_node(8);
+        assert!(!g.can_redo());
+    }
+
+    #[test]
+    fn test_observers() {
+        let (graph, n, e) = sample_graph::<Graph<i32>>();
+        let mut g = JournaledGraph::new(graph);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&events);
+        let observer = g.subscribe(move |event| sink.borrow_mut().push(*event));
+
+        g.set_node_val(n[5], 60);
+        g.del_node(n[0]);
+        g.undo();
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                GraphEvent::NodeChanged(n[5]),
+                GraphEvent::EdgeRemoved(e[0]),
+                GraphEvent::EdgeRemoved(e[5]),
+                GraphEvent::EdgeRemoved(e[6]),
+                GraphEvent::NodeRemoved(n[0]),
+                GraphEvent::NodeAdded(n[0]),
+                GraphEvent::EdgeAdded(e[0]),
+                GraphEvent::EdgeAdded(e[5]),
+                GraphEvent::EdgeAdded(e[6]),
+            ]
+        );
+
+        assert!(g.unsubscribe(observer));
+        assert!(!g.unsubscribe(observer));
+        g.del_edge(e[0]);
+        assert_eq!(events.borrow().len(), 9);
+    }
+
+    #[test]
+    fn test_failed_operations_are_not_recorded() {
+        let mut g: JournaledGraph<i32> = JournaledGraph::new(Graph::new());
+        assert!(!g.del_node(1));
+        assert!(!g.del_edge(1));
+        g.set_node_val(1, 5);
+        g.set_edge(1, EdgeTriplet(1, 2, 3));
+        assert!(!g.can_undo());
+        assert!(!g.undo());
+        assert!(!g.redo());
+    }
+}
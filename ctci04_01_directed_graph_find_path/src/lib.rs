@@ -0,0 +1,32 @@
+pub mod apsp;
+pub mod bellman_ford;
+pub mod centrality;
+#[cfg(test)]
+mod conformance;
+pub mod contraction;
+pub mod csrgraph;
+#[cfg(test)]
+mod differential;
+pub mod diskgraph;
+pub mod generators;
+pub mod gps_utils;
+pub mod graph;
+pub mod graphtraits;
+pub mod idgraph;
+pub mod isochrone;
+pub mod journal;
+pub mod mapmatch;
+pub mod maxflow;
+pub mod osmgraph;
+pub mod osmwriter;
+pub mod rcgraph;
+pub mod report;
+pub mod scc;
+pub mod sharedgraph;
+pub mod shortest_path;
+pub mod snapshot;
+pub mod spanning_tree;
+pub mod subgraph;
+pub mod toposort;
+pub mod traveltime;
+pub mod turns;
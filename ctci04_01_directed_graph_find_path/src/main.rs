@@ -1,40 +1,189 @@
-use std::{fs::File, io::BufReader};
+use std::{env, fs, fs::File, io::BufReader, process, time::Instant};
 
-use graphtraits::GraphCrud;
-use graphtraits::GraphDisplay;
-use osmgraph::{create_osm_graph, parse_osm};
-use rcgraph::{Graph, add_edge, find_path, new_graph, new_node, print_nodes};
+use ctci04_01_directed_graph_find_path::graph;
+use ctci04_01_directed_graph_find_path::graphtraits::{
+    GraphAlgo, GraphCrud, GraphDisplay, GraphView,
+};
+use ctci04_01_directed_graph_find_path::osmgraph::{
+    OsmNode, OsmTags, create_osm_graph, parse_osm, parse_osm_with_tags,
+};
+use ctci04_01_directed_graph_find_path::osmwriter::save_osm;
+use ctci04_01_directed_graph_find_path::rcgraph::Graph;
+use ctci04_01_directed_graph_find_path::report::OsmReport;
+use ctci04_01_directed_graph_find_path::snapshot::{MAGIC, read_snapshot, save_snapshot};
+use ctci04_01_directed_graph_find_path::traveltime::{Metric, Router};
 use xml::EventReader;
 
-pub mod gps_utils;
-pub mod graph;
-pub mod graphtraits;
-pub mod idgraph;
-pub mod osmgraph;
-pub mod rcgraph;
-
-fn main() -> std::io::Result<()> {
-    let mut g: Graph = new_graph();
-    let n1 = new_node(&mut g, 1);
-    let n2 = new_node(&mut g, 2);
-    let n3 = new_node(&mut g, 3);
-    let n4 = new_node(&mut g, 4);
-    let n5 = new_node(&mut g, 5);
-    let n6 = new_node(&mut g, 6);
-
-    add_edge(&mut g, n1.clone(), n2.clone(), 1);
-    add_edge(&mut g, n2.clone(), n3.clone(), 2);
-    add_edge(&mut g, n3.clone(), n4.clone(), 3);
-    add_edge(&mut g, n3.clone(), n5.clone(), 4);
-    add_edge(&mut g, n4.clone(), n6.clone(), 5);
-    add_edge(&mut g, n3.clone(), n1.clone(), 6);
-    add_edge(&mut g, n1.clone(), n5.clone(), 7);
-    add_edge(&mut g, n2.clone(), n5.clone(), 8);
-    add_edge(&mut g, n5.clone(), n2.clone(), 9);
-    add_edge(&mut g, n6.clone(), n4.clone(), 10);
-    print_nodes(&g);
-
-    let result = find_path(&g, n1, n6);
+const USAGE: &str = "usage:
+  ctci04_01_directed_graph_find_path                           run the demo
+  ctci04_01_directed_graph_find_path snapshot <in.osm> <out>   write a binary snapshot
+  ctci04_01_directed_graph_find_path export <file> <out.osm>   write OSM XML, e.g. of a snapshot
+  ctci04_01_directed_graph_find_path route <file> <from> <to> [shortest|fastest]
+                                                               route, <file> is an .osm file or
+                                                               a snapshot
+  ctci04_01_directed_graph_find_path isochrone <file> <start> <budget> [shortest|fastest]
+                                                               reachable area as GeoJSON, the
+                                                               budget is in meters or seconds
+  ctci04_01_directed_graph_find_path stats <file> [text|json]  statistics and problems of the data";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let result = match args.as_slice() {
+        [] => demo(),
+        ["snapshot", osm, out] => write_snapshot(osm, out),
+        ["export", file, out] => export(file, out),
+        ["route", file, from, to, metric @ ..] if metric.len() <= 1 => {
+            match (from.parse(), to.parse(), parse_metric(metric)) {
+                (Ok(from), Ok(to), Some(metric)) => route(file, from, to, metric),
+                (_, _, None) => Err("the metric must be shortest or fastest".into()),
+                _ => Err("node IDs must be numbers".into()),
+            }
+        }
+        ["isochrone", file, start, budget, metric @ ..] if metric.len() <= 1 => {
+            match (start.parse(), budget.parse(), parse_metric(metric)) {
+                (Ok(start), Ok(budget), Some(metric)) if f64::is_finite(budget) => {
+                    print_isochrone(file, start, budget, metric)
+                }
+                (_, _, None) => Err("the metric must be shortest or fastest".into()),
+                _ => Err("the start must be a node ID and the budget a finite number".into()),
+            }
+        }
+        ["stats", file, format @ ..] if format.len() <= 1 => match format.first() {
+            None | Some(&"text") => print_stats(file, false),
+            Some(&"json") => print_stats(file, true),
+            Some(_) => Err("the format must be text or json".into()),
+        },
+        _ => Err(USAGE.into()),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// The optional metric argument, shortest if it is missing.
+fn parse_metric(metric: &[&str]) -> Option<Metric> {
+    match metric.first() {
+        None | Some(&"shortest") => Some(Metric::Shortest),
+        Some(&"fastest") => Some(Metric::Fastest),
+        Some(_) => None,
+    }
+}
+
+/// Loads an OSM graph from a snapshot or, if the file is no snapshot, from OSM XML.
+fn load_graph(path: &str) -> Result<(graph::Graph<OsmNode>, OsmTags), Box<dyn std::error::Error>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(MAGIC) {
+        return Ok(read_snapshot(&bytes)?);
+    }
+    drop(bytes);
+    let mut reader = EventReader::new(BufReader::new(File::open(path)?));
+    let mut g = create_osm_graph();
+    let mut tags = OsmTags::default();
+    parse_osm_with_tags(&mut reader, &mut g, &mut tags);
+    Ok((g, tags))
+}
+
+fn write_snapshot(osm: &str, out: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let (g, tags) = load_graph(osm)?;
+    println!(
+        "{}: {} nodes, {} edges in {:?}",
+        osm,
+        g.node_ids().len(),
+        g.edge_ids().len(),
+        start.elapsed()
+    );
+    save_snapshot(out, &g, &tags)?;
+    println!("wrote {}", out);
+    Ok(())
+}
+
+fn export(file: &str, out: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (g, tags) = load_graph(file)?;
+    save_osm(out, &g, &tags)?;
+    println!("wrote {}", out);
+    Ok(())
+}
+
+fn route(
+    file: &str,
+    from: usize,
+    to: usize,
+    metric: Metric,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let (g, tags) = load_graph(file)?;
+    let router = Router::new(g, tags);
+    println!("loaded {} in {:?}", file, start.elapsed());
+    match router.route(from, to, metric) {
+        Some(path) => {
+            match metric {
+                Metric::Shortest => println!("distance: {} m", path.weight as f64 / 100.0),
+                Metric::Fastest => println!("travel time: {} s", path.weight as f64 / 1000.0),
+            }
+            for edge_id in path.edges {
+                let edge = router.graph().get_edge(edge_id).unwrap();
+                println!(
+                    "edge_id: {} from: {}, to: {}, weight: {}",
+                    edge_id, edge.0, edge.1, edge.2
+                );
+            }
+        }
+        None => println!("no path from {} to {}", from, to),
+    }
+    Ok(())
+}
+
+fn print_isochrone(
+    file: &str,
+    start: usize,
+    budget: f64,
+    metric: Metric,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (g, tags) = load_graph(file)?;
+    let router = Router::new(g, tags);
+    let area = router
+        .isochrone(start, budget, metric)
+        .ok_or("unknown start node")?;
+    println!("{}", area.to_geojson());
+    Ok(())
+}
+
+fn print_stats(file: &str, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (g, tags) = load_graph(file)?;
+    let report = OsmReport::new(&g, &tags);
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        println!("{}", report.to_text());
+    }
+    Ok(())
+}
+
+fn demo() -> Result<(), Box<dyn std::error::Error>> {
+    let mut g: Graph<i32> = Graph::new();
+    let n1 = g.new_node(1);
+    let n2 = g.new_node(2);
+    let n3 = g.new_node(3);
+    let n4 = g.new_node(4);
+    let n5 = g.new_node(5);
+    let n6 = g.new_node(6);
+
+    g.new_edge(n1, n2, 1);
+    g.new_edge(n2, n3, 2);
+    g.new_edge(n3, n4, 3);
+    g.new_edge(n3, n5, 4);
+    g.new_edge(n4, n6, 5);
+    g.new_edge(n3, n1, 6);
+    g.new_edge(n1, n5, 7);
+    g.new_edge(n2, n5, 8);
+    g.new_edge(n5, n2, 9);
+    g.new_edge(n6, n4, 10);
+    println!("{}", g.to_string());
+
+    let result = g.path_exists(n1, n6);
     println!("Result: {}", result);
 
     let file = File::open("./test.osm")?;
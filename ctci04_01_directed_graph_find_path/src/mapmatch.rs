@@ -0,0 +1,602 @@
+// -----------------------------------------------------------------------------------------------
+// Module mapmatch
+//
+// Map matching: which edges of an OSM graph did a vehicle drive on, given a noisy GPS trace. This
+// is the hidden Markov model of Newson and Krumm ("Hidden Markov Map Matching Through Noise and
+// Sparseness", 2009) solved with the Viterbi algorithm:
+//
+// - the hidden states of a GPS point are the candidate positions on the edges near it, found
+//   with a grid index of the edges
+// - the emission probability of a candidate falls with its distance from the GPS point
+//   (gaussian, `sigma`)
+// - the transition probability between candidates of consecutive points falls with the
+//   difference between the route distance on the graph and the straight line distance of the two
+//   points (exponential, `beta`), so detours are unlikely
+//
+// The matched route is the most probable sequence of candidates, connected by the shortest paths
+// found for the transitions.
+//
+//   let matcher = MapMatcher::new(&osmgraph, MatchParams::default());
+//   let path = matcher.match_trace(&parse_gpx(&mut reader)?);
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::Read;
+
+use xml::EventReader;
+use xml::reader::XmlEvent;
+
+use crate::gps_utils::calculate_distance;
+use crate::graphtraits::{EdgeId, GraphView, NodeId, Path};
+use crate::osmgraph::OsmNode;
+
+/// Meters per degree of latitude.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchParams {
+    /// standard deviation of the GPS error in meters
+    pub sigma: f64,
+    /// how strongly detours are penalized, in meters
+    pub beta: f64,
+    /// candidates are searched within this distance from a GPS point, in meters
+    pub radius: f64,
+    /// at most this many candidates (the closest ones) per GPS point
+    pub max_candidates: usize,
+}
+
+impl Default for MatchParams {
+    /// The values of Newson and Krumm for a GPS point every few seconds.
+    fn default() -> Self {
+        MatchParams {
+            sigma: 4.07,
+            beta: 3.0,
+            radius: 50.0,
+            max_candidates: 8,
+        }
+    }
+}
+
+/// A position on an edge close to a GPS point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub edge: EdgeId,
+    /// how far along the edge, 0 is the start and 1 the end
+    pub fraction: f64,
+    pub lat: f64,
+    pub lon: f64,
+    /// the distance from the GPS point in meters
+    pub distance: f64,
+}
+
+/// A grid of cells of `cell_size` degrees, every cell knows the edges that pass near it.
+pub struct EdgeIndex {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<EdgeId>>,
+}
+
+/// The closest point to `p` on the segment from `a` to `b`, all as (lat, lon).
+///
+/// # Returns
+///
+/// The fraction along the segment and the point.
+fn project(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> (f64, (f64, f64)) {
+    // a local flat projection is good enough for the length of an edge
+    let scale = a.0.to_radians().cos();
+    let (dx, dy) = ((b.1 - a.1) * scale, b.0 - a.0);
+    let (px, py) = ((p.1 - a.1) * scale, p.0 - a.0);
+    let length = dx * dx + dy * dy;
+    let fraction = if length == 0.0 {
+        0.0
+    } else {
+        ((px * dx + py * dy) / length).clamp(0.0, 1.0)
+    };
+    (
+        fraction,
+        (a.0 + fraction * (b.0 - a.0), a.1 + fraction * (b.1 - a.1)),
+    )
+}
+
+impl EdgeIndex {
+    /// Indexes all edges of the graph. About 0.001 degrees (100 m) is a good cell size for
+    /// city streets.
+    pub fn new<G: GraphView<OsmNode> + ?Sized>(g: &G, cell_size: f64) -> Self {
+        let mut index = EdgeIndex {
+            cell_size,
+            cells: HashMap::new(),
+        };
+        for edge_id in g.edge_ids() {
+            let Some(edge) = g.get_edge(edge_id) else {
+                continue;
+            };
+            let (Some(from), Some(to)) = (g.get_node_val(edge.0), g.get_node_val(edge.1)) else {
+                continue;
+            };
+            let (min_row, min_col) = index.cell(from.lat.min(to.lat), from.lon.min(to.lon));
+            let (max_row, max_col) = index.cell(from.lat.max(to.lat), from.lon.max(to.lon));
+            for row in min_row..=max_row {
+                for col in min_col..=max_col {
+                    index.cells.entry((row, col)).or_default().push(edge_id);
+                }
+            }
+        }
+        index
+    }
+
+    fn cell(&self, lat: f64, lon: f64) -> (i64, i64) {
+        (
+            (lat / self.cell_size).floor() as i64,
+            (lon / self.cell_size).floor() as i64,
+        )
+    }
+
+    /// The edges that may come within `radius` meters of the point, a superset of them.
+    pub fn edges_near(&self, lat: f64, lon: f64, radius: f64) -> Vec<EdgeId> {
+        let dlat = radius / METERS_PER_DEGREE;
+        let dlon = dlat / lat.to_radians().cos().max(0.01);
+        let (min_row, min_col) = self.cell(lat - dlat, lon - dlon);
+        let (max_row, max_col) = self.cell(lat + dlat, lon + dlon);
+        let mut edges = HashSet::new();
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                if let Some(cell) = self.cells.get(&(row, col)) {
+                    edges.extend(cell.iter().copied());
+                }
+            }
+        }
+        let mut edges: Vec<EdgeId> = edges.into_iter().collect();
+        edges.sort_unstable();
+        edges
+    }
+}
+
+pub struct MapMatcher<'a, G: GraphView<OsmNode> + ?Sized> {
+    graph: &'a G,
+    index: EdgeIndex,
+    params: MatchParams,
+}
+
+/// The Viterbi state of one GPS point.
+struct Step {
+    candidates: Vec<Candidate>,
+    /// log probability of the best sequence ending in each candidate
+    scores: Vec<f64>,
+    /// the candidate of the previous step on that sequence, `None` where a new part starts
+    back: Vec<Option<usize>>,
+    /// the edges between the previous candidate on that sequence and each candidate
+    links: Vec<Path>,
+}
+
+impl<'a, G: GraphView<OsmNode> + ?Sized> MapMatcher<'a, G> {
+    pub fn new(graph: &'a G, params: MatchParams) -> Self {
+        MapMatcher {
+            graph,
+            index: EdgeIndex::new(graph, 0.001),
+            params,
+        }
+    }
+
+    fn node(&self, id: NodeId) -> (f64, f64) {
+        let node = self.graph.get_node_val(id).unwrap();
+        (node.lat, node.lon)
+    }
+
+    /// The candidates for a GPS point, closest first.
+    pub fn candidates(&self, lat: f64, lon: f64) -> Vec<Candidate> {
+        let mut candidates: Vec<Candidate> = self
+            .index
+            .edges_near(lat, lon, self.params.radius)
+            .into_iter()
+            .filter_map(|edge_id| {
+                let edge = self.graph.get_edge(edge_id)?;
+                let (fraction, (clat, clon)) =
+                    project((lat, lon), self.node(edge.0), self.node(edge.1));
+                let distance = calculate_distance(lat, lon, clat, clon);
+                (distance <= self.params.radius).then_some(Candidate {
+                    edge: edge_id,
+                    fraction,
+                    lat: clat,
+                    lon: clon,
+                    distance,
+                })
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.edge.cmp(&b.edge)));
+        candidates.truncate(self.params.max_candidates);
+        candidates
+    }
+
+    /// Dijkstra from `from` up to `limit` (in edge weights).
+    ///
+    /// # Returns
+    ///
+    /// The distance and the last edge of the shortest path to every node within the limit.
+    fn distances(&self, from: NodeId, limit: i64) -> HashMap<NodeId, (i64, Option<EdgeId>)> {
+        let mut dist: HashMap<NodeId, (i64, Option<EdgeId>)> = HashMap::from([(from, (0, None))]);
+        let mut heap = BinaryHeap::from([Reverse((0i64, from))]);
+        while let Some(Reverse((d, node))) = heap.pop() {
+            if d > dist[&node].0 {
+                continue;
+            }
+            for edge_id in self.graph.find_edges_from(node).unwrap_or_default() {
+                let Some(edge) = self.graph.get_edge(edge_id) else {
+                    continue;
+                };
+                let nd = d + edge.2 as i64;
+                if nd <= limit && dist.get(&edge.1).is_none_or(|(old, _)| nd < *old) {
+                    dist.insert(edge.1, (nd, Some(edge_id)));
+                    heap.push(Reverse((nd, edge.1)));
+                }
+            }
+        }
+        dist
+    }
+
+    /// The length of an edge in meters (the OSM import stores centimeters).
+    fn length(&self, edge: EdgeId) -> f64 {
+        self.graph
+            .get_edge(edge)
+            .map_or(0.0, |e| e.2 as f64 / 100.0)
+    }
+
+    /// The edges of the shortest path to `end` in the result of [`Self::distances`].
+    fn edges_to(&self, reachable: &HashMap<NodeId, (i64, Option<EdgeId>)>, end: NodeId) -> Path {
+        let mut edges = Vec::new();
+        let mut node = end;
+        while let Some((_, Some(edge_id))) = reachable.get(&node) {
+            edges.push(*edge_id);
+            node = self.graph.get_edge(*edge_id).unwrap().0;
+        }
+        edges.reverse();
+        edges
+    }
+
+    /// Routes from `from` to each of `to`, `None` where there is no route shorter than `limit`
+    /// meters.
+    ///
+    /// # Returns
+    ///
+    /// The route distance in meters and the edges between the two candidate edges.
+    fn routes(&self, from: &Candidate, to: &[Candidate], limit: f64) -> Vec<Option<(f64, Path)>> {
+        let start = self.graph.get_edge(from.edge).unwrap().1;
+        let rest = (1.0 - from.fraction) * self.length(from.edge);
+        let reachable = self.distances(start, (limit * 100.0) as i64);
+        to.iter()
+            .map(|candidate| {
+                if candidate.edge == from.edge {
+                    // going back a little on the same edge is GPS noise
+                    let back = (candidate.fraction - from.fraction) * self.length(from.edge);
+                    return Some((back.max(0.0), Vec::new()));
+                }
+                let edge_start = self.graph.get_edge(candidate.edge)?.0;
+                let (between, _) = reachable.get(&edge_start)?;
+                Some((
+                    rest + *between as f64 / 100.0
+                        + candidate.fraction * self.length(candidate.edge),
+                    self.edges_to(&reachable, edge_start),
+                ))
+            })
+            .collect()
+    }
+
+    fn emission(&self, candidate: &Candidate) -> f64 {
+        -0.5 * (candidate.distance / self.params.sigma).powi(2)
+    }
+
+    /// Matches a GPS trace of (lat, lon) points onto the graph.
+    ///
+    /// # Returns
+    ///
+    /// The edges driven on, in order. Points without an edge within the search radius are
+    /// skipped; if consecutive points cannot be connected on the graph the matching starts over
+    /// and the path has a gap there. `None` if no point is close to an edge.
+    pub fn match_trace(&self, points: &[(f64, f64)]) -> Option<Path> {
+        let mut steps: Vec<Step> = Vec::new();
+        let mut previous_point: Option<(f64, f64)> = None;
+        for point in points {
+            let candidates = self.candidates(point.0, point.1);
+            if candidates.is_empty() {
+                continue;
+            }
+            let emissions: Vec<f64> = candidates.iter().map(|c| self.emission(c)).collect();
+            let mut scores = vec![f64::NEG_INFINITY; candidates.len()];
+            let mut back = vec![None; candidates.len()];
+            let mut links = vec![Vec::new(); candidates.len()];
+            if let (Some(last), Some(previous)) = (steps.last(), previous_point) {
+                let straight = calculate_distance(previous.0, previous.1, point.0, point.1);
+                // routes much longer than the straight line are too unlikely to matter
+                let limit = 2.0 * straight + 2.0 * self.params.radius + 100.0;
+                for (i, from) in last.candidates.iter().enumerate() {
+                    let routes = self.routes(from, &candidates, limit);
+                    for (j, route) in routes.into_iter().enumerate() {
+                        let Some((route, edges)) = route else {
+                            continue;
+                        };
+                        let transition = -(route - straight).abs() / self.params.beta;
+                        let score = last.scores[i] + transition + emissions[j];
+                        if score > scores[j] {
+                            scores[j] = score;
+                            back[j] = Some(i);
+                            links[j] = edges;
+                        }
+                    }
+                }
+            }
+            if scores.iter().all(|s| *s == f64::NEG_INFINITY) {
+                // the first point or a break in the trace
+                scores = emissions;
+            }
+            steps.push(Step {
+                candidates,
+                scores,
+                back,
+                links,
+            });
+            previous_point = Some(*point);
+        }
+
+        // follow the best sequence back from the end
+        let best = |scores: &[f64]| {
+            (0..scores.len())
+                .max_by(|a, b| scores[*a].total_cmp(&scores[*b]).then(b.cmp(a)))
+                .unwrap()
+        };
+        let mut chosen: Vec<Option<(Candidate, &Path)>> = Vec::with_capacity(steps.len());
+        let mut current = best(&steps.last()?.scores);
+        for i in (0..steps.len()).rev() {
+            chosen.push(Some((
+                steps[i].candidates[current],
+                &steps[i].links[current],
+            )));
+            match steps[i].back[current] {
+                Some(previous) => current = previous,
+                None if i > 0 => {
+                    // the start of a part, go on with the best candidate of the step before
+                    chosen.push(None);
+                    current = best(&steps[i - 1].scores);
+                }
+                None => {}
+            }
+        }
+        chosen.reverse();
+
+        let mut path: Path = Vec::new();
+        let mut previous: Option<Candidate> = None;
+        for step in chosen {
+            let Some((candidate, link)) = step else {
+                previous = None;
+                continue;
+            };
+            if let Some(previous) = previous
+                && previous.edge != candidate.edge
+            {
+                path.extend(link);
+            }
+            if path.last() != Some(&candidate.edge) {
+                path.push(candidate.edge);
+            }
+            previous = Some(candidate);
+        }
+        Some(path)
+    }
+}
+
+/// Reads the points of all tracks of a GPX file.
+///
+/// # Returns
+///
+/// The (lat, lon) of every `trkpt` in order, or the XML error.
+pub fn parse_gpx<R: Read>(
+    parser: &mut EventReader<R>,
+) -> Result<Vec<(f64, f64)>, xml::reader::Error> {
+    let mut points = Vec::new();
+    loop {
+        match parser.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if name.local_name == "trkpt" => {
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|a| a.name.local_name == key)
+                        .and_then(|a| a.value.parse::<f64>().ok())
+                };
+                if let (Some(lat), Some(lon)) = (attribute("lat"), attribute("lon")) {
+                    points.push((lat, lon));
+                }
+            }
+            XmlEvent::EndDocument => return Ok(points),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use crate::generators::Rng;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphCrud;
+    use crate::osmgraph::{OsmTags, create_osm_graph, parse_osm_with_tags};
+
+    /// A 6 x 6 grid of streets 100 m apart in both directions, node index y * 6 + x + 1.
+    fn city() -> Graph<OsmNode> {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let step = 100.0 / METERS_PER_DEGREE;
+        let position = |x: usize, y: usize| {
+            (
+                41.0 + y as f64 * step,
+                -71.0 + x as f64 * step / 41f64.to_radians().cos(),
+            )
+        };
+        for y in 0..6 {
+            for x in 0..6 {
+                let id = y * 6 + x + 1;
+                let (lat, lon) = position(x, y);
+                g.set_node(
+                    OsmNode {
+                        id,
+                        lat,
+                        lon,
+                        version: 1,
+                    },
+                    id,
+                );
+            }
+        }
+        let mut connect = |a: NodeId, b: NodeId| {
+            let (p, q) = (*g.get_node_val(a).unwrap(), *g.get_node_val(b).unwrap());
+            let weight = (calculate_distance(p.lat, p.lon, q.lat, q.lon) * 100.0) as i32;
+            g.new_edge(a, b, weight);
+            g.new_edge(b, a, weight);
+        };
+        for y in 0..6 {
+            for x in 0..6 {
+                let id = y * 6 + x + 1;
+                if x < 5 {
+                    connect(id, id + 1);
+                }
+                if y < 5 {
+                    connect(id, id + 6);
+                }
+            }
+        }
+        g
+    }
+
+    fn edge_between(g: &Graph<OsmNode>, from: NodeId, to: NodeId) -> EdgeId {
+        g.find_edges_from(from)
+            .unwrap()
+            .into_iter()
+            .find(|e| g.get_edge(*e).unwrap().1 == to)
+            .unwrap()
+    }
+
+    /// GPS points every `spacing` meters along the nodes, with up to `noise` meters of error.
+    fn drive(
+        g: &Graph<OsmNode>,
+        nodes: &[NodeId],
+        spacing: f64,
+        noise: f64,
+        rng: &mut Rng,
+    ) -> Vec<(f64, f64)> {
+        let mut points = Vec::new();
+        let noise_degrees = noise / METERS_PER_DEGREE;
+        let mut jitter = || (rng.next_u64() as f64 / u64::MAX as f64 * 2.0 - 1.0) * noise_degrees;
+        for pair in nodes.windows(2) {
+            let (a, b) = (
+                g.get_node_val(pair[0]).unwrap(),
+                g.get_node_val(pair[1]).unwrap(),
+            );
+            let length = calculate_distance(a.lat, a.lon, b.lat, b.lon);
+            let count = (length / spacing).ceil() as usize;
+            for i in 0..count {
+                let f = (i as f64 + 0.5) / count as f64;
+                points.push((
+                    a.lat + f * (b.lat - a.lat) + jitter(),
+                    a.lon + f * (b.lon - a.lon) + jitter(),
+                ));
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn test_candidates() {
+        let g = city();
+        // the cross streets are 50 m away
+        let params = MatchParams {
+            radius: 40.0,
+            ..MatchParams::default()
+        };
+        let matcher = MapMatcher::new(&g, params);
+        // 10 m north of the middle of the street from 1 to 2
+        let (a, b) = (g.get_node_val(1).unwrap(), g.get_node_val(2).unwrap());
+        let lat = a.lat + 10.0 / METERS_PER_DEGREE;
+        let lon = (a.lon + b.lon) / 2.0;
+        let candidates = matcher.candidates(lat, lon);
+        // both directions of the street
+        assert_eq!(candidates.len(), 2);
+        assert!((candidates[0].distance - 10.0).abs() < 0.1);
+        assert!((candidates[0].fraction - 0.5).abs() < 0.01);
+        assert_eq!(
+            candidates.iter().map(|c| c.edge).collect::<HashSet<_>>(),
+            HashSet::from([edge_between(&g, 1, 2), edge_between(&g, 2, 1)])
+        );
+        assert!(matcher.candidates(lat + 1.0, lon).is_empty());
+    }
+
+    #[test]
+    fn test_match_city() {
+        let g = city();
+        let matcher = MapMatcher::new(&g, MatchParams::default());
+        let route: Vec<NodeId> = vec![1, 2, 3, 9, 15, 16, 17, 23, 29, 28];
+        let expected: Path = route
+            .windows(2)
+            .map(|p| edge_between(&g, p[0], p[1]))
+            .collect();
+        let mut rng = Rng::new(48);
+        for spacing in [10.0, 30.0, 80.0] {
+            let points = drive(&g, &route, spacing, 8.0, &mut rng);
+            assert_eq!(
+                matcher.match_trace(&points),
+                Some(expected.clone()),
+                "every {} m",
+                spacing
+            );
+        }
+        assert_eq!(matcher.match_trace(&[]), None);
+        assert_eq!(matcher.match_trace(&[(0.0, 0.0)]), None);
+    }
+
+    #[test]
+    fn test_match_sparse() {
+        // one point on the first and one on the last street of a row, the streets in between
+        // come from the route of the transition
+        let g = city();
+        let middle = |a: NodeId, b: NodeId| {
+            let (p, q) = (g.get_node_val(a).unwrap(), g.get_node_val(b).unwrap());
+            ((p.lat + q.lat) / 2.0, (p.lon + q.lon) / 2.0)
+        };
+        let points = [middle(1, 2), middle(5, 6)];
+        let expected: Path = [1, 2, 3, 4, 5, 6]
+            .windows(2)
+            .map(|p| edge_between(&g, p[0], p[1]))
+            .collect();
+        let matcher = MapMatcher::new(&g, MatchParams::default());
+        assert_eq!(matcher.match_trace(&points), Some(expected));
+    }
+
+    #[test]
+    fn test_match_gpx() {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let mut tags = OsmTags::default();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm_with_tags(&mut reader, &mut g, &mut tags);
+
+        let gpx = "<?xml version=\"1.0\"?>
+            <gpx version=\"1.1\"><trk><trkseg>
+              <trkpt lat=\"41.8190260\" lon=\"-71.3847350\"><time>2025-05-03T20:00:00Z</time></trkpt>
+              <trkpt lat=\"41.8190400\" lon=\"-71.3842000\"/>
+              <trkpt lat=\"41.8190300\" lon=\"-71.3839000\"/>
+            </trkseg></trk></gpx>";
+        let points = parse_gpx(&mut EventReader::new(gpx.as_bytes())).unwrap();
+        assert_eq!(points.len(), 3);
+        let path = MapMatcher::new(&g, MatchParams::default())
+            .match_trace(&points)
+            .unwrap();
+        // all on the first way of test.osm, in order
+        assert!(path.len() >= 2);
+        assert!(path.iter().all(|e| tags.edge_way[e] == 5386504));
+        for pair in path.windows(2) {
+            assert_eq!(
+                g.get_edge(pair[0]).unwrap().1,
+                g.get_edge(pair[1]).unwrap().0
+            );
+        }
+    }
+}
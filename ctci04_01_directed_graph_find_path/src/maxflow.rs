@@ -0,0 +1,200 @@
+// -----------------------------------------------------------------------------------------------
+// Module maxflow
+//
+// Maximum flow and minimum cut with the Edmonds-Karp algorithm: Ford-Fulkerson where every
+// augmenting path is found with a breadth search, which bounds the run time to O(N * E^2).
+// The edge weights are used as capacities.
+//
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::graphtraits::{EdgeId, GraphView, NodeId, valid_edges};
+
+/// The result of a max flow computation.
+#[derive(Debug, PartialEq)]
+pub struct MaxFlow {
+    /// The total flow from the source to the sink.
+    pub value: i64,
+    /// The flow over every edge of the graph (0 for unused edges).
+    pub flow: HashMap<EdgeId, i64>,
+    /// The edges of a minimum cut, sorted. Their capacities add up to `value`.
+    pub min_cut: Vec<EdgeId>,
+}
+
+/// One direction of an edge in the residual graph. Arc `2 * i` is the forward arc of edge `i`,
+/// arc `2 * i + 1` the backward arc.
+struct Arc {
+    to: usize,
+    residual: i64,
+}
+
+/// Computes the maximum flow from `source` to `sink` and a minimum cut.
+///
+/// Edge weights are taken as capacities, negative weights count as 0. Parallel edges simply add
+/// up their capacities.
+///
+/// # Returns
+///
+/// `None` if `source` or `sink` is not a node of the graph.
+pub fn max_flow<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    source: NodeId,
+    sink: NodeId,
+) -> Option<MaxFlow> {
+    g.get_node_val(source)?;
+    g.get_node_val(sink)?;
+
+    let nodes = g.node_ids();
+    let index: HashMap<NodeId, usize> = nodes.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let edges = valid_edges(g);
+    let mut arcs: Vec<Arc> = Vec::with_capacity(2 * edges.len());
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    for (_, from, to, capacity) in edges.iter() {
+        let (from, to) = (index[from], index[to]);
+        adjacency[from].push(arcs.len());
+        arcs.push(Arc {
+            to,
+            residual: (*capacity).max(0) as i64,
+        });
+        adjacency[to].push(arcs.len());
+        arcs.push(Arc {
+            to: from,
+            residual: 0,
+        });
+    }
+
+    let (s, t) = (index[&source], index[&sink]);
+    let mut value = 0;
+    if s != t {
+        loop {
+            // breadth search for the shortest augmenting path, remember the arc into every node
+            let mut via: Vec<Option<usize>> = vec![None; nodes.len()];
+            let mut queue = VecDeque::from([s]);
+            while let Some(node) = queue.pop_front() {
+                if node == t {
+                    break;
+                }
+                for arc in adjacency[node].iter() {
+                    let next = arcs[*arc].to;
+                    if arcs[*arc].residual > 0 && next != s && via[next].is_none() {
+                        via[next] = Some(*arc);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            if via[t].is_none() {
+                break;
+            }
+
+            let mut bottleneck = i64::MAX;
+            let mut node = t;
+            while let Some(arc) = via[node] {
+                bottleneck = bottleneck.min(arcs[arc].residual);
+                node = arcs[arc ^ 1].to;
+            }
+            let mut node = t;
+            while let Some(arc) = via[node] {
+                arcs[arc].residual -= bottleneck;
+                arcs[arc ^ 1].residual += bottleneck;
+                node = arcs[arc ^ 1].to;
+            }
+            value += bottleneck;
+        }
+    }
+
+    // the flow over an edge is what has been pushed back to the backward arc
+    let flow: HashMap<EdgeId, i64> = edges
+        .iter()
+        .enumerate()
+        .map(|(i, (edge_id, _, _, _))| (*edge_id, arcs[2 * i + 1].residual))
+        .collect();
+
+    // nodes still reachable from the source in the residual graph form the source side of the cut
+    let mut reachable = vec![false; nodes.len()];
+    reachable[s] = true;
+    let mut todo = vec![s];
+    while let Some(node) = todo.pop() {
+        for arc in adjacency[node].iter() {
+            let next = arcs[*arc].to;
+            if arcs[*arc].residual > 0 && !reachable[next] {
+                reachable[next] = true;
+                todo.push(next);
+            }
+        }
+    }
+    let min_cut: Vec<EdgeId> = edges
+        .iter()
+        .filter(|(_, from, to, capacity)| {
+            *capacity > 0 && reachable[index[from]] && !reachable[index[to]]
+        })
+        .map(|(edge_id, _, _, _)| *edge_id)
+        .collect();
+
+    Some(MaxFlow {
+        value,
+        flow,
+        min_cut,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphAlgo;
+    use crate::graphtraits::GraphCrud;
+
+    #[test]
+    fn test_max_flow() {
+        // the example from CLRS, s = 1, t = 6, the max flow is 23
+        let mut g: Graph<i32> = Graph::new();
+        for v in 1..=6 {
+            g.set_node(v, v as NodeId);
+        }
+        let e12 = g.new_edge(1, 2, 16);
+        let _e13 = g.new_edge(1, 3, 13);
+        let _e32 = g.new_edge(3, 2, 4);
+        let e24 = g.new_edge(2, 4, 12);
+        let _e35 = g.new_edge(3, 5, 14);
+        let e43 = g.new_edge(4, 3, 9);
+        let _e46 = g.new_edge(4, 6, 20);
+        let e54 = g.new_edge(5, 4, 7);
+        let e56 = g.new_edge(5, 6, 4);
+
+        let result = g.max_flow(1, 6).unwrap();
+        assert_eq!(result.value, 23);
+        assert_eq!(result.min_cut, vec![e24, e54, e56]);
+        assert_eq!(result.flow[&e43], 0);
+        assert!(result.flow[&e12] <= 16);
+
+        // flow conservation in every inner node
+        for node in 2..=5 {
+            let inflow: i64 = g
+                .find_edges_to(node)
+                .unwrap()
+                .iter()
+                .map(|e| result.flow[e])
+                .sum();
+            let outflow: i64 = g
+                .find_edges_from(node)
+                .unwrap()
+                .iter()
+                .map(|e| result.flow[e])
+                .sum();
+            assert_eq!(inflow, outflow);
+        }
+    }
+
+    #[test]
+    fn test_no_path() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        let ab = g.new_edge(a, b, 5);
+        let result = g.max_flow(b, a).unwrap();
+        assert_eq!(result.value, 0);
+        assert!(result.min_cut.is_empty());
+        assert_eq!(result.flow[&ab], 0);
+        assert_eq!(g.max_flow(a, 100), None);
+    }
+}
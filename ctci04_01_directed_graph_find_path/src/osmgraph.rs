@@ -1,14 +1,22 @@
 use derive_more::Display;
 
-use std::{collections::HashMap, fs::File, io::BufReader};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufReader,
+};
 
 use xml::{EventReader, attribute::OwnedAttribute, reader::XmlEvent};
 
 use crate::gps_utils::calculate_distance;
-use crate::{graph::Graph, graphtraits::GraphCrud};
+use crate::{
+    graph::Graph,
+    graphtraits::{EdgeId, GraphCrud, NodeId},
+};
 
-#[derive(Display)]
+#[derive(Display, Debug, Clone, Copy, PartialEq)]
 #[display("{{ id: {}, lat: {}, lon: {}, version: {} }}", id, lat, lon, version)]
+#[repr(C)]
 pub struct OsmNode {
     pub id: usize,
     pub lat: f64,
@@ -16,6 +24,67 @@ pub struct OsmNode {
     pub version: i32,
 }
 
+/// The ID of an OSM way. Ways that are not in the OSM database yet have negative IDs, e.g. the
+/// pieces of split ways written by [`crate::osmwriter`].
+pub type WayId = i64;
+
+/// The tags of an OSM element, key -> value.
+pub type Tags = BTreeMap<String, String>;
+
+/// The `via` member of a turn restriction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Via {
+    Node(NodeId),
+    Way(WayId),
+}
+
+/// A `type=restriction` relation: turning from the way `from` over `via` into the way `to` is
+/// forbidden ("no_*" restrictions) or the only allowed turn ("only_*" restrictions).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnRestriction {
+    /// the ID of the relation
+    pub id: usize,
+    /// the value of the restriction tag, e.g. "no_left_turn" or "only_straight_on"
+    pub restriction: String,
+    pub from: WayId,
+    pub via: Via,
+    pub to: WayId,
+}
+
+impl TurnRestriction {
+    pub fn is_only(&self) -> bool {
+        self.restriction.starts_with("only_")
+    }
+}
+
+/// The `<bounds>` element of an OSM file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl Bounds {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        (self.min_lat..=self.max_lat).contains(&lat) && (self.min_lon..=self.max_lon).contains(&lon)
+    }
+}
+
+/// What the OSM import knows besides the graph: the tags of nodes and ways (only the ones that
+/// have tags), for every edge the ID of the way it was created from, the turn restrictions, the
+/// bounds of the file and the (way, node) references to nodes that are not in the file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OsmTags {
+    pub nodes: HashMap<NodeId, Tags>,
+    pub ways: HashMap<WayId, Tags>,
+    pub edge_way: HashMap<EdgeId, WayId>,
+    pub restrictions: Vec<TurnRestriction>,
+    pub bounds: Option<Bounds>,
+    pub missing_nodes: Vec<(WayId, NodeId)>,
+}
+
 pub fn create_osm_graph() -> Graph<OsmNode> {
     Graph::new()
 }
@@ -56,6 +125,42 @@ fn parse_until_end_element(parser: &mut EventReader<BufReader<File>>, name: &str
     }
 }
 
+/// Returns the key and value of a `tag` element.
+fn tag_attributes(attributes: &[OwnedAttribute]) -> Option<(String, String)> {
+    let k = attributes.iter().find(|a| a.name.local_name == "k")?;
+    let v = attributes.iter().find(|a| a.name.local_name == "v")?;
+    Some((k.value.clone(), v.value.clone()))
+}
+
+/// Collects the `tag` children of an element until its EndElement, other children are skipped.
+///
+/// # Arguments:
+///
+/// * `parser` - the XML event parser
+///
+fn parse_tags(parser: &mut EventReader<BufReader<File>>) -> Tags {
+    let mut tags = Tags::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {
+                name: child,
+                attributes,
+                namespace: _,
+            }) => {
+                if child.local_name == "tag"
+                    && let Some((k, v)) = tag_attributes(&attributes)
+                {
+                    tags.insert(k, v);
+                }
+                parse_until_end_element(parser, &child.local_name);
+            }
+            // the XML reader already rejects end elements that do not match their start element
+            Ok(XmlEvent::EndElement { .. }) | Ok(XmlEvent::EndDocument) | Err(_) => return tags,
+            _ => {}
+        }
+    }
+}
+
 /// Parses an OSM Node event, and adds it to the graph
 ///
 /// # Parameters:
@@ -63,14 +168,16 @@ fn parse_until_end_element(parser: &mut EventReader<BufReader<File>>, name: &str
 /// * `parser`     - the XML event parser
 /// * `g`          - the OSM graph to which the node shall be added
 /// * `attributes` - the attributes belonging to the startElement event
+/// * `tags`       - receives the tags of the node, `None` skips them
 ///
 /// # TODO
 ///
 /// correctly deal with unwrap
-fn parse_osm_node_event(
+fn parse_osm_node_event<G: GraphCrud<OsmNode> + ?Sized>(
     parser: &mut EventReader<BufReader<File>>,
-    g: &mut Graph<OsmNode>,
+    g: &mut G,
     attributes: &[OwnedAttribute],
+    tags: Option<&mut OsmTags>,
 ) {
     let attr: HashMap<String, String> = attributes
         .iter()
@@ -84,7 +191,14 @@ fn parse_osm_node_event(
     };
     let id = osmnode.id;
     g.set_node(osmnode, id);
-    parse_until_end_element(parser, "node"); // jump over tags
+    let Some(tags) = tags else {
+        parse_until_end_element(parser, "node"); // jump over tags
+        return;
+    };
+    let node_tags = parse_tags(parser);
+    if !node_tags.is_empty() {
+        tags.nodes.insert(id, node_tags);
+    }
 }
 
 fn parse_osm_nd_event(
@@ -97,7 +211,17 @@ fn parse_osm_nd_event(
     x.ok()
 }
 
-fn parse_osm_way_event(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<OsmNode>) {
+fn parse_osm_way_event<G: GraphCrud<OsmNode> + ?Sized>(
+    parser: &mut EventReader<BufReader<File>>,
+    g: &mut G,
+    attributes: &[OwnedAttribute],
+    mut tags: Option<&mut OsmTags>,
+) {
+    let way_id: Option<WayId> = attributes
+        .iter()
+        .find(|a| a.name.local_name == "id")
+        .and_then(|a| a.value.parse().ok());
+    let mut way_tags = Tags::new();
     let mut nd_vec = Vec::new();
     // Part 1: parse the OSM file and collect all nd child elements into a vector
     let mut keep_parsing = true;
@@ -114,6 +238,13 @@ fn parse_osm_way_event(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<
                     if let Some(num) = id {
                         nd_vec.push(num);
                     }
+                } else if name.local_name == "tag" {
+                    if tags.is_some()
+                        && let Some((k, v)) = tag_attributes(&attributes)
+                    {
+                        way_tags.insert(k, v);
+                    }
+                    parse_until_end_element(parser, "tag");
                 } else {
                     // most likely a tag, we ignore any other tag, we are only interested in "nd"
                     parse_until_end_element(parser, &name.local_name);
@@ -129,23 +260,126 @@ fn parse_osm_way_event(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<
             _ => {}
         }
     }
+    if let (Some(tags), Some(way_id)) = (tags.as_deref_mut(), way_id)
+        && !way_tags.is_empty()
+    {
+        tags.ways.insert(way_id, way_tags);
+    }
     // Part 2: Turn all the collected nd elements (way points) into edges and add them to our graph:
     if nd_vec.len() < 2 {
         return;
     }
+    if let Some(tags) = tags.as_deref_mut() {
+        for nd in nd_vec.iter() {
+            if g.get_node_val(*nd).is_none() {
+                tags.missing_nodes.push((way_id.unwrap_or_default(), *nd));
+            }
+        }
+    }
     let mut it = nd_vec.iter();
     let mut from = *(it.next().unwrap());
     for nd in it {
-        let from_node = g.get_node_val(from).unwrap();
-        let nd_node = g.get_node_val(*nd).unwrap();
-        let weight = calculate_distance(from_node.lat, from_node.lon, nd_node.lat, nd_node.lon);
-        println!("from: {}, to: {}, weight: {}", from, *nd, weight);
-        g.new_edge(from, *nd, (weight * 100.0) as i32); // turning the distance to mm
+        // no edges to nodes that are not in the file
+        if let (Some(from_node), Some(nd_node)) = (g.get_node_val(from), g.get_node_val(*nd)) {
+            let weight = calculate_distance(from_node.lat, from_node.lon, nd_node.lat, nd_node.lon);
+            let edge_id = g.new_edge(from, *nd, (weight * 100.0) as i32); // turning the distance to cm
+            if let (Some(tags), Some(way_id)) = (tags.as_deref_mut(), way_id) {
+                tags.edge_way.insert(edge_id, way_id);
+            }
+        }
         from = *nd;
     }
 }
 
-fn parse_osm_body(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<OsmNode>) {
+/// Parses an OSM relation. Only turn restrictions are kept, with exactly one `from` way, one `via`
+/// node or way and one `to` way; restrictions over several via ways are skipped.
+///
+/// # Parameters:
+///
+/// * `parser`     - the XML event parser
+/// * `attributes` - the attributes belonging to the startElement event
+/// * `tags`       - receives the turn restriction
+fn parse_osm_relation_event(
+    parser: &mut EventReader<BufReader<File>>,
+    attributes: &[OwnedAttribute],
+    tags: &mut OsmTags,
+) {
+    let id: Option<usize> = attributes
+        .iter()
+        .find(|a| a.name.local_name == "id")
+        .and_then(|a| a.value.parse().ok());
+    let mut relation_tags = Tags::new();
+    // (type, ref, role)
+    let mut members: Vec<(String, i64, String)> = Vec::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                if name.local_name == "tag" {
+                    if let Some((k, v)) = tag_attributes(&attributes) {
+                        relation_tags.insert(k, v);
+                    }
+                } else if name.local_name == "member" {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|a| a.name.local_name == key)
+                            .map(|a| a.value.clone())
+                    };
+                    if let (Some(kind), Some(Ok(id)), Some(role)) = (
+                        attribute("type"),
+                        attribute("ref").map(|r| r.parse()),
+                        attribute("role"),
+                    ) {
+                        members.push((kind, id, role));
+                    }
+                }
+                parse_until_end_element(parser, &name.local_name);
+            }
+            Ok(XmlEvent::EndElement { .. }) | Ok(XmlEvent::EndDocument) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    if relation_tags.get("type").map(|t| t.as_str()) != Some("restriction") {
+        return;
+    }
+    let restriction = relation_tags
+        .get("restriction")
+        .or_else(|| relation_tags.get("restriction:motorcar"));
+    let role = |role: &str| -> Vec<&(String, i64, String)> {
+        members.iter().filter(|m| m.2 == role).collect()
+    };
+    let (from, via, to) = (role("from"), role("via"), role("to"));
+    if let (Some(id), Some(restriction), [from], [via], [to]) =
+        (id, restriction, &from[..], &via[..], &to[..])
+        && from.0 == "way"
+        && to.0 == "way"
+    {
+        let via = match via.0.as_str() {
+            "node" => match NodeId::try_from(via.1) {
+                Ok(node) => Via::Node(node),
+                Err(_) => return,
+            },
+            "way" => Via::Way(via.1),
+            _ => return,
+        };
+        tags.restrictions.push(TurnRestriction {
+            id,
+            restriction: restriction.clone(),
+            from: from.1,
+            via,
+            to: to.1,
+        });
+    }
+}
+
+fn parse_osm_body<G: GraphCrud<OsmNode> + ?Sized>(
+    parser: &mut EventReader<BufReader<File>>,
+    g: &mut G,
+    mut tags: Option<&mut OsmTags>,
+) {
     let mut keep_parsing = true;
     while keep_parsing {
         let event = parser.next();
@@ -156,9 +390,32 @@ fn parse_osm_body(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<OsmNo
                 namespace: _,
             }) => {
                 if name.local_name == "node" {
-                    parse_osm_node_event(parser, g, &attributes);
+                    parse_osm_node_event(parser, g, &attributes, tags.as_deref_mut());
                 } else if name.local_name == "way" {
-                    parse_osm_way_event(parser, g);
+                    parse_osm_way_event(parser, g, &attributes, tags.as_deref_mut());
+                } else if let (Some(tags), "relation") = (tags.as_deref_mut(), &*name.local_name) {
+                    parse_osm_relation_event(parser, &attributes, tags);
+                } else if let (Some(tags), "bounds") = (tags.as_deref_mut(), &*name.local_name) {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|a| a.name.local_name == key)
+                            .and_then(|a| a.value.parse().ok())
+                    };
+                    if let (Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) = (
+                        attribute("minlat"),
+                        attribute("minlon"),
+                        attribute("maxlat"),
+                        attribute("maxlon"),
+                    ) {
+                        tags.bounds = Some(Bounds {
+                            min_lat,
+                            min_lon,
+                            max_lat,
+                            max_lon,
+                        });
+                    }
+                    parse_until_end_element(parser, "bounds");
                 } else {
                     // ignore all other element types
                     parse_until_end_element(parser, &name.local_name);
@@ -173,7 +430,30 @@ fn parse_osm_body(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<OsmNo
 }
 
 // wait for osm start event, then read node and way events, all other ignore. If endosm is found then stop
-pub fn parse_osm(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<OsmNode>) {
+//
+// Only the graph is filled, nothing else is kept in memory, so this works for a DiskGraph with a
+// file larger than RAM.
+pub fn parse_osm<G: GraphCrud<OsmNode> + ?Sized>(
+    parser: &mut EventReader<BufReader<File>>,
+    g: &mut G,
+) {
+    parse_osm_into(parser, g, None);
+}
+
+/// Like [`parse_osm`], but also collects the tags of the nodes and ways.
+pub fn parse_osm_with_tags<G: GraphCrud<OsmNode> + ?Sized>(
+    parser: &mut EventReader<BufReader<File>>,
+    g: &mut G,
+    tags: &mut OsmTags,
+) {
+    parse_osm_into(parser, g, Some(tags));
+}
+
+fn parse_osm_into<G: GraphCrud<OsmNode> + ?Sized>(
+    parser: &mut EventReader<BufReader<File>>,
+    g: &mut G,
+    mut tags: Option<&mut OsmTags>,
+) {
     let mut keep_parsing = true;
     while keep_parsing {
         let event = parser.next();
@@ -184,7 +464,7 @@ pub fn parse_osm(parser: &mut EventReader<BufReader<File>>, g: &mut Graph<OsmNod
                 namespace: _,
             }) => {
                 if name.local_name == "osm" {
-                    parse_osm_body(parser, g);
+                    parse_osm_body(parser, g, tags.as_deref_mut());
                 } else {
                     // ignore unkown element
                     parse_until_end_element(parser, &name.local_name);
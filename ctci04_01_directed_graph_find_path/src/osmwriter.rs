@@ -0,0 +1,374 @@
+// -----------------------------------------------------------------------------------------------
+// Module osmwriter
+//
+// Writes an OSM graph back to OSM XML (API 0.6), so that processed networks can be opened in
+// other OSM tools like JOSM or osmium. Nodes keep their IDs, coordinates, versions and tags.
+// Edges are turned back into ways: the edges of a way are chained in edge ID order, which is the
+// order the parser created them in. If a way no longer forms one chain, e.g. because a filter
+// removed one of its nodes, the first piece keeps the way ID and the other pieces become new ways
+// with the same tags and negative IDs, which OSM uses for ways that are not uploaded yet. Edges
+// without a way become new untagged ways as well. Turn restrictions are written if all their
+// members are.
+//
+//   let view = FilteredGraph::new(&osmgraph, |n| bbox.contains(n.lat, n.lon), |_| true);
+//   save_osm("extract.osm", &view, &tags)?;
+//
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path as FsPath;
+
+use xml::writer::{EmitterConfig, EventWriter, Result, XmlEvent};
+
+use crate::graphtraits::{GraphView, NodeId};
+use crate::osmgraph::{OsmNode, OsmTags, Tags, Via, WayId};
+
+/// The version written for ways and relations, the graph does not keep theirs.
+const DEFAULT_VERSION: &str = "1";
+
+/// Writes an element without children, attributes are (name, value) pairs.
+fn write_empty<W: Write>(
+    w: &mut EventWriter<W>,
+    name: &str,
+    attributes: &[(&str, &str)],
+) -> Result<()> {
+    let mut start = XmlEvent::start_element(name);
+    for (key, value) in attributes {
+        start = start.attr(*key, value);
+    }
+    w.write(start)?;
+    w.write(XmlEvent::end_element())
+}
+
+fn write_tags<W: Write>(w: &mut EventWriter<W>, tags: Option<&Tags>) -> Result<()> {
+    for (k, v) in tags.into_iter().flatten() {
+        write_empty(w, "tag", &[("k", k), ("v", v)])?;
+    }
+    Ok(())
+}
+
+/// Chains the edges of one way into node lists, a new list starts where an edge does not continue
+/// the previous one.
+fn chain(edges: &[(NodeId, NodeId)]) -> Vec<Vec<NodeId>> {
+    let mut pieces: Vec<Vec<NodeId>> = Vec::new();
+    for (from, to) in edges {
+        match pieces.last_mut() {
+            Some(piece) if piece.last() == Some(from) => piece.push(*to),
+            _ => pieces.push(vec![*from, *to]),
+        }
+    }
+    pieces
+}
+
+/// Writes the graph as OSM XML.
+///
+/// # Arguments
+///
+/// * `w` - receives the XML
+/// * `g` - the graph, any view of an OSM graph
+/// * `tags` - the tags, ways and turn restrictions from the import
+pub fn write_osm<W: Write, G: GraphView<OsmNode> + ?Sized>(
+    w: W,
+    g: &G,
+    tags: &OsmTags,
+) -> Result<()> {
+    let mut w = EmitterConfig::new()
+        .perform_indent(true)
+        .indent_string("\t")
+        .create_writer(w);
+    w.write(
+        XmlEvent::start_element("osm")
+            .attr("version", "0.6")
+            .attr("generator", env!("CARGO_PKG_NAME")),
+    )?;
+    if let Some(b) = tags.bounds {
+        let (min_lat, min_lon) = (b.min_lat.to_string(), b.min_lon.to_string());
+        let (max_lat, max_lon) = (b.max_lat.to_string(), b.max_lon.to_string());
+        write_empty(
+            &mut w,
+            "bounds",
+            &[
+                ("minlat", &min_lat),
+                ("minlon", &min_lon),
+                ("maxlat", &max_lat),
+                ("maxlon", &max_lon),
+            ],
+        )?;
+    }
+
+    let mut node_ids = g.node_ids();
+    node_ids.sort();
+    for node_id in node_ids.iter() {
+        let node = g.get_node_val(*node_id).unwrap();
+        let (id, lat, lon) = (
+            node_id.to_string(),
+            node.lat.to_string(),
+            node.lon.to_string(),
+        );
+        let version = node.version.to_string();
+        w.write(
+            XmlEvent::start_element("node")
+                .attr("id", &id)
+                .attr("lat", &lat)
+                .attr("lon", &lon)
+                .attr("version", &version),
+        )?;
+        write_tags(&mut w, tags.nodes.get(node_id))?;
+        w.write(XmlEvent::end_element())?;
+    }
+
+    // way -> edges in ID order, edges without a way are collected under None
+    let mut edge_ids = g.edge_ids();
+    edge_ids.sort();
+    let mut way_edges: BTreeMap<Option<WayId>, Vec<(NodeId, NodeId)>> = BTreeMap::new();
+    for edge_id in edge_ids {
+        if let Some(edge) = g.get_edge(edge_id) {
+            way_edges
+                .entry(tags.edge_way.get(&edge_id).copied())
+                .or_default()
+                .push((edge.0, edge.1));
+        }
+    }
+    // new ways count down from below the negative IDs that are already taken
+    let mut next_id = tags
+        .ways
+        .keys()
+        .chain(tags.edge_way.values())
+        .min()
+        .map_or(-1, |id| (*id).min(0) - 1);
+    // (id, nodes, tags), the new pieces come after the original ways
+    let mut ways: Vec<(WayId, Vec<NodeId>, Option<&Tags>)> = Vec::new();
+    let mut pieces = Vec::new();
+    for (way, edges) in way_edges.iter() {
+        let way_tags = way.and_then(|way| tags.ways.get(&way));
+        for (i, nodes) in chain(edges).into_iter().enumerate() {
+            match way {
+                Some(way) if i == 0 => ways.push((*way, nodes, way_tags)),
+                _ => pieces.push((nodes, way_tags)),
+            }
+        }
+    }
+    for (nodes, way_tags) in pieces {
+        ways.push((next_id, nodes, way_tags));
+        next_id -= 1;
+    }
+    for (id, nodes, way_tags) in ways.iter() {
+        let id = id.to_string();
+        w.write(
+            XmlEvent::start_element("way")
+                .attr("id", &id)
+                .attr("version", DEFAULT_VERSION),
+        )?;
+        for node in nodes {
+            write_empty(&mut w, "nd", &[("ref", &node.to_string())])?;
+        }
+        write_tags(&mut w, *way_tags)?;
+        w.write(XmlEvent::end_element())?;
+    }
+
+    let written_ways: HashSet<WayId> = ways.iter().map(|way| way.0).collect();
+    let mut restrictions: Vec<_> = tags
+        .restrictions
+        .iter()
+        .filter(|r| written_ways.contains(&r.from) && written_ways.contains(&r.to))
+        .filter(|r| match r.via {
+            Via::Node(node) => g.get_node_val(node).is_some(),
+            Via::Way(way) => written_ways.contains(&way),
+        })
+        .collect();
+    restrictions.sort_by_key(|r| r.id);
+    for r in restrictions {
+        let (id, from, to) = (r.id.to_string(), r.from.to_string(), r.to.to_string());
+        let (via_type, via) = match r.via {
+            Via::Node(node) => ("node", node.to_string()),
+            Via::Way(way) => ("way", way.to_string()),
+        };
+        w.write(
+            XmlEvent::start_element("relation")
+                .attr("id", &id)
+                .attr("version", DEFAULT_VERSION),
+        )?;
+        for (member_type, member, role) in [
+            ("way", &from, "from"),
+            (via_type, &via, "via"),
+            ("way", &to, "to"),
+        ] {
+            write_empty(
+                &mut w,
+                "member",
+                &[("type", member_type), ("ref", member), ("role", role)],
+            )?;
+        }
+        write_empty(
+            &mut w,
+            "tag",
+            &[("k", "restriction"), ("v", &r.restriction)],
+        )?;
+        write_empty(&mut w, "tag", &[("k", "type"), ("v", "restriction")])?;
+        w.write(XmlEvent::end_element())?;
+    }
+    w.write(XmlEvent::end_element())?;
+    w.into_inner().flush()?;
+    Ok(())
+}
+
+/// Writes an OSM XML file, see [`write_osm`].
+pub fn save_osm<P: AsRef<FsPath>, G: GraphView<OsmNode> + ?Sized>(
+    path: P,
+    g: &G,
+    tags: &OsmTags,
+) -> Result<()> {
+    write_osm(BufWriter::new(File::create(path)?), g, tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    use xml::EventReader;
+
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphCrud;
+    use crate::osmgraph::{TurnRestriction, create_osm_graph, parse_osm_with_tags};
+    use crate::subgraph::FilteredGraph;
+
+    fn load(path: &FsPath) -> (Graph<OsmNode>, OsmTags) {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let mut tags = OsmTags::default();
+        let mut reader = EventReader::new(BufReader::new(File::open(path).unwrap()));
+        parse_osm_with_tags(&mut reader, &mut g, &mut tags);
+        (g, tags)
+    }
+
+    /// Writes the graph to a temporary file and parses it again.
+    fn round_trip<G: GraphView<OsmNode> + ?Sized>(
+        name: &str,
+        g: &G,
+        tags: &OsmTags,
+    ) -> (Graph<OsmNode>, OsmTags) {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("osmwriter-{}-{}.osm", name, std::process::id()));
+        save_osm(&path, g, tags).unwrap();
+        let result = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    /// The edges as (from, to, weight, way), sorted, edge IDs change when parsing again.
+    fn edges(g: &Graph<OsmNode>, tags: &OsmTags) -> Vec<(NodeId, NodeId, i32, Option<WayId>)> {
+        let mut edges: Vec<_> = g
+            .edge_ids()
+            .into_iter()
+            .map(|id| {
+                let edge = g.get_edge(id).unwrap();
+                (edge.0, edge.1, edge.2, tags.edge_way.get(&id).copied())
+            })
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    fn assert_same(a: &(Graph<OsmNode>, OsmTags), b: &(Graph<OsmNode>, OsmTags)) {
+        let mut node_ids = a.0.node_ids();
+        node_ids.sort();
+        let mut other_ids = b.0.node_ids();
+        other_ids.sort();
+        assert_eq!(node_ids, other_ids);
+        for id in node_ids {
+            assert_eq!(a.0.get_node_val(id), b.0.get_node_val(id));
+        }
+        assert_eq!(edges(&a.0, &a.1), edges(&b.0, &b.1));
+        assert_eq!(a.1.nodes, b.1.nodes);
+        assert_eq!(a.1.ways, b.1.ways);
+        assert_eq!(a.1.restrictions, b.1.restrictions);
+        assert_eq!(a.1.bounds, b.1.bounds);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for file in ["test.osm", "turns.osm"] {
+            let original = load(FsPath::new(file));
+            let copy = round_trip(file, &original.0, &original.1);
+            assert_same(&original, &copy);
+            assert_eq!(copy.1.missing_nodes, vec![]);
+        }
+    }
+
+    #[test]
+    fn test_filtered_graph() {
+        // without node 1 only the detour is left, and no turn restriction has all its ways
+        let (g, tags) = load(FsPath::new("turns.osm"));
+        let view = FilteredGraph::new(&g, |n: &OsmNode| n.id != 1, |_| true);
+        let (copy, copy_tags) = round_trip("filtered", &view, &tags);
+        assert_eq!(copy.node_ids().len(), 5);
+        assert_eq!(
+            edges(&copy, &copy_tags)
+                .iter()
+                .map(|e| (e.0, e.1, e.3))
+                .collect::<Vec<_>>(),
+            vec![(4, 6, Some(105)), (6, 2, Some(106)), (6, 5, Some(105))]
+        );
+        assert_eq!(copy_tags.restrictions, vec![]);
+    }
+
+    #[test]
+    fn test_split_ways() {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        for id in 1..=4 {
+            let node = OsmNode {
+                id,
+                lat: 41.0 + id as f64 / 1000.0,
+                lon: -71.0,
+                version: 2,
+            };
+            g.set_node(node, id);
+        }
+        let mut tags = OsmTags::default();
+        // way 10 was 1 -> 2 -> 3 -> 4 before 2 -> 3 was removed, 4 -> 1 has no way; the
+        // untagged edges get new IDs first
+        tags.edge_way.insert(g.new_edge(1, 2, 0), 10);
+        tags.edge_way.insert(g.new_edge(3, 4, 0), 10);
+        g.new_edge(4, 1, 0);
+        let name = Tags::from([("name".to_string(), "Smith & Sons <Lane>".to_string())]);
+        tags.ways.insert(10, name.clone());
+        tags.nodes.insert(3, name.clone());
+        let restriction = |id, to| TurnRestriction {
+            id,
+            restriction: "no_u_turn".to_string(),
+            from: 10,
+            via: Via::Node(2),
+            to,
+        };
+        tags.restrictions = vec![restriction(2, 99), restriction(1, 10)];
+
+        let mut xml = Vec::new();
+        write_osm(&mut xml, &g, &tags).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("<way id=\"-1\" version=\"1\">"));
+        assert!(xml.contains("v=\"Smith &amp; Sons &lt;Lane&gt;\""));
+
+        let (copy, copy_tags) = round_trip("split", &g, &tags);
+        assert_eq!(copy.get_node_val(3).unwrap().version, 2);
+        assert_eq!(copy_tags.nodes[&3], name);
+        assert_eq!(
+            edges(&copy, &copy_tags)
+                .iter()
+                .map(|e| (e.0, e.1, e.3))
+                .collect::<Vec<_>>(),
+            vec![(1, 2, Some(10)), (3, 4, Some(-2)), (4, 1, Some(-1))]
+        );
+        assert_eq!(
+            copy_tags.ways,
+            HashMap::from([(10, name.clone()), (-2, name)])
+        );
+        assert_eq!(copy_tags.restrictions, vec![restriction(1, 10)]);
+
+        // the new ways keep their negative IDs when they are written again
+        let again = round_trip("split-again", &copy, &copy_tags);
+        assert_eq!(edges(&again.0, &again.1), edges(&copy, &copy_tags));
+    }
+}
@@ -1,136 +1,466 @@
 // -----------------------------------------------------------------------------------------------
 // Module rcgraph
 //
-// This module uses Rc and RefCell to manage the relationships between nodes and edges
+// This module uses Rc and Weak to manage the relationships between nodes and edges
 //
+// The graph owns its nodes, every node owns its out edges (`Rc<Edge>`) and only holds weak
+// back-references to its in edges. Edges point to their end nodes with weak references as well,
+// so there are never reference cycles, not even for cycles in the graph, and dropping the graph
+// frees everything.
+//
+// The node value is not behind a RefCell, only the edge lists are. That is what allows
+// `get_node_val` to hand out a plain `&T`. The price is that `set_node_val` has to replace the
+// node and re-point the edges to the new node.
+//
+// An edge can only point to nodes that exist. Edges created before their nodes are kept aside as
+// plain triplets, indexed by both of their nodes, and linked in when the last of their nodes is
+// created.
+//
+
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::rc::{Rc, Weak};
+
+use crate::graphtraits::{
+    EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, GraphDisplay, GraphView, NodeId, Path,
+};
+use crate::shortest_path::{all_simple_paths, dijkstra};
 
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::{cell::RefCell, collections::HashSet, rc::Rc};
+type RcNode<T> = Rc<Node<T>>;
+type WeakNode<T> = Weak<Node<T>>;
+type RcEdge<T> = Rc<Edge<T>>;
+type WeakEdge<T> = Weak<Edge<T>>;
 
-fn get_id() -> usize {
-    static COUNTER: AtomicUsize = AtomicUsize::new(1);
-    COUNTER.fetch_add(1, Ordering::Relaxed)
+pub struct Node<T> {
+    id: NodeId,
+    value: T,
+    out_edges: RefCell<Vec<RcEdge<T>>>,
+    in_edges: RefCell<Vec<WeakEdge<T>>>,
 }
 
-type Vedge = Vec<Edge>;
+pub struct Edge<T> {
+    id: EdgeId,
+    weight: i32,
+    from: RefCell<WeakNode<T>>,
+    to: RefCell<WeakNode<T>>,
+}
+
+impl<T> Edge<T> {
+    fn source_id(&self) -> NodeId {
+        self.from.borrow().upgrade().map(|n| n.id).unwrap()
+    }
+
+    fn target_id(&self) -> NodeId {
+        self.to.borrow().upgrade().map(|n| n.id).unwrap()
+    }
+}
 
-pub struct Node {
-    id: usize,
-    value: i32,
-    edges: Vedge,
+pub struct Graph<T> {
+    last_id: usize,
+    nodes: HashMap<NodeId, RcNode<T>>,
+    /// index to find an edge by its ID, the edge itself is owned by its source node
+    edges: HashMap<EdgeId, WeakEdge<T>>,
+    /// edges whose nodes do not all exist yet
+    pending: HashMap<EdgeId, EdgeTriplet>,
+    /// the pending edges of every node they start or end at
+    pending_at: HashMap<NodeId, Vec<EdgeId>>,
 }
 
-impl Default for Node {
-    fn default() -> Self {
-        Node {
-            id: get_id(),
-            value: 0,
-            edges: Vec::new(),
+impl<T> Graph<T> {
+    fn next_id(&mut self) -> usize {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn edge(&self, edge_id: EdgeId) -> Option<RcEdge<T>> {
+        self.edges.get(&edge_id)?.upgrade()
+    }
+
+    /// Creates an edge between two existing nodes and links it into both nodes.
+    fn link(&mut self, id: EdgeId, from: &RcNode<T>, to: &RcNode<T>, weight: i32) {
+        let edge = Rc::new(Edge {
+            id,
+            weight,
+            from: RefCell::new(Rc::downgrade(from)),
+            to: RefCell::new(Rc::downgrade(to)),
+        });
+        to.in_edges.borrow_mut().push(Rc::downgrade(&edge));
+        self.edges.insert(id, Rc::downgrade(&edge));
+        from.out_edges.borrow_mut().push(edge);
+    }
+
+    /// Links the edge if both nodes exist, otherwise keeps it until they do.
+    fn add_edge(&mut self, id: EdgeId, edge: EdgeTriplet) {
+        match (
+            self.nodes.get(&edge.0).cloned(),
+            self.nodes.get(&edge.1).cloned(),
+        ) {
+            (Some(from), Some(to)) => self.link(id, &from, &to, edge.2),
+            _ => {
+                self.pending.insert(id, edge);
+                self.pending_at.entry(edge.0).or_default().push(id);
+                if edge.1 != edge.0 {
+                    self.pending_at.entry(edge.1).or_default().push(id);
+                }
+            }
         }
     }
-}
 
-impl Node {
-    pub fn new() -> Self {
-        Default::default()
+    /// Removes a pending edge and its index entries.
+    fn remove_pending(&mut self, id: EdgeId) -> Option<EdgeTriplet> {
+        let edge = self.pending.remove(&id)?;
+        for node_id in [edge.0, edge.1] {
+            if let Some(ids) = self.pending_at.get_mut(&node_id) {
+                ids.retain(|e| *e != id);
+                if ids.is_empty() {
+                    self.pending_at.remove(&node_id);
+                }
+            }
+        }
+        Some(edge)
     }
-    pub fn from_val_edge(v: i32, e: Vec<Edge>) -> Self {
-        Node {
-            id: get_id(),
-            value: v,
-            edges: e,
+
+    /// Removes an edge from both of its nodes, which drops it.
+    fn unlink(&mut self, edge: &RcEdge<T>) {
+        self.edges.remove(&edge.id);
+        if let Some(to) = edge.to.borrow().upgrade() {
+            to.in_edges
+                .borrow_mut()
+                .retain(|e| e.as_ptr() != Rc::as_ptr(edge));
+        }
+        if let Some(from) = edge.from.borrow().upgrade() {
+            from.out_edges.borrow_mut().retain(|e| !Rc::ptr_eq(e, edge));
         }
     }
 }
 
-type RCnode = Rc<RefCell<Node>>;
-type VecRCnode = Vec<RCnode>;
+impl<T> GraphView<T> for Graph<T> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        self.nodes.get(&nodeid).map(|node| &node.value)
+    }
 
-pub struct Edge {
-    // id: usize,
-    weight: i32,
-    destination: RCnode,
-}
+    fn get_edge(&self, edgeid: EdgeId) -> Option<EdgeTriplet> {
+        match self.edge(edgeid) {
+            Some(e) => Some(EdgeTriplet(e.source_id(), e.target_id(), e.weight)),
+            None => self.pending.get(&edgeid).copied(),
+        }
+    }
 
-pub struct Graph {
-    // id: usize,
-    nodes: VecRCnode,
-}
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        let node = self.nodes.get(&from)?;
+        Some(node.out_edges.borrow().iter().map(|e| e.id).collect())
+    }
+
+    fn find_edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        let node = self.nodes.get(&to)?;
+        Some(
+            node.in_edges
+                .borrow()
+                .iter()
+                .filter_map(|e| e.upgrade())
+                .map(|e| e.id)
+                .collect(),
+        )
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self.nodes.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
 
-pub fn new_graph() -> Graph {
-    Graph {
-        // id: get_id(),
-        nodes: vec![],
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        let mut ids: Vec<EdgeId> = self
+            .edges
+            .keys()
+            .chain(self.pending.keys())
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        ids
     }
 }
 
-pub fn new_node(g: &mut Graph, value: i32) -> Rc<RefCell<Node>> {
-    let n = Rc::new(RefCell::new(Node {
-        id: get_id(),
-        value,
-        edges: vec![],
-    }));
-    g.nodes.push(n.clone());
-    n
+impl<T> GraphCrud<T> for Graph<T> {
+    fn new() -> Self {
+        Graph {
+            last_id: 0,
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+            pending: HashMap::new(),
+            pending_at: HashMap::new(),
+        }
+    }
+
+    fn new_node(&mut self, value: T) -> NodeId {
+        let id = self.next_id();
+        self.set_node(value, id)
+    }
+
+    fn set_node(&mut self, value: T, id: NodeId) -> NodeId {
+        // new_node and new_edge must not hand out this ID later
+        self.last_id = self.last_id.max(id);
+        match self.nodes.entry(id) {
+            Entry::Occupied(_) => self.set_node_val(id, value),
+            Entry::Vacant(entry) => {
+                entry.insert(Rc::new(Node {
+                    id,
+                    value,
+                    out_edges: RefCell::new(Vec::new()),
+                    in_edges: RefCell::new(Vec::new()),
+                }));
+                let mut ready: Vec<EdgeId> = self
+                    .pending_at
+                    .get(&id)
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .filter(|edge_id| {
+                        let e = self.pending[edge_id];
+                        self.nodes.contains_key(&e.0) && self.nodes.contains_key(&e.1)
+                    })
+                    .collect();
+                ready.sort_unstable();
+                for edge_id in ready {
+                    let edge = self.remove_pending(edge_id).unwrap();
+                    self.add_edge(edge_id, edge);
+                }
+            }
+        }
+        id
+    }
+
+    fn del_node(&mut self, nodeid: NodeId) -> bool {
+        let Some(node) = self.nodes.get(&nodeid).cloned() else {
+            return false;
+        };
+        let out_edges: Vec<RcEdge<T>> = node.out_edges.borrow().clone();
+        let in_edges: Vec<RcEdge<T>> = node
+            .in_edges
+            .borrow()
+            .iter()
+            .filter_map(|e| e.upgrade())
+            .collect();
+        for edge in out_edges.iter().chain(in_edges.iter()) {
+            self.unlink(edge);
+        }
+        for edge_id in self.pending_at.get(&nodeid).cloned().unwrap_or_default() {
+            self.remove_pending(edge_id);
+        }
+        self.nodes.remove(&nodeid);
+        true
+    }
+
+    fn set_node_val(&mut self, nodeid: NodeId, val: T) {
+        let Some(old) = self.nodes.remove(&nodeid) else {
+            return;
+        };
+        let node = Rc::new(Node {
+            id: nodeid,
+            value: val,
+            out_edges: RefCell::new(old.out_edges.take()),
+            in_edges: RefCell::new(old.in_edges.take()),
+        });
+        for edge in node.out_edges.borrow().iter() {
+            *edge.from.borrow_mut() = Rc::downgrade(&node);
+        }
+        for edge in node.in_edges.borrow().iter().filter_map(|e| e.upgrade()) {
+            *edge.to.borrow_mut() = Rc::downgrade(&node);
+        }
+        self.nodes.insert(nodeid, node);
+    }
+
+    fn new_edge(&mut self, from: NodeId, to: NodeId, weight: i32) -> EdgeId {
+        let id = self.next_id();
+        self.add_edge(id, EdgeTriplet(from, to, weight));
+        id
+    }
+
+    fn del_edge(&mut self, edgeid: EdgeId) -> bool {
+        match self.edge(edgeid) {
+            Some(edge) => {
+                self.unlink(&edge);
+                true
+            }
+            None => self.remove_pending(edgeid).is_some(),
+        }
+    }
+
+    fn set_edge(&mut self, edgeid: EdgeId, edge_data: EdgeTriplet) {
+        // edges are immutable, replace it by a new one with the same ID
+        match self.edge(edgeid) {
+            Some(edge) => self.unlink(&edge),
+            None if self.remove_pending(edgeid).is_some() => {}
+            None => return,
+        }
+        self.add_edge(edgeid, edge_data);
+    }
 }
 
-pub fn add_edge(_g: &mut Graph, node1: Rc<RefCell<Node>>, node2: Rc<RefCell<Node>>, weight: i32) {
-    node1.borrow_mut().edges.push(Edge {
-        // id: get_id(),
-        weight,
-        destination: node2,
-    });
+impl<T: Display> GraphDisplay<T> for Graph<T> {
+    fn to_string(&self) -> String {
+        let nodes: Vec<String> = self
+            .node_ids()
+            .iter()
+            .filter_map(|id| self.node_to_string(*id))
+            .collect();
+        let edges: Vec<String> = self
+            .edge_ids()
+            .iter()
+            .filter_map(|id| self.edge_to_string(*id))
+            .collect();
+        format!(
+            "{{ \"nodes\": [{}], \"edges\": [{}] }}",
+            nodes.join(", "),
+            edges.join(", ")
+        )
+    }
+
+    fn node_to_string(&self, node_id: NodeId) -> Option<String> {
+        let node = self.nodes.get(&node_id)?;
+        Some(format!(
+            "{{ \"nodeid\": {}, \"value\": \"{}\" }}",
+            node_id, node.value
+        ))
+    }
+
+    fn edge_to_string(&self, edge_id: EdgeId) -> Option<String> {
+        let edge = self.get_edge(edge_id)?;
+        Some(format!(
+            "{{ \"edge_id\": {}, \"from\": {}, \"to\": {}, \"weight\": {} }}",
+            edge_id, edge.0, edge.1, edge.2
+        ))
+    }
+
+    fn path_to_string(&self, path: &Path) -> Option<String> {
+        let edges: Option<Vec<String>> = path.iter().map(|e| self.edge_to_string(*e)).collect();
+        Some(format!("[{}]", edges?.join(", ")))
+    }
 }
 
-pub fn print_nodes(g: &Graph) {
-    for n in g.nodes.iter() {
-        print!("val: {}, edges: ", n.borrow().value);
-        for e in n.borrow().edges.iter() {
-            print!("(w: {} d: {}) ", e.weight, e.destination.borrow().value);
+impl<T> GraphAlgo<T> for Graph<T> {
+    /// Depth search that follows the Rc links directly instead of looking up IDs.
+    fn path_exists(&self, from: NodeId, to: NodeId) -> bool {
+        let (Some(start), Some(_)) = (self.nodes.get(&from), self.nodes.get(&to)) else {
+            return false;
+        };
+        let mut visited_nodes: HashSet<NodeId> = HashSet::from([from]);
+        let mut todo: Vec<RcNode<T>> = vec![start.clone()];
+        while let Some(node) = todo.pop() {
+            if node.id == to {
+                return true;
+            }
+            for edge in node.out_edges.borrow().iter() {
+                if let Some(next) = edge.to.borrow().upgrade()
+                    && visited_nodes.insert(next.id)
+                {
+                    todo.push(next);
+                }
+            }
         }
-        println!();
+        false
     }
-}
 
-pub fn find_path(g: &Graph, start_node: Rc<RefCell<Node>>, dest_node: Rc<RefCell<Node>>) -> bool {
-    let mut visited_nodes = HashSet::new();
-    let mut path = Vec::new();
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        dijkstra(self, from, to).map(|path| path.edges)
+    }
 
-    find_recursive_path(g, start_node, dest_node, &mut visited_nodes, &mut path)
+    fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
+        all_simple_paths(self, from, to)
+    }
 }
 
-pub fn find_recursive_path(
-    _g: &Graph,
-    start_node: Rc<RefCell<Node>>,
-    dest_node: Rc<RefCell<Node>>,
-    visited_nodes: &mut HashSet<usize>,
-    path: &mut Vec<usize>,
-) -> bool {
-    if start_node.borrow().id == dest_node.borrow().id {
-        return true;
-    }
-    // contains does not work because we would need to have the trait hash implemented
-    //if visited_nodes.contains(start_node) {}
-    let start_node_id = start_node.borrow().id;
-    if visited_nodes.iter().any(|i| *i == start_node_id) {
-        return false;
-    }
-    visited_nodes.insert(start_node_id);
-    path.push(start_node_id);
-
-    for edge in start_node.borrow().edges.iter() {
-        let result = find_recursive_path(
-            _g,
-            edge.destination.clone(),
-            dest_node.clone(),
-            visited_nodes,
-            path,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::conformance::conformance_tests!(Graph<i32>);
+
+    #[test]
+    fn test_weak_back_references() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        let ab = g.new_edge(a, b, 5);
+        let ba = g.new_edge(b, a, 6);
+        assert_eq!(g.find_edges_to(b), Some(vec![ab]));
+
+        // the edge is owned by its source node only
+        let edge = g.edge(ab).unwrap();
+        assert_eq!(Rc::strong_count(&edge), 2);
+        drop(edge);
+
+        // the cycle a <-> b does not keep the nodes alive
+        let node_a = Rc::downgrade(&g.nodes[&a]);
+        assert!(g.del_node(a));
+        assert!(node_a.upgrade().is_none());
+        assert!(g.get_edge(ab).is_none());
+        assert!(g.get_edge(ba).is_none());
+        assert_eq!(g.find_edges_to(b), Some(vec![]));
+        assert_eq!(g.find_edges_from(b), Some(vec![]));
+    }
+
+    #[test]
+    fn test_set_node_val_keeps_edges() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        let ab = g.new_edge(a, b, 5);
+        g.set_node_val(a, 10);
+        g.set_node_val(b, 20);
+        assert_eq!(g.get_node_val(a), Some(&10));
+        let edge = g.get_edge(ab).unwrap();
+        assert_eq!((edge.0, edge.1, edge.2), (a, b, 5));
+        assert!(g.path_exists(a, b));
+        assert!(!g.path_exists(b, a));
+    }
+
+    #[test]
+    fn test_edges_before_nodes() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let ab = g.new_edge(a, 100, 5);
+        let bb = g.new_edge(100, 100, 6);
+        assert_eq!(g.get_edge(ab), Some(EdgeTriplet(a, 100, 5)));
+        assert_eq!(g.edge_ids(), vec![ab, bb]);
+        assert_eq!(g.find_edges_from(a), Some(vec![]));
+        g.set_node(2, 100);
+        assert_eq!(g.find_edges_from(a), Some(vec![ab]));
+        assert_eq!(g.find_edges_to(100).map(|e| e.len()), Some(2));
+        assert!(g.del_node(100));
+        assert_eq!(g.edge_ids(), vec![]);
+
+        // the pending edges are found by their nodes, not by scanning
+        let cd = g.new_edge(200, 300, 7);
+        let ce = g.new_edge(200, 400, 8);
+        assert_eq!(g.pending_at[&200], vec![cd, ce]);
+        g.set_node(3, 200);
+        g.set_node(4, 300);
+        assert_eq!(g.find_edges_from(200), Some(vec![cd]));
+        // 200 -> 400 is still waiting for node 400
+        assert_eq!(g.pending_at.len(), 2);
+        assert_eq!(g.pending_at[&400], vec![ce]);
+        assert!(g.del_edge(ce));
+        assert!(g.pending.is_empty() && g.pending_at.is_empty());
+    }
+
+    #[test]
+    fn test_display() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        let ab = g.new_edge(a, b, 5);
+        assert_eq!(
+            g.to_string(),
+            "{ \"nodes\": [{ \"nodeid\": 1, \"value\": \"1\" }, { \"nodeid\": 2, \"value\": \"2\" }], \
+             \"edges\": [{ \"edge_id\": 3, \"from\": 1, \"to\": 2, \"weight\": 5 }] }"
         );
-        if result {
-            return true;
-        } else {
-            path.pop();
-        }
+        assert_eq!(
+            g.path_to_string(&vec![ab]),
+            Some("[{ \"edge_id\": 3, \"from\": 1, \"to\": 2, \"weight\": 5 }]".to_string())
+        );
+        assert_eq!(g.path_to_string(&vec![100]), None);
     }
-    false
 }
@@ -0,0 +1,366 @@
+// -----------------------------------------------------------------------------------------------
+// Module report
+//
+// Statistics and sanity checks for a loaded OSM graph: what was loaded, and what looks wrong
+// with it (references to nodes that are not in the file, self loops, duplicate edges, nodes
+// outside the bounds of the file, parts of the network that are cut off). The report can be
+// printed as text for people or as JSON for scripts:
+//
+//   let report = OsmReport::new(&osmgraph, &tags);
+//   println!("{}", report.to_text());
+//
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::csrgraph::CsrGraph;
+use crate::graphtraits::{GraphView, NodeId};
+use crate::osmgraph::{Bounds, OsmNode, OsmTags, WayId};
+use crate::scc::tarjan_scc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsmReport {
+    pub nodes: usize,
+    pub edges: usize,
+    /// ways that edges were created from
+    pub ways: usize,
+    /// nodes without any edge
+    pub isolated_nodes: usize,
+    /// references of ways to nodes that are not in the file
+    pub missing_nodes: usize,
+    pub self_loops: usize,
+    /// edges with the same start and end node as an edge with a lower ID
+    pub duplicate_edges: usize,
+    /// degree -> number of nodes
+    pub out_degrees: BTreeMap<usize, usize>,
+    pub in_degrees: BTreeMap<usize, usize>,
+    /// the box around all nodes
+    pub bounding_box: Option<Bounds>,
+    /// the `<bounds>` element of the file
+    pub bounds: Option<Bounds>,
+    pub nodes_outside_bounds: usize,
+    pub weak_components: usize,
+    pub largest_weak_component: usize,
+    pub strong_components: usize,
+    pub largest_strong_component: usize,
+}
+
+/// The sizes of the weakly connected components, i.e. ignoring the direction of the edges.
+fn weak_component_sizes(nodes: &[NodeId], edges: &[(NodeId, NodeId)]) -> Vec<usize> {
+    let mut neighbours: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+    for (from, to) in edges {
+        neighbours.entry(*from).or_default().push(*to);
+        neighbours.entry(*to).or_default().push(*from);
+    }
+    let mut seen: HashSet<NodeId> = HashSet::new();
+    let mut sizes = Vec::new();
+    for start in nodes {
+        if !seen.insert(*start) {
+            continue;
+        }
+        let mut size = 0;
+        let mut queue = VecDeque::from([*start]);
+        while let Some(node) = queue.pop_front() {
+            size += 1;
+            for next in neighbours.get(&node).into_iter().flatten() {
+                if seen.insert(*next) {
+                    queue.push_back(*next);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    sizes
+}
+
+impl OsmReport {
+    pub fn new<G: GraphView<OsmNode> + ?Sized>(g: &G, tags: &OsmTags) -> Self {
+        let node_ids = g.node_ids();
+        let node_set: HashSet<NodeId> = node_ids.iter().copied().collect();
+        // edges between existing nodes, ordered by ID
+        let edges: Vec<(NodeId, NodeId)> = g
+            .edge_ids()
+            .into_iter()
+            .filter_map(|edge_id| g.get_edge(edge_id))
+            .filter(|edge| node_set.contains(&edge.0) && node_set.contains(&edge.1))
+            .map(|edge| (edge.0, edge.1))
+            .collect();
+
+        let mut out_degree: HashMap<NodeId, usize> = HashMap::new();
+        let mut in_degree: HashMap<NodeId, usize> = HashMap::new();
+        let mut pairs: HashSet<(NodeId, NodeId)> = HashSet::new();
+        let (mut self_loops, mut duplicate_edges) = (0, 0);
+        for (from, to) in edges.iter() {
+            *out_degree.entry(*from).or_default() += 1;
+            *in_degree.entry(*to).or_default() += 1;
+            if from == to {
+                self_loops += 1;
+            }
+            if !pairs.insert((*from, *to)) {
+                duplicate_edges += 1;
+            }
+        }
+        let histogram = |degrees: &HashMap<NodeId, usize>| {
+            let mut histogram = BTreeMap::new();
+            for id in node_ids.iter() {
+                *histogram
+                    .entry(degrees.get(id).copied().unwrap_or(0))
+                    .or_default() += 1;
+            }
+            histogram
+        };
+        let isolated_nodes = node_ids
+            .iter()
+            .filter(|id| !out_degree.contains_key(id) && !in_degree.contains_key(id))
+            .count();
+
+        let mut bounding_box: Option<Bounds> = None;
+        let mut nodes_outside_bounds = 0;
+        for id in node_ids.iter() {
+            let node = g.get_node_val(*id).unwrap();
+            if tags
+                .bounds
+                .is_some_and(|bounds| !bounds.contains(node.lat, node.lon))
+            {
+                nodes_outside_bounds += 1;
+            }
+            let b = bounding_box.get_or_insert(Bounds {
+                min_lat: node.lat,
+                min_lon: node.lon,
+                max_lat: node.lat,
+                max_lon: node.lon,
+            });
+            b.min_lat = b.min_lat.min(node.lat);
+            b.min_lon = b.min_lon.min(node.lon);
+            b.max_lat = b.max_lat.max(node.lat);
+            b.max_lon = b.max_lon.max(node.lon);
+        }
+
+        let weak = weak_component_sizes(&node_ids, &edges);
+        // Tarjan needs the out edges of every node, which is fast on a CSR graph
+        let strong = tarjan_scc(&CsrGraph::freeze(g));
+        let ways: HashSet<WayId> = tags.edge_way.values().copied().collect();
+        OsmReport {
+            nodes: node_ids.len(),
+            edges: g.edge_ids().len(),
+            ways: ways.len(),
+            isolated_nodes,
+            missing_nodes: tags.missing_nodes.len(),
+            self_loops,
+            duplicate_edges,
+            out_degrees: histogram(&out_degree),
+            in_degrees: histogram(&in_degree),
+            bounding_box,
+            bounds: tags.bounds,
+            nodes_outside_bounds,
+            weak_components: weak.len(),
+            largest_weak_component: weak.iter().copied().max().unwrap_or(0),
+            strong_components: strong.len(),
+            largest_strong_component: strong.iter().map(|c| c.len()).max().unwrap_or(0),
+        }
+    }
+
+    /// The problems found, empty if the data looks fine.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let mut warn = |count: usize, text: &str| {
+            if count > 0 {
+                warnings.push(format!("{} {}", count, text));
+            }
+        };
+        warn(
+            self.missing_nodes,
+            "way references to nodes that are not in the file",
+        );
+        warn(self.self_loops, "self loops");
+        warn(self.duplicate_edges, "duplicate edges");
+        warn(self.nodes_outside_bounds, "nodes outside of the bounds");
+        if self.weak_components > 1 {
+            warnings.push(format!(
+                "the network falls apart into {} pieces, the largest has {} of {} nodes",
+                self.weak_components, self.largest_weak_component, self.nodes
+            ));
+        }
+        warnings
+    }
+
+    pub fn to_text(&self) -> String {
+        let bounds = |b: &Option<Bounds>| match b {
+            Some(b) => format!(
+                "{}, {} - {}, {}",
+                b.min_lat, b.min_lon, b.max_lat, b.max_lon
+            ),
+            None => "-".to_string(),
+        };
+        let histogram = |h: &BTreeMap<usize, usize>| {
+            h.iter()
+                .map(|(degree, count)| format!("{}: {}", degree, count))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let mut lines = vec![
+            format!("nodes:                     {}", self.nodes),
+            format!("edges:                     {}", self.edges),
+            format!("ways:                      {}", self.ways),
+            format!("isolated nodes:            {}", self.isolated_nodes),
+            format!("missing nodes:             {}", self.missing_nodes),
+            format!("self loops:                {}", self.self_loops),
+            format!("duplicate edges:           {}", self.duplicate_edges),
+            format!(
+                "out degrees:               {}",
+                histogram(&self.out_degrees)
+            ),
+            format!("in degrees:                {}", histogram(&self.in_degrees)),
+            format!("bounding box:              {}", bounds(&self.bounding_box)),
+            format!("bounds:                    {}", bounds(&self.bounds)),
+            format!("nodes outside bounds:      {}", self.nodes_outside_bounds),
+            format!(
+                "weak components:           {} (largest {})",
+                self.weak_components, self.largest_weak_component
+            ),
+            format!(
+                "strong components:         {} (largest {})",
+                self.strong_components, self.largest_strong_component
+            ),
+        ];
+        for warning in self.warnings() {
+            lines.push(format!("warning: {}", warning));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let bounds = |b: &Option<Bounds>| match b {
+            Some(b) => format!(
+                "{{\"min_lat\": {}, \"min_lon\": {}, \"max_lat\": {}, \"max_lon\": {}}}",
+                b.min_lat, b.min_lon, b.max_lat, b.max_lon
+            ),
+            None => "null".to_string(),
+        };
+        let histogram = |h: &BTreeMap<usize, usize>| {
+            let entries: Vec<String> = h
+                .iter()
+                .map(|(degree, count)| format!("\"{}\": {}", degree, count))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        };
+        let warnings: Vec<String> = self
+            .warnings()
+            .iter()
+            .map(|w| format!("\"{}\"", w))
+            .collect();
+        let fields = [
+            format!("\"nodes\": {}", self.nodes),
+            format!("\"edges\": {}", self.edges),
+            format!("\"ways\": {}", self.ways),
+            format!("\"isolated_nodes\": {}", self.isolated_nodes),
+            format!("\"missing_nodes\": {}", self.missing_nodes),
+            format!("\"self_loops\": {}", self.self_loops),
+            format!("\"duplicate_edges\": {}", self.duplicate_edges),
+            format!("\"out_degrees\": {}", histogram(&self.out_degrees)),
+            format!("\"in_degrees\": {}", histogram(&self.in_degrees)),
+            format!("\"bounding_box\": {}", bounds(&self.bounding_box)),
+            format!("\"bounds\": {}", bounds(&self.bounds)),
+            format!("\"nodes_outside_bounds\": {}", self.nodes_outside_bounds),
+            format!("\"weak_components\": {}", self.weak_components),
+            format!(
+                "\"largest_weak_component\": {}",
+                self.largest_weak_component
+            ),
+            format!("\"strong_components\": {}", self.strong_components),
+            format!(
+                "\"largest_strong_component\": {}",
+                self.largest_strong_component
+            ),
+            format!("\"warnings\": [{}]", warnings.join(", ")),
+        ];
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphCrud;
+    use crate::osmgraph::{create_osm_graph, parse_osm_with_tags};
+
+    fn load(path: &str) -> (Graph<OsmNode>, OsmTags) {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let mut tags = OsmTags::default();
+        let mut reader = EventReader::new(BufReader::new(File::open(path).unwrap()));
+        parse_osm_with_tags(&mut reader, &mut g, &mut tags);
+        (g, tags)
+    }
+
+    #[test]
+    fn test_turns_osm() {
+        let (g, tags) = load("./turns.osm");
+        let report = OsmReport::new(&g, &tags);
+        assert_eq!(report.nodes, 6);
+        assert_eq!(report.edges, 8);
+        assert_eq!(report.ways, 5);
+        assert_eq!(report.isolated_nodes, 0);
+        assert_eq!(
+            report.out_degrees,
+            BTreeMap::from([(0, 1), (1, 3), (2, 1), (3, 1)])
+        );
+        assert_eq!(report.in_degrees.values().sum::<usize>(), 6);
+        assert_eq!(report.weak_components, 1);
+        // node 2 is a dead end
+        assert!(report.strong_components > 1);
+        assert_eq!(report.bounds.unwrap().max_lat, 41.001);
+        assert_eq!(report.nodes_outside_bounds, 0);
+        assert_eq!(report.warnings(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_problems() {
+        let (mut g, mut tags) = load("./turns.osm");
+        g.new_edge(1, 1, 0);
+        g.new_edge(3, 1, 100);
+        g.set_node(
+            OsmNode {
+                id: 7,
+                lat: 42.0,
+                lon: -71.0,
+                version: 1,
+            },
+            7,
+        );
+        tags.missing_nodes.push((100, 99));
+        let report = OsmReport::new(&g, &tags);
+        assert_eq!(report.self_loops, 1);
+        assert_eq!(report.duplicate_edges, 1);
+        assert_eq!(report.isolated_nodes, 1);
+        assert_eq!(report.nodes_outside_bounds, 1);
+        assert_eq!(report.bounding_box.unwrap().max_lat, 42.0);
+        assert_eq!(report.weak_components, 2);
+        assert_eq!(report.largest_weak_component, 6);
+        assert_eq!(report.warnings().len(), 5);
+
+        let text = report.to_text();
+        assert!(text.contains("self loops:                1"));
+        assert!(text.contains("warning: 1 duplicate edges"));
+        let json = report.to_json();
+        assert!(json.starts_with("{\"nodes\": 7, \"edges\": 10,"));
+        assert!(json.contains("\"out_degrees\": {\"0\": 2,"));
+        assert!(json.contains("\"warnings\": [\"1 way references"));
+    }
+
+    #[test]
+    fn test_test_osm() {
+        let (g, tags) = load("./test.osm");
+        let report = OsmReport::new(&g, &tags);
+        assert_eq!(report.nodes, 70);
+        assert_eq!(report.edges, 40);
+        assert_eq!(report.missing_nodes, 0);
+        assert_eq!(report.nodes_outside_bounds, 0);
+        assert!(report.isolated_nodes > 0);
+        assert_eq!(report.out_degrees.values().sum::<usize>(), report.nodes);
+    }
+}
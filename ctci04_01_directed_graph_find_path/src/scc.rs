@@ -0,0 +1,316 @@
+// -----------------------------------------------------------------------------------------------
+// Module scc
+//
+// Strongly connected components of a directed graph. A strongly connected component (SCC) is a
+// maximal set of nodes in which every node can reach every other node. In a road network any
+// node outside of the biggest SCC is either a trap (you can get in but not out again, e.g. a
+// one-way street into a dead end) or unreachable from most of the network.
+//
+
+use std::collections::{HashMap, HashSet};
+
+use crate::graph::Graph;
+use crate::graphtraits::{GraphCrud, GraphView, NodeId};
+use crate::subgraph::induced_subgraph;
+
+/// The condensation of a graph: every strongly connected component is collapsed into one node.
+/// The result is always a directed acyclic graph.
+pub struct Condensation {
+    /// One node per component, the node value holds the (sorted) member nodes of the original graph.
+    /// Node IDs are assigned in topological order, i.e. edges always go from lower to higher IDs.
+    pub graph: Graph<Vec<NodeId>>,
+    /// Maps every node of the original graph to the node of its component in `graph`.
+    pub component_of: HashMap<NodeId, NodeId>,
+}
+
+/// Returns the target nodes of all out edges of `node`. Edges pointing to nodes that do not
+/// exist (anymore) are skipped.
+fn successors<T, G: GraphView<T> + ?Sized>(g: &G, node: NodeId) -> Vec<NodeId> {
+    g.find_edges_from(node)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|edge_id| g.get_edge(*edge_id))
+        .map(|edge| edge.1)
+        .filter(|to| g.get_node_val(*to).is_some())
+        .collect()
+}
+
+/// Returns the source nodes of all in edges of `node`.
+fn predecessors<T, G: GraphView<T> + ?Sized>(g: &G, node: NodeId) -> Vec<NodeId> {
+    g.find_edges_to(node)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|edge_id| g.get_edge(*edge_id))
+        .map(|edge| edge.0)
+        .filter(|from| g.get_node_val(*from).is_some())
+        .collect()
+}
+
+/// Computes the strongly connected components with Tarjan's algorithm.
+///
+/// The depth search is iterative, so it does not overflow the stack on large OSM graphs.
+///
+/// # Returns
+///
+/// The components in reverse topological order (a component is listed before every component
+/// that has an edge into it). The nodes of each component are sorted by ID.
+pub fn tarjan_scc<T, G: GraphView<T> + ?Sized>(g: &G) -> Vec<Vec<NodeId>> {
+    let mut index: HashMap<NodeId, usize> = HashMap::new();
+    let mut lowlink: HashMap<NodeId, usize> = HashMap::new();
+    let mut on_stack: HashSet<NodeId> = HashSet::new();
+    let mut stack: Vec<NodeId> = Vec::new();
+    let mut components: Vec<Vec<NodeId>> = Vec::new();
+
+    for root in g.node_ids() {
+        if index.contains_key(&root) {
+            continue;
+        }
+        // every frame holds the node, its successors and the position of the next successor to visit
+        let mut call_stack: Vec<(NodeId, Vec<NodeId>, usize)> = Vec::new();
+        index.insert(root, index.len());
+        lowlink.insert(root, index[&root]);
+        stack.push(root);
+        on_stack.insert(root);
+        call_stack.push((root, successors(g, root), 0));
+
+        while let Some((node, succ, pos)) = call_stack.last_mut() {
+            let node = *node;
+            if *pos < succ.len() {
+                let next = succ[*pos];
+                *pos += 1;
+                if !index.contains_key(&next) {
+                    index.insert(next, index.len());
+                    lowlink.insert(next, index[&next]);
+                    stack.push(next);
+                    on_stack.insert(next);
+                    call_stack.push((next, successors(g, next), 0));
+                } else if on_stack.contains(&next) {
+                    let low = lowlink[&node].min(index[&next]);
+                    lowlink.insert(node, low);
+                }
+                continue;
+            }
+
+            call_stack.pop();
+            if let Some((parent, _, _)) = call_stack.last() {
+                let low = lowlink[parent].min(lowlink[&node]);
+                lowlink.insert(*parent, low);
+            }
+            if lowlink[&node] == index[&node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack.remove(&member);
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+/// Computes the strongly connected components with Kosaraju's algorithm.
+///
+/// Gives the same components as [`tarjan_scc`] but needs `find_edges_to`, so it is a good cross
+/// check for graph implementations.
+///
+/// # Returns
+///
+/// The components in topological order. The nodes of each component are sorted by ID.
+pub fn kosaraju_scc<T, G: GraphView<T> + ?Sized>(g: &G) -> Vec<Vec<NodeId>> {
+    // Pass 1: depth search on the graph, record the nodes in the order in which they are finished
+    let mut visited: HashSet<NodeId> = HashSet::new();
+    let mut finished: Vec<NodeId> = Vec::new();
+    for root in g.node_ids() {
+        if !visited.insert(root) {
+            continue;
+        }
+        let mut call_stack: Vec<(NodeId, Vec<NodeId>, usize)> =
+            vec![(root, successors(g, root), 0)];
+        while let Some((node, succ, pos)) = call_stack.last_mut() {
+            if *pos < succ.len() {
+                let next = succ[*pos];
+                *pos += 1;
+                if visited.insert(next) {
+                    call_stack.push((next, successors(g, next), 0));
+                }
+            } else {
+                finished.push(*node);
+                call_stack.pop();
+            }
+        }
+    }
+
+    // Pass 2: depth search on the reversed graph, in reverse finishing order
+    let mut assigned: HashSet<NodeId> = HashSet::new();
+    let mut components: Vec<Vec<NodeId>> = Vec::new();
+    for root in finished.iter().rev() {
+        if !assigned.insert(*root) {
+            continue;
+        }
+        let mut component = Vec::new();
+        let mut todo = vec![*root];
+        while let Some(node) = todo.pop() {
+            component.push(node);
+            for prev in predecessors(g, node) {
+                if assigned.insert(prev) {
+                    todo.push(prev);
+                }
+            }
+        }
+        component.sort_unstable();
+        components.push(component);
+    }
+    components
+}
+
+/// Builds the condensation DAG of a graph.
+///
+/// Parallel edges between two components are merged into one edge that carries the smallest
+/// weight. Edges inside a component are dropped.
+pub fn condensation<T, G: GraphView<T> + ?Sized>(g: &G) -> Condensation {
+    let mut graph: Graph<Vec<NodeId>> = Graph::new();
+    let mut component_of: HashMap<NodeId, NodeId> = HashMap::new();
+
+    // tarjan returns the components in reverse topological order
+    for component in tarjan_scc(g).into_iter().rev() {
+        let members = component.clone();
+        let component_id = graph.new_node(component);
+        for member in members {
+            component_of.insert(member, component_id);
+        }
+    }
+
+    let mut edges: HashMap<(NodeId, NodeId), i32> = HashMap::new();
+    for edge_id in g.edge_ids() {
+        let Some(edge) = g.get_edge(edge_id) else {
+            continue;
+        };
+        let (Some(from), Some(to)) = (component_of.get(&edge.0), component_of.get(&edge.1)) else {
+            continue;
+        };
+        if from != to {
+            edges
+                .entry((*from, *to))
+                .and_modify(|w| *w = (*w).min(edge.2))
+                .or_insert(edge.2);
+        }
+    }
+    let mut edges: Vec<((NodeId, NodeId), i32)> = edges.into_iter().collect();
+    edges.sort_unstable();
+    for ((from, to), weight) in edges {
+        graph.new_edge(from, to, weight);
+    }
+
+    Condensation {
+        graph,
+        component_of,
+    }
+}
+
+/// Copies the largest strongly connected component into a new graph that can be used for routing:
+/// within the result every node can reach every other node.
+///
+/// Node IDs are kept, edge IDs are newly assigned. If several components have the same size the
+/// one with the smallest node ID is taken.
+pub fn largest_scc_subgraph<T: Clone, G: GraphView<T> + ?Sized>(g: &G) -> Graph<T> {
+    let mut largest: Vec<NodeId> = Vec::new();
+    for component in tarjan_scc(g) {
+        if component.len() > largest.len()
+            || (component.len() == largest.len() && component.first() < largest.first())
+        {
+            largest = component;
+        }
+    }
+    induced_subgraph(g, &largest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphtraits::GraphAlgo;
+
+    /// The sample graph from main.rs: {1, 2, 3, 5} and {4, 6} are the strongly connected components
+    fn sample_graph() -> Graph<i32> {
+        let mut g: Graph<i32> = Graph::new();
+        let n: Vec<NodeId> = (1..=6).map(|v| g.new_node(v)).collect();
+        let edges = [
+            (0, 1, 1),
+            (1, 2, 2),
+            (2, 3, 3),
+            (2, 4, 4),
+            (3, 5, 5),
+            (2, 0, 6),
+            (0, 4, 7),
+            (1, 4, 8),
+            (4, 1, 9),
+            (5, 3, 10),
+        ];
+        for (from, to, weight) in edges {
+            g.new_edge(n[from], n[to], weight);
+        }
+        g
+    }
+
+    #[test]
+    fn test_tarjan_scc() {
+        let g = sample_graph();
+        let components = g.strongly_connected_components();
+        // reverse topological order: the sink component comes first
+        assert_eq!(components, vec![vec![4, 6], vec![1, 2, 3, 5]]);
+    }
+
+    #[test]
+    fn test_kosaraju_matches_tarjan() {
+        let g = sample_graph();
+        let mut tarjan = tarjan_scc(&g);
+        let mut kosaraju = kosaraju_scc(&g);
+        tarjan.sort();
+        kosaraju.sort();
+        assert_eq!(tarjan, kosaraju);
+    }
+
+    #[test]
+    fn test_single_nodes_are_components() {
+        let mut g: Graph<i32> = Graph::new();
+        let a = g.new_node(1);
+        let b = g.new_node(2);
+        let c = g.new_node(3);
+        g.new_edge(a, b, 1);
+        g.new_edge(b, c, 1);
+        assert_eq!(tarjan_scc(&g), vec![vec![c], vec![b], vec![a]]);
+        assert_eq!(kosaraju_scc(&g), vec![vec![a], vec![b], vec![c]]);
+    }
+
+    #[test]
+    fn test_condensation() {
+        let g = sample_graph();
+        let cond = g.condensation();
+        assert_eq!(cond.graph.node_ids(), vec![1, 2]);
+        assert_eq!(cond.graph.get_node_val(1), Some(&vec![1, 2, 3, 5]));
+        assert_eq!(cond.graph.get_node_val(2), Some(&vec![4, 6]));
+        assert_eq!(cond.component_of[&5], 1);
+        assert_eq!(cond.component_of[&6], 2);
+
+        // the only edge between the two components is 3 -> 4 with weight 3
+        let edges = cond.graph.edge_ids();
+        assert_eq!(edges.len(), 1);
+        let edge = cond.graph.get_edge(edges[0]).unwrap();
+        assert_eq!((edge.0, edge.1, edge.2), (1, 2, 3));
+    }
+
+    #[test]
+    fn test_largest_scc_subgraph() {
+        let g = sample_graph();
+        let sub = g.largest_scc_subgraph();
+        assert_eq!(sub.node_ids(), vec![1, 2, 3, 5]);
+        assert_eq!(sub.get_node_val(5), Some(&5));
+        // 1->2, 2->3, 3->5, 3->1, 1->5, 2->5, 5->2
+        assert_eq!(sub.edge_ids().len(), 7);
+        assert_eq!(tarjan_scc(&sub).len(), 1);
+    }
+}
@@ -0,0 +1,170 @@
+// -----------------------------------------------------------------------------------------------
+// Module sharedgraph
+//
+// A graph that can be shared between threads: a frozen CsrGraph behind an Arc. It is Send + Sync
+// as long as the node values are, cloning it only clones the Arc. Meant for a service that loads
+// the OSM graph once and answers routing requests from worker threads:
+//
+//   let shared = osmgraph.share();
+//   let paths = shared.shortest_paths(&queries, 0);
+//
+// All GraphView and GraphAlgo methods are available through Deref to the CsrGraph, generic
+// algorithms take `&*shared`.
+//
+
+use std::ops::Deref;
+use std::sync::Arc;
+use std::thread;
+
+use crate::csrgraph::CsrGraph;
+use crate::graph::Graph;
+use crate::graphtraits::{GraphAlgo, GraphView, NodeId};
+use crate::shortest_path::WeightedPath;
+
+pub struct SharedGraph<T> {
+    graph: Arc<CsrGraph<T>>,
+}
+
+impl<T> Clone for SharedGraph<T> {
+    fn clone(&self) -> Self {
+        SharedGraph {
+            graph: Arc::clone(&self.graph),
+        }
+    }
+}
+
+impl<T> From<CsrGraph<T>> for SharedGraph<T> {
+    fn from(graph: CsrGraph<T>) -> Self {
+        SharedGraph {
+            graph: Arc::new(graph),
+        }
+    }
+}
+
+impl<T> Deref for SharedGraph<T> {
+    type Target = CsrGraph<T>;
+
+    fn deref(&self) -> &CsrGraph<T> {
+        &self.graph
+    }
+}
+
+impl<T: Clone> Graph<T> {
+    /// Freezes the graph and wraps it for sharing between threads.
+    pub fn share(&self) -> SharedGraph<T> {
+        self.freeze().into()
+    }
+}
+
+impl<T: Sync> SharedGraph<T> {
+    /// Runs `query` for every (from, to) pair of `queries` on `threads` threads, 0 means one
+    /// thread per available CPU. The queries are split into one contiguous chunk per thread.
+    ///
+    /// # Returns
+    ///
+    /// The results in the order of the queries.
+    pub fn batch<R, F>(&self, queries: &[(NodeId, NodeId)], threads: usize, query: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(&CsrGraph<T>, NodeId, NodeId) -> R + Sync,
+    {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let chunk_size = queries.len().div_ceil(threads).max(1);
+        let graph: &CsrGraph<T> = &self.graph;
+        let query = &query;
+        thread::scope(|scope| {
+            let workers: Vec<_> = queries
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|(from, to)| query(graph, *from, *to))
+                            .collect::<Vec<R>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// Shortest paths for many (from, to) pairs in parallel, see [`SharedGraph::batch`].
+    pub fn shortest_paths(
+        &self,
+        queries: &[(NodeId, NodeId)],
+        threads: usize,
+    ) -> Vec<Option<WeightedPath>> {
+        self.batch(queries, threads, |g, from, to| {
+            let edges = g.shortest_path(from, to)?;
+            let weight = edges
+                .iter()
+                .filter_map(|edge_id| g.get_edge(*edge_id))
+                .map(|edge| edge.2 as i64)
+                .sum();
+            Some(WeightedPath { weight, edges })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{Rng, grid};
+    use crate::osmgraph::OsmNode;
+    use crate::shortest_path::dijkstra;
+
+    fn assert_send_sync<S: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<SharedGraph<i32>>();
+        assert_send_sync::<SharedGraph<OsmNode>>();
+    }
+
+    #[test]
+    fn test_shortest_paths() {
+        let g: Graph<usize> = grid(10, 10, 1..=9, &mut Rng::new(11), |i| i);
+        let shared = g.share();
+        let nodes = g.node_ids();
+        let mut rng = Rng::new(12);
+        let queries: Vec<(NodeId, NodeId)> = (0..200)
+            .map(|_| (nodes[rng.below(100)], nodes[rng.below(100)]))
+            .collect();
+        let expected: Vec<Option<i64>> = queries
+            .iter()
+            .map(|(from, to)| dijkstra(&g, *from, *to).map(|p| p.weight))
+            .collect();
+        for threads in [0, 1, 3, 500] {
+            let weights: Vec<Option<i64>> = shared
+                .shortest_paths(&queries, threads)
+                .into_iter()
+                .map(|p| p.map(|p| p.weight))
+                .collect();
+            assert_eq!(weights, expected);
+        }
+        assert!(shared.shortest_paths(&[], 4).is_empty());
+        assert_eq!(shared.shortest_paths(&[(nodes[0], 1000)], 4), vec![None]);
+    }
+
+    #[test]
+    fn test_worker_threads() {
+        let g: Graph<usize> = grid(5, 5, 1..=1, &mut Rng::new(1), |i| i);
+        let shared = g.share();
+        let (from, to) = (g.node_ids()[0], g.node_ids()[24]);
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || shared.path_exists(from, to))
+            })
+            .collect();
+        assert!(workers.into_iter().all(|w| w.join().unwrap()));
+        let exists = shared.batch(&[(from, to), (to, from)], 2, |g, a, b| g.path_exists(a, b));
+        assert_eq!(exists, vec![true, true]);
+    }
+}
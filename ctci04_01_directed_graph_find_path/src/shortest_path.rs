@@ -0,0 +1,484 @@
+// -----------------------------------------------------------------------------------------------
+// Module shortest_path
+//
+// Dijkstra's and A* shortest path and Yen's k shortest (loopless) paths. They expect non negative
+// edge weights, which is what the OSM import produces (distances). Also the breadth first search
+// for the path with the fewest edges and the enumeration of all simple paths between two nodes.
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::graphtraits::{EdgeId, GraphView, NodeId, Path};
+
+/// A path together with its total weight.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WeightedPath {
+    pub weight: i64,
+    pub edges: Path,
+}
+
+/// Dijkstra from `from` to `to` that is not allowed to use the given nodes and edges.
+///
+/// # Returns
+///
+/// The shortest path or `None` if `to` cannot be reached. The path from a node to itself is
+/// the empty path.
+pub fn dijkstra_excluding<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    from: NodeId,
+    to: NodeId,
+    blocked_nodes: &HashSet<NodeId>,
+    blocked_edges: &HashSet<EdgeId>,
+) -> Option<WeightedPath> {
+    g.get_node_val(from)?;
+    g.get_node_val(to)?;
+    if blocked_nodes.contains(&from) || blocked_nodes.contains(&to) {
+        return None;
+    }
+
+    let mut dist: HashMap<NodeId, i64> = HashMap::from([(from, 0)]);
+    let mut pred: HashMap<NodeId, EdgeId> = HashMap::new();
+    let mut done: HashSet<NodeId> = HashSet::new();
+    let mut heap = BinaryHeap::from([Reverse((0i64, from))]);
+    while let Some(Reverse((d, node))) = heap.pop() {
+        if !done.insert(node) {
+            continue;
+        }
+        if node == to {
+            break;
+        }
+        for edge_id in g.find_edges_from(node).unwrap_or_default() {
+            if blocked_edges.contains(&edge_id) {
+                continue;
+            }
+            let Some(edge) = g.get_edge(edge_id) else {
+                continue;
+            };
+            let next = edge.1;
+            if blocked_nodes.contains(&next) || done.contains(&next) {
+                continue;
+            }
+            if g.get_node_val(next).is_none() {
+                continue;
+            }
+            let nd = d + edge.2 as i64;
+            // ties are broken by the edge ID so that results are reproducible
+            let better = match dist.get(&next) {
+                None => true,
+                Some(old) => nd < *old || (nd == *old && edge_id < pred[&next]),
+            };
+            if better {
+                dist.insert(next, nd);
+                pred.insert(next, edge_id);
+                heap.push(Reverse((nd, next)));
+            }
+        }
+    }
+
+    let weight = *dist.get(&to)?;
+    let edges = trace_back(g, from, to, &pred)?;
+    Some(WeightedPath { weight, edges })
+}
+
+/// Follows the predecessor edges from `to` back to `from`.
+fn trace_back<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    from: NodeId,
+    to: NodeId,
+    pred: &HashMap<NodeId, EdgeId>,
+) -> Option<Path> {
+    let mut edges = Vec::new();
+    let mut node = to;
+    while node != from {
+        let edge_id = *pred.get(&node)?;
+        edges.push(edge_id);
+        node = g.get_edge(edge_id)?.0;
+    }
+    edges.reverse();
+    Some(edges)
+}
+
+/// Dijkstra's shortest path from `from` to `to`, see [`dijkstra_excluding`].
+pub fn dijkstra<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    from: NodeId,
+    to: NodeId,
+) -> Option<WeightedPath> {
+    dijkstra_excluding(g, from, to, &HashSet::new(), &HashSet::new())
+}
+
+/// A* search from `from` to `to`. `heuristic` estimates the remaining distance from a node to
+/// `to`, e.g. the straight line distance when the edge weights are geographic distances.
+///
+/// The heuristic has to be consistent: 0 for `to` and along every edge it may not drop by more
+/// than the weight of the edge. With `|_| 0` this is Dijkstra's algorithm.
+///
+/// # Returns
+///
+/// The shortest path or `None` if `to` cannot be reached.
+pub fn astar<T, G: GraphView<T> + ?Sized, H: Fn(NodeId) -> i64>(
+    g: &G,
+    from: NodeId,
+    to: NodeId,
+    heuristic: H,
+) -> Option<WeightedPath> {
+    g.get_node_val(from)?;
+    g.get_node_val(to)?;
+
+    let mut dist: HashMap<NodeId, i64> = HashMap::from([(from, 0)]);
+    let mut pred: HashMap<NodeId, EdgeId> = HashMap::new();
+    let mut done: HashSet<NodeId> = HashSet::new();
+    let mut heap = BinaryHeap::from([Reverse((heuristic(from), from))]);
+    while let Some(Reverse((_, node))) = heap.pop() {
+        if !done.insert(node) {
+            continue;
+        }
+        if node == to {
+            break;
+        }
+        let d = dist[&node];
+        for edge_id in g.find_edges_from(node).unwrap_or_default() {
+            let Some(edge) = g.get_edge(edge_id) else {
+                continue;
+            };
+            let next = edge.1;
+            if done.contains(&next) || g.get_node_val(next).is_none() {
+                continue;
+            }
+            let nd = d + edge.2 as i64;
+            let better = match dist.get(&next) {
+                None => true,
+                Some(old) => nd < *old || (nd == *old && edge_id < pred[&next]),
+            };
+            if better {
+                dist.insert(next, nd);
+                pred.insert(next, edge_id);
+                heap.push(Reverse((nd + heuristic(next), next)));
+            }
+        }
+    }
+
+    let weight = *dist.get(&to)?;
+    let edges = trace_back(g, from, to, &pred)?;
+    Some(WeightedPath { weight, edges })
+}
+
+/// Breadth first search for the path with the fewest edges from `from` to `to`, the edge weights
+/// are ignored. Edges are visited in the order of their IDs, so that the result is reproducible.
+///
+/// # Returns
+///
+/// The path or `None` if `to` cannot be reached.
+pub fn bfs<T, G: GraphView<T> + ?Sized>(g: &G, from: NodeId, to: NodeId) -> Option<Path> {
+    g.get_node_val(from)?;
+    g.get_node_val(to)?;
+
+    let mut pred: HashMap<NodeId, EdgeId> = HashMap::new();
+    let mut visited: HashSet<NodeId> = HashSet::from([from]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            return trace_back(g, from, to, &pred);
+        }
+        let mut edges = g.find_edges_from(node).unwrap_or_default();
+        edges.sort_unstable();
+        for edge_id in edges {
+            let Some(edge) = g.get_edge(edge_id) else {
+                continue;
+            };
+            if g.get_node_val(edge.1).is_none() || !visited.insert(edge.1) {
+                continue;
+            }
+            pred.insert(edge.1, edge_id);
+            queue.push_back(edge.1);
+        }
+    }
+    None
+}
+
+/// Enumerates all simple (loopless) paths from `from` to `to` with a depth search.
+///
+/// The number of paths grows exponentially with the size of the graph, so this is only meant for
+/// small graphs.
+///
+/// # Returns
+///
+/// `None` if one of the nodes does not exist, otherwise the paths ordered by their edge IDs.
+pub fn all_simple_paths<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    from: NodeId,
+    to: NodeId,
+) -> Option<Vec<Path>> {
+    g.get_node_val(from)?;
+    g.get_node_val(to)?;
+    let mut paths = Vec::new();
+    let mut on_path: HashSet<NodeId> = HashSet::from([from]);
+    let mut path = Vec::new();
+    collect_paths(g, from, to, &mut on_path, &mut path, &mut paths);
+    paths.sort();
+    Some(paths)
+}
+
+fn collect_paths<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    node: NodeId,
+    to: NodeId,
+    on_path: &mut HashSet<NodeId>,
+    path: &mut Path,
+    paths: &mut Vec<Path>,
+) {
+    if node == to {
+        paths.push(path.clone());
+        return;
+    }
+    for edge_id in g.find_edges_from(node).unwrap_or_default() {
+        let Some(edge) = g.get_edge(edge_id) else {
+            continue;
+        };
+        if g.get_node_val(edge.1).is_none() || !on_path.insert(edge.1) {
+            continue;
+        }
+        path.push(edge_id);
+        collect_paths(g, edge.1, to, on_path, path, paths);
+        path.pop();
+        on_path.remove(&edge.1);
+    }
+}
+
+/// Returns the nodes visited by a path that starts in `from`.
+fn path_nodes<T, G: GraphView<T> + ?Sized>(g: &G, from: NodeId, path: &Path) -> Vec<NodeId> {
+    let mut nodes = vec![from];
+    for edge_id in path {
+        if let Some(edge) = g.get_edge(*edge_id) {
+            nodes.push(edge.1);
+        }
+    }
+    nodes
+}
+
+/// Yen's algorithm: the `k` shortest loopless paths from `from` to `to`.
+///
+/// Every further path is found by deviating from one of the paths found so far at a "spur" node,
+/// while blocking the edges used by the known paths at that point and the nodes of the common
+/// prefix (which keeps the paths loopless).
+///
+/// # Returns
+///
+/// Up to `k` different paths, ordered by weight (paths of equal weight by their edge IDs).
+pub fn k_shortest_paths<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    from: NodeId,
+    to: NodeId,
+    k: usize,
+) -> Vec<WeightedPath> {
+    let mut found: Vec<WeightedPath> = Vec::new();
+    if k == 0 {
+        return found;
+    }
+    let Some(first) = dijkstra(g, from, to) else {
+        return found;
+    };
+    found.push(first);
+    let mut candidates: BinaryHeap<Reverse<WeightedPath>> = BinaryHeap::new();
+    let mut known: HashSet<Path> = HashSet::from([found[0].edges.clone()]);
+
+    while found.len() < k {
+        let previous = found.last().unwrap().clone();
+        let nodes = path_nodes(g, from, &previous.edges);
+        for i in 0..previous.edges.len() {
+            let spur_node = nodes[i];
+            let root_path = &previous.edges[..i];
+
+            let blocked_edges: HashSet<EdgeId> = found
+                .iter()
+                .filter(|p| p.edges.len() > i && p.edges[..i] == *root_path)
+                .map(|p| p.edges[i])
+                .collect();
+            let blocked_nodes: HashSet<NodeId> = nodes[..i].iter().copied().collect();
+
+            let Some(spur) = dijkstra_excluding(g, spur_node, to, &blocked_nodes, &blocked_edges)
+            else {
+                continue;
+            };
+            let mut edges = root_path.to_vec();
+            edges.extend(spur.edges);
+            if known.insert(edges.clone()) {
+                let root_weight: i64 = root_path
+                    .iter()
+                    .filter_map(|edge_id| g.get_edge(*edge_id))
+                    .map(|edge| edge.2 as i64)
+                    .sum();
+                candidates.push(Reverse(WeightedPath {
+                    weight: root_weight + spur.weight,
+                    edges,
+                }));
+            }
+        }
+        match candidates.pop() {
+            Some(Reverse(path)) => found.push(path),
+            None => break,
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphAlgo;
+    use crate::graphtraits::GraphCrud;
+
+    /// The classic example from the Wikipedia article on Yen's algorithm, C=1 ... H=6
+    fn yen_graph() -> (Graph<char>, HashMap<char, NodeId>) {
+        let mut g: Graph<char> = Graph::new();
+        let ids: HashMap<char, NodeId> = "CDEFGH".chars().map(|c| (c, g.new_node(c))).collect();
+        let edges = [
+            ('C', 'D', 3),
+            ('C', 'E', 2),
+            ('D', 'F', 4),
+            ('E', 'D', 1),
+            ('E', 'F', 2),
+            ('E', 'G', 3),
+            ('F', 'G', 2),
+            ('F', 'H', 1),
+            ('G', 'H', 2),
+        ];
+        for (from, to, weight) in edges {
+            g.new_edge(ids[&from], ids[&to], weight);
+        }
+        (g, ids)
+    }
+
+    fn node_string(g: &Graph<char>, from: NodeId, path: &WeightedPath) -> String {
+        path_nodes(g, from, &path.edges)
+            .iter()
+            .map(|id| *g.get_node_val(*id).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let (g, ids) = yen_graph();
+        let path = g.shortest_path(ids[&'C'], ids[&'H']).unwrap();
+        assert_eq!(path.len(), 3);
+        assert_eq!(
+            node_string(&g, ids[&'C'], &dijkstra(&g, ids[&'C'], ids[&'H']).unwrap()),
+            "CEFH"
+        );
+        assert_eq!(g.shortest_path(ids[&'H'], ids[&'C']), None);
+        assert_eq!(g.shortest_path(ids[&'C'], ids[&'C']), Some(vec![]));
+    }
+
+    #[test]
+    fn test_bfs() {
+        let (g, ids) = yen_graph();
+        let path = bfs(&g, ids[&'C'], ids[&'H']).unwrap();
+        // C -> D -> F -> H comes first by edge ID among the paths with 3 edges
+        assert_eq!(
+            node_string(
+                &g,
+                ids[&'C'],
+                &WeightedPath {
+                    weight: 0,
+                    edges: path
+                }
+            ),
+            "CDFH"
+        );
+        assert_eq!(bfs(&g, ids[&'H'], ids[&'C']), None);
+        assert_eq!(bfs(&g, ids[&'C'], ids[&'C']), Some(vec![]));
+    }
+
+    #[test]
+    fn test_astar_on_grid() {
+        // 4 x 4 grid with edges in both directions, weight 2 horizontally and 3 vertically
+        let mut g: Graph<(i64, i64)> = Graph::new();
+        let mut ids = HashMap::new();
+        for y in 0..4 {
+            for x in 0..4 {
+                ids.insert((x, y), g.new_node((x, y)));
+            }
+        }
+        for y in 0..4 {
+            for x in 0..4 {
+                if x < 3 {
+                    g.new_edge(ids[&(x, y)], ids[&(x + 1, y)], 2);
+                    g.new_edge(ids[&(x + 1, y)], ids[&(x, y)], 2);
+                }
+                if y < 3 {
+                    g.new_edge(ids[&(x, y)], ids[&(x, y + 1)], 3);
+                    g.new_edge(ids[&(x, y + 1)], ids[&(x, y)], 3);
+                }
+            }
+        }
+        let (from, to) = (ids[&(0, 0)], ids[&(3, 2)]);
+        let manhattan = |node: NodeId| {
+            let (x, y) = g.get_node_val(node).unwrap();
+            2 * (3 - x).abs() + 2 * (2 - y).abs()
+        };
+        let path = astar(&g, from, to, manhattan).unwrap();
+        assert_eq!(path.weight, 12);
+        assert_eq!(path.weight, dijkstra(&g, from, to).unwrap().weight);
+        assert_eq!(astar(&g, from, to, |_| 0), dijkstra(&g, from, to));
+        assert_eq!(astar(&g, from, 1000, |_| 0), None);
+    }
+
+    #[test]
+    fn test_all_paths() {
+        let (g, ids) = yen_graph();
+        let paths = g.all_paths(ids[&'C'], ids[&'H']).unwrap();
+        assert_eq!(paths.len(), 7);
+        assert_eq!(g.all_paths(ids[&'H'], ids[&'C']), Some(vec![]));
+        assert_eq!(g.all_paths(ids[&'C'], ids[&'C']), Some(vec![vec![]]));
+        assert_eq!(g.all_paths(ids[&'C'], 1000), None);
+    }
+
+    #[test]
+    fn test_all_simple_paths_skip_cycles() {
+        // a <-> b -> c, a -> c and a self loop on b
+        let mut g: Graph<char> = Graph::new();
+        let (a, b, c) = (g.new_node('a'), g.new_node('b'), g.new_node('c'));
+        let ab = g.new_edge(a, b, 1);
+        g.new_edge(b, a, 1);
+        g.new_edge(b, b, 1);
+        let bc = g.new_edge(b, c, 1);
+        let ac = g.new_edge(a, c, 1);
+        assert_eq!(
+            all_simple_paths(&g, a, c),
+            Some(vec![vec![ab, bc], vec![ac]])
+        );
+        assert_eq!(all_simple_paths(&g, b, b), Some(vec![vec![]]));
+    }
+
+    #[test]
+    fn test_k_shortest_paths() {
+        let (g, ids) = yen_graph();
+        let paths = g.k_shortest_paths(ids[&'C'], ids[&'H'], 3);
+        let result: Vec<(String, i64)> = paths
+            .iter()
+            .map(|p| (node_string(&g, ids[&'C'], p), p.weight))
+            .collect();
+        assert_eq!(
+            result,
+            vec![
+                ("CEFH".to_string(), 5),
+                ("CEGH".to_string(), 7),
+                ("CDFH".to_string(), 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_k_larger_than_number_of_paths() {
+        let (g, ids) = yen_graph();
+        let paths = g.k_shortest_paths(ids[&'C'], ids[&'H'], 100);
+        // C to H has 7 loopless paths
+        assert_eq!(paths.len(), 7);
+        assert!(paths.windows(2).all(|w| w[0].weight <= w[1].weight));
+        let unique: HashSet<&Path> = paths.iter().map(|p| &p.edges).collect();
+        assert_eq!(unique.len(), 7);
+        assert!(g.k_shortest_paths(ids[&'H'], ids[&'C'], 3).is_empty());
+    }
+}
@@ -0,0 +1,558 @@
+// -----------------------------------------------------------------------------------------------
+// Module snapshot
+//
+// A compact binary format for parsed OSM graphs, so that a large extract is parsed from XML only
+// once and afterwards loaded in a fraction of the time.
+//
+// All numbers are little endian. The file starts with the magic bytes "OSMGRAPH", the format
+// version (u32) and the number of sections (u32). Every section is
+//
+//   kind u32, payload length u64, CRC-32 of the payload u32, payload
+//
+// with the kinds
+//
+//   1 nodes    count u64, per node: id u64, lat f64, lon f64, version i32
+//   2 edges    count u64, per edge: id u64, from u64, to u64, weight i32, way i64 (i64::MIN: none)
+//   3 strings  count u64, per string: length u32, UTF-8 bytes
+//   4 tags     count u64, per element: node (0) or way (1) u8, id u64 or i64, number of tags u32,
+//              per tag: key u32, value u32 (indices into the strings)
+//   5 turn restrictions
+//              count u64, per restriction: relation id u64, restriction u32 (a string), from way
+//              i64, via node (0) or way (1) u8, via u64 or i64, to way i64
+//   6 import   has bounds u8 (0 or 1), min lat f64, min lon f64, max lat f64, max lon f64, count
+//              u64, per reference to a node that is not in the file: way i64, node u64
+//
+// Node and edge IDs are kept. Readers skip sections of unknown kinds, changes that old readers
+// cannot handle get a new version.
+//
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path as FsPath;
+
+use derive_more::Display;
+
+use crate::graph::Graph;
+use crate::graphtraits::{EdgeTriplet, GraphCrud, GraphView, NodeId};
+use crate::osmgraph::{Bounds, OsmNode, OsmTags, Tags, TurnRestriction, Via, WayId};
+
+pub const MAGIC: &[u8; 8] = b"OSMGRAPH";
+pub const VERSION: u32 = 2;
+
+const NODES: u32 = 1;
+const EDGES: u32 = 2;
+const STRINGS: u32 = 3;
+const TAGS: u32 = 4;
+const RESTRICTIONS: u32 = 5;
+const IMPORT: u32 = 6;
+const NO_WAY: i64 = i64::MIN;
+
+#[derive(Debug, Display)]
+pub enum SnapshotError {
+    #[display("I/O error: {_0}")]
+    Io(io::Error),
+    #[display("not an OSM graph snapshot")]
+    BadMagic,
+    #[display("unsupported snapshot version {_0}")]
+    UnsupportedVersion(u32),
+    #[display("checksum mismatch in section {_0}")]
+    Checksum(u32),
+    #[display("snapshot is truncated")]
+    Truncated,
+    #[display("invalid snapshot: {_0}")]
+    Invalid(String),
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 (IEEE, as used by zip and PNG).
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc = CRC_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Collects the strings of the tags, every string is stored once.
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+    index: HashMap<String, u32>,
+}
+
+impl StringTable {
+    fn add(&mut self, s: &str) -> u32 {
+        if let Some(i) = self.index.get(s) {
+            return *i;
+        }
+        let i = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.index.insert(s.to_string(), i);
+        i
+    }
+}
+
+fn write_section<W: Write>(w: &mut W, kind: u32, payload: &[u8]) -> io::Result<()> {
+    w.write_all(&kind.to_le_bytes())?;
+    w.write_all(&(payload.len() as u64).to_le_bytes())?;
+    w.write_all(&crc32(payload).to_le_bytes())?;
+    w.write_all(payload)
+}
+
+/// Writes the graph and its tags as a snapshot.
+pub fn write_snapshot<W: Write, G: GraphView<OsmNode> + ?Sized>(
+    w: &mut W,
+    g: &G,
+    tags: &OsmTags,
+) -> io::Result<()> {
+    let node_ids = g.node_ids();
+    let mut nodes = Vec::with_capacity(8 + node_ids.len() * 28);
+    nodes.extend((node_ids.len() as u64).to_le_bytes());
+    for id in node_ids.iter() {
+        let node = g.get_node_val(*id).unwrap();
+        nodes.extend((*id as u64).to_le_bytes());
+        nodes.extend(node.lat.to_le_bytes());
+        nodes.extend(node.lon.to_le_bytes());
+        nodes.extend(node.version.to_le_bytes());
+    }
+
+    let edge_ids = g.edge_ids();
+    let mut edges = Vec::with_capacity(8 + edge_ids.len() * 36);
+    edges.extend((edge_ids.len() as u64).to_le_bytes());
+    for id in edge_ids.iter() {
+        let EdgeTriplet(from, to, weight) = g.get_edge(*id).unwrap();
+        edges.extend((*id as u64).to_le_bytes());
+        edges.extend((from as u64).to_le_bytes());
+        edges.extend((to as u64).to_le_bytes());
+        edges.extend(weight.to_le_bytes());
+        let way = tags.edge_way.get(id).copied().unwrap_or(NO_WAY);
+        edges.extend(way.to_le_bytes());
+    }
+
+    let mut strings = StringTable::default();
+    // the IDs as their bytes, way IDs are signed
+    let mut elements: Vec<(u8, u64, &Tags)> = tags
+        .nodes
+        .iter()
+        .map(|(id, t)| (0, *id as u64, t))
+        .chain(tags.ways.iter().map(|(id, t)| (1, *id as u64, t)))
+        .collect();
+    elements.sort_by_key(|(kind, id, _)| (*kind, *id));
+    let mut tag_section = Vec::new();
+    tag_section.extend((elements.len() as u64).to_le_bytes());
+    for (kind, id, element_tags) in elements {
+        tag_section.push(kind);
+        tag_section.extend(id.to_le_bytes());
+        tag_section.extend((element_tags.len() as u32).to_le_bytes());
+        for (k, v) in element_tags {
+            tag_section.extend(strings.add(k).to_le_bytes());
+            tag_section.extend(strings.add(v).to_le_bytes());
+        }
+    }
+    let mut restriction_section = Vec::new();
+    restriction_section.extend((tags.restrictions.len() as u64).to_le_bytes());
+    for restriction in tags.restrictions.iter() {
+        let (via_kind, via) = match restriction.via {
+            Via::Node(id) => (0u8, id as u64),
+            Via::Way(id) => (1u8, id as u64),
+        };
+        restriction_section.extend((restriction.id as u64).to_le_bytes());
+        restriction_section.extend(strings.add(&restriction.restriction).to_le_bytes());
+        restriction_section.extend(restriction.from.to_le_bytes());
+        restriction_section.push(via_kind);
+        restriction_section.extend(via.to_le_bytes());
+        restriction_section.extend(restriction.to.to_le_bytes());
+    }
+    let mut import_section = Vec::new();
+    let bounds = tags.bounds.unwrap_or(Bounds {
+        min_lat: 0.0,
+        min_lon: 0.0,
+        max_lat: 0.0,
+        max_lon: 0.0,
+    });
+    import_section.push(tags.bounds.is_some() as u8);
+    for value in [
+        bounds.min_lat,
+        bounds.min_lon,
+        bounds.max_lat,
+        bounds.max_lon,
+    ] {
+        import_section.extend(value.to_le_bytes());
+    }
+    import_section.extend((tags.missing_nodes.len() as u64).to_le_bytes());
+    for (way, node) in tags.missing_nodes.iter() {
+        import_section.extend(way.to_le_bytes());
+        import_section.extend((*node as u64).to_le_bytes());
+    }
+    let mut string_section = Vec::new();
+    string_section.extend((strings.strings.len() as u64).to_le_bytes());
+    for s in strings.strings.iter() {
+        string_section.extend((s.len() as u32).to_le_bytes());
+        string_section.extend(s.as_bytes());
+    }
+
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&6u32.to_le_bytes())?;
+    write_section(w, NODES, &nodes)?;
+    write_section(w, EDGES, &edges)?;
+    write_section(w, STRINGS, &string_section)?;
+    write_section(w, TAGS, &tag_section)?;
+    write_section(w, RESTRICTIONS, &restriction_section)?;
+    write_section(w, IMPORT, &import_section)
+}
+
+/// Writes a snapshot file, see [`write_snapshot`].
+pub fn save_snapshot<P: AsRef<FsPath>, G: GraphView<OsmNode> + ?Sized>(
+    path: P,
+    g: &G,
+    tags: &OsmTags,
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_snapshot(&mut w, g, tags)?;
+    w.flush()
+}
+
+/// Reads numbers from a byte slice, running out of bytes is a truncated snapshot.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < n {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, SnapshotError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, SnapshotError> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    /// A count of records of `size` bytes, checked against the remaining bytes so that a broken
+    /// count cannot make the loader allocate huge amounts of memory.
+    fn count(&mut self, size: usize) -> Result<usize, SnapshotError> {
+        let count = self.u64()? as usize;
+        match count.checked_mul(size) {
+            Some(total) if total <= self.bytes.len() => Ok(count),
+            _ => Err(SnapshotError::Truncated),
+        }
+    }
+}
+
+fn lookup(strings: &[String], i: u32) -> Result<String, SnapshotError> {
+    strings
+        .get(i as usize)
+        .cloned()
+        .ok_or_else(|| SnapshotError::Invalid(format!("unknown string {}", i)))
+}
+
+/// Loads a snapshot from memory.
+pub fn read_snapshot(bytes: &[u8]) -> Result<(Graph<OsmNode>, OsmTags), SnapshotError> {
+    let mut r = Reader { bytes };
+    if r.take(MAGIC.len()).map_err(|_| SnapshotError::BadMagic)? != MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+    let version = r.u32()?;
+    if version != VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    let section_count = r.u32()?;
+
+    let mut g: Graph<OsmNode> = Graph::new();
+    let mut tags = OsmTags::default();
+    let mut strings: Vec<String> = Vec::new();
+    let mut seen = Vec::new();
+    for _ in 0..section_count {
+        let kind = r.u32()?;
+        let length = r.u64()? as usize;
+        let checksum = r.u32()?;
+        let payload = r.take(length)?;
+        if crc32(payload) != checksum {
+            return Err(SnapshotError::Checksum(kind));
+        }
+        let mut s = Reader { bytes: payload };
+        match kind {
+            NODES => {
+                let count = s.count(28)?;
+                g.reserve(count, 0);
+                for _ in 0..count {
+                    let id = s.u64()? as NodeId;
+                    let (lat, lon, version) = (s.f64()?, s.f64()?, s.i32()?);
+                    g.set_node(
+                        OsmNode {
+                            id,
+                            lat,
+                            lon,
+                            version,
+                        },
+                        id,
+                    );
+                }
+            }
+            EDGES => {
+                let count = s.count(36)?;
+                g.reserve(0, count);
+                tags.edge_way.reserve(count);
+                for _ in 0..count {
+                    let id = s.u64()? as usize;
+                    let (from, to) = (s.u64()? as NodeId, s.u64()? as NodeId);
+                    let weight = s.i32()?;
+                    g.restore_edge(id, EdgeTriplet(from, to, weight));
+                    let way = s.i64()?;
+                    if way != NO_WAY {
+                        tags.edge_way.insert(id, way);
+                    }
+                }
+            }
+            STRINGS => {
+                let count = s.count(4)?;
+                strings.reserve(count);
+                for _ in 0..count {
+                    let len = s.u32()? as usize;
+                    let text = std::str::from_utf8(s.take(len)?)
+                        .map_err(|_| SnapshotError::Invalid("string is not UTF-8".into()))?;
+                    strings.push(text.to_string());
+                }
+            }
+            TAGS => {
+                let string = |i: u32| lookup(&strings, i);
+                let count = s.count(13)?;
+                for _ in 0..count {
+                    let element = s.u8()?;
+                    let id = s.u64()?;
+                    let mut element_tags = Tags::new();
+                    for _ in 0..s.u32()? {
+                        let (k, v) = (s.u32()?, s.u32()?);
+                        element_tags.insert(string(k)?, string(v)?);
+                    }
+                    match element {
+                        0 => tags.nodes.insert(id as NodeId, element_tags),
+                        1 => tags.ways.insert(id as WayId, element_tags),
+                        other => {
+                            return Err(SnapshotError::Invalid(format!(
+                                "unknown element type {}",
+                                other
+                            )));
+                        }
+                    };
+                }
+            }
+            RESTRICTIONS => {
+                let count = s.count(37)?;
+                tags.restrictions.reserve(count);
+                for _ in 0..count {
+                    let id = s.u64()? as usize;
+                    let restriction = lookup(&strings, s.u32()?)?;
+                    let from = s.i64()?;
+                    let via = match (s.u8()?, s.u64()?) {
+                        (0, node) => Via::Node(node as NodeId),
+                        (1, way) => Via::Way(way as WayId),
+                        (other, _) => {
+                            return Err(SnapshotError::Invalid(format!(
+                                "unknown via type {}",
+                                other
+                            )));
+                        }
+                    };
+                    let to = s.i64()?;
+                    tags.restrictions.push(TurnRestriction {
+                        id,
+                        restriction,
+                        from,
+                        via,
+                        to,
+                    });
+                }
+            }
+            IMPORT => {
+                let has_bounds = s.u8()? != 0;
+                let bounds = Bounds {
+                    min_lat: s.f64()?,
+                    min_lon: s.f64()?,
+                    max_lat: s.f64()?,
+                    max_lon: s.f64()?,
+                };
+                tags.bounds = has_bounds.then_some(bounds);
+                let count = s.count(16)?;
+                tags.missing_nodes.reserve(count);
+                for _ in 0..count {
+                    let (way, node) = (s.i64()?, s.u64()? as NodeId);
+                    tags.missing_nodes.push((way, node));
+                }
+            }
+            _ => continue,
+        }
+        if !s.bytes.is_empty() {
+            return Err(SnapshotError::Invalid(format!(
+                "{} bytes left over in section {}",
+                s.bytes.len(),
+                kind
+            )));
+        }
+        seen.push(kind);
+    }
+    if !seen.contains(&NODES) || !seen.contains(&EDGES) {
+        return Err(SnapshotError::Invalid("nodes or edges missing".into()));
+    }
+    Ok((g, tags))
+}
+
+/// Loads a snapshot file, see [`read_snapshot`].
+pub fn load_snapshot<P: AsRef<FsPath>>(
+    path: P,
+) -> Result<(Graph<OsmNode>, OsmTags), SnapshotError> {
+    read_snapshot(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::osmgraph::parse_osm_with_tags;
+
+    fn load_osm(path: &str) -> (Graph<OsmNode>, OsmTags) {
+        let mut g: Graph<OsmNode> = Graph::new();
+        let mut tags = OsmTags::default();
+        let mut reader = EventReader::new(BufReader::new(File::open(path).unwrap()));
+        parse_osm_with_tags(&mut reader, &mut g, &mut tags);
+        (g, tags)
+    }
+
+    fn snapshot_bytes(g: &Graph<OsmNode>, tags: &OsmTags) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_snapshot(&mut bytes, g, tags).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (g, tags) = load_osm("./test.osm");
+        assert_eq!(tags.ways[&5386504]["maxspeed"], "50 mph");
+        assert!(!tags.nodes.is_empty());
+
+        let bytes = snapshot_bytes(&g, &tags);
+        let (loaded, loaded_tags) = read_snapshot(&bytes).unwrap();
+        assert_eq!(loaded_tags, tags);
+        assert_eq!(loaded.node_ids(), g.node_ids());
+        assert_eq!(loaded.edge_ids(), g.edge_ids());
+        for id in g.node_ids() {
+            assert_eq!(loaded.get_node_val(id), g.get_node_val(id));
+        }
+        for id in g.edge_ids() {
+            assert_eq!(loaded.get_edge(id), g.get_edge(id));
+        }
+        // the same graph always gives the same bytes
+        assert_eq!(snapshot_bytes(&loaded, &loaded_tags), bytes);
+
+        let (g, mut tags) = load_osm("./turns.osm");
+        assert_eq!(tags.restrictions.len(), 3);
+        let (_, loaded_tags) = read_snapshot(&snapshot_bytes(&g, &tags)).unwrap();
+        assert_eq!(loaded_tags, tags);
+
+        // new ways have negative IDs
+        let way = tags.restrictions[0].from;
+        for edge_way in tags.edge_way.values_mut().filter(|w| **w == way) {
+            *edge_way = -1;
+        }
+        tags.ways.insert(-1, tags.ways[&way].clone());
+        tags.restrictions[0].from = -1;
+        tags.restrictions[0].via = Via::Way(-2);
+        tags.missing_nodes.push((-1, 99));
+        let (_, loaded_tags) = read_snapshot(&snapshot_bytes(&g, &tags)).unwrap();
+        assert_eq!(loaded_tags, tags);
+    }
+
+    #[test]
+    fn test_corrupt_snapshots() {
+        let (g, tags) = load_osm("./test.osm");
+        let bytes = snapshot_bytes(&g, &tags);
+
+        assert!(matches!(
+            read_snapshot(b"<?xml version"),
+            Err(SnapshotError::BadMagic)
+        ));
+        let mut newer = bytes.clone();
+        newer[8] = 3;
+        assert!(matches!(
+            read_snapshot(&newer),
+            Err(SnapshotError::UnsupportedVersion(3))
+        ));
+        let mut flipped = bytes.clone();
+        flipped[100] ^= 1;
+        assert!(matches!(
+            read_snapshot(&flipped),
+            Err(SnapshotError::Checksum(NODES))
+        ));
+        assert!(matches!(
+            read_snapshot(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Truncated)
+        ));
+    }
+}
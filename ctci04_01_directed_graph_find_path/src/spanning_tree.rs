@@ -0,0 +1,369 @@
+// -----------------------------------------------------------------------------------------------
+// Module spanning_tree
+//
+// Minimum spanning trees. Kruskal and Prim ignore the direction of the edges (undirected view of
+// the graph), Chu-Liu/Edmonds computes a minimum spanning arborescence, i.e. a tree of directed
+// edges in which every node can be reached from a given root.
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::graphtraits::{EdgeId, GraphView, NodeId, valid_edges};
+
+/// The edges of a spanning tree (or forest) together with their total weight.
+#[derive(Debug, PartialEq)]
+pub struct SpanningTree {
+    /// The edge IDs of the tree, sorted.
+    pub edges: Vec<EdgeId>,
+    pub weight: i64,
+}
+
+impl SpanningTree {
+    fn from_edges<T, G: GraphView<T> + ?Sized>(g: &G, mut edges: Vec<EdgeId>) -> Self {
+        edges.sort_unstable();
+        let weight = edges
+            .iter()
+            .filter_map(|edge_id| g.get_edge(*edge_id))
+            .map(|edge| edge.2 as i64)
+            .sum();
+        SpanningTree { edges, weight }
+    }
+}
+
+/// Union find with path compression, used by Kruskal.
+struct DisjointSet {
+    parent: HashMap<NodeId, NodeId>,
+}
+
+impl DisjointSet {
+    fn find(&mut self, node: NodeId) -> NodeId {
+        let mut root = node;
+        while let Some(parent) = self.parent.get(&root).copied() {
+            if parent == root {
+                break;
+            }
+            root = parent;
+        }
+        let mut node = node;
+        while node != root {
+            let next = self.parent[&node];
+            self.parent.insert(node, root);
+            node = next;
+        }
+        root
+    }
+
+    /// Merges the sets of `a` and `b`. Returns false if they were in the same set already.
+    fn union(&mut self, a: NodeId, b: NodeId) -> bool {
+        let root_a = self.find(a);
+        let root_b = self.find(b);
+        if root_a == root_b {
+            return false;
+        }
+        self.parent.insert(root_a, root_b);
+        true
+    }
+}
+
+/// Minimum spanning forest of the undirected view of the graph with Kruskal's algorithm.
+///
+/// Every edge is treated as if it could be used in both directions. If the graph is not
+/// (weakly) connected the result is a forest with one tree per component.
+pub fn kruskal_mst<T, G: GraphView<T> + ?Sized>(g: &G) -> SpanningTree {
+    let mut edges = valid_edges(g);
+    edges.sort_unstable_by_key(|(edge_id, _, _, weight)| (*weight, *edge_id));
+
+    let mut sets = DisjointSet {
+        parent: g.node_ids().into_iter().map(|id| (id, id)).collect(),
+    };
+    let mut tree = Vec::new();
+    for (edge_id, from, to, _) in edges {
+        if sets.union(from, to) {
+            tree.push(edge_id);
+        }
+    }
+    SpanningTree::from_edges(g, tree)
+}
+
+/// Minimum spanning forest of the undirected view of the graph with Prim's algorithm.
+///
+/// Gives a tree of the same weight as [`kruskal_mst`], but grows it node by node from the node
+/// with the smallest ID (and restarts on the next unvisited node for every further component).
+pub fn prim_mst<T, G: GraphView<T> + ?Sized>(g: &G) -> SpanningTree {
+    let mut adjacency: HashMap<NodeId, Vec<(EdgeId, NodeId, i32)>> = HashMap::new();
+    for (edge_id, from, to, weight) in valid_edges(g) {
+        if from != to {
+            adjacency
+                .entry(from)
+                .or_default()
+                .push((edge_id, to, weight));
+            adjacency
+                .entry(to)
+                .or_default()
+                .push((edge_id, from, weight));
+        }
+    }
+
+    let mut visited: HashSet<NodeId> = HashSet::new();
+    let mut tree = Vec::new();
+    for root in g.node_ids() {
+        if !visited.insert(root) {
+            continue;
+        }
+        let mut heap = BinaryHeap::new();
+        for (edge_id, to, weight) in adjacency.get(&root).into_iter().flatten() {
+            heap.push(Reverse((*weight, *edge_id, *to)));
+        }
+        while let Some(Reverse((_, edge_id, node))) = heap.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            tree.push(edge_id);
+            for (next_edge, to, weight) in adjacency.get(&node).into_iter().flatten() {
+                if !visited.contains(to) {
+                    heap.push(Reverse((*weight, *next_edge, *to)));
+                }
+            }
+        }
+    }
+    SpanningTree::from_edges(g, tree)
+}
+
+/// One edge of the (possibly contracted) graph used by Chu-Liu/Edmonds. `source` is the index of
+/// the edge in the graph one level up, or the position in the original edge list on the top level.
+struct ArcEdge {
+    from: usize,
+    to: usize,
+    weight: i64,
+    source: usize,
+}
+
+/// Chu-Liu/Edmonds on a graph with `n` dense node indices in which every node can be reached from
+/// `root`. Returns the indices (into `edges`) of the edges of a minimum arborescence.
+fn edmonds(n: usize, root: usize, edges: &[ArcEdge]) -> Vec<usize> {
+    // Step 1: pick the cheapest incoming edge for every node but the root
+    let mut best_in: Vec<Option<usize>> = vec![None; n];
+    for (idx, edge) in edges.iter().enumerate() {
+        if edge.from == edge.to || edge.to == root {
+            continue;
+        }
+        match best_in[edge.to] {
+            Some(best) if edges[best].weight <= edge.weight => {}
+            _ => best_in[edge.to] = Some(idx),
+        }
+    }
+
+    // Step 2: look for cycles among the picked edges
+    let mut cycle_of: Vec<Option<usize>> = vec![None; n];
+    let mut cycles = 0;
+    let mut seen_in_walk: Vec<Option<usize>> = vec![None; n];
+    for start in 0..n {
+        let mut node = start;
+        while node != root && seen_in_walk[node].is_none() && cycle_of[node].is_none() {
+            seen_in_walk[node] = Some(start);
+            node = edges[best_in[node].unwrap()].from;
+        }
+        if node != root && seen_in_walk[node] == Some(start) && cycle_of[node].is_none() {
+            // walked into a node of the current walk: this is a new cycle
+            let mut member = node;
+            loop {
+                cycle_of[member] = Some(cycles);
+                member = edges[best_in[member].unwrap()].from;
+                if member == node {
+                    break;
+                }
+            }
+            cycles += 1;
+        }
+    }
+    if cycles == 0 {
+        return best_in.into_iter().flatten().collect();
+    }
+
+    // Step 3: contract every cycle into one node, the other nodes are numbered after the cycles
+    let mut new_index = vec![0; n];
+    let mut next = cycles;
+    for node in 0..n {
+        new_index[node] = match cycle_of[node] {
+            Some(cycle) => cycle,
+            None => {
+                next += 1;
+                next - 1
+            }
+        };
+    }
+    let mut contracted = Vec::new();
+    for (idx, edge) in edges.iter().enumerate() {
+        let (from, to) = (new_index[edge.from], new_index[edge.to]);
+        if from == to {
+            continue;
+        }
+        // entering a cycle at `edge.to` means dropping the picked edge into `edge.to`
+        let weight = match cycle_of[edge.to] {
+            Some(_) => edge.weight - edges[best_in[edge.to].unwrap()].weight,
+            None => edge.weight,
+        };
+        contracted.push(ArcEdge {
+            from,
+            to,
+            weight,
+            source: idx,
+        });
+    }
+
+    // Step 4: solve the contracted graph and expand the cycles again
+    let chosen: Vec<usize> = edmonds(next, new_index[root], &contracted)
+        .into_iter()
+        .map(|idx| contracted[idx].source)
+        .collect();
+    let mut entered: HashSet<usize> = HashSet::new();
+    for idx in chosen.iter() {
+        if cycle_of[edges[*idx].to].is_some() {
+            entered.insert(edges[*idx].to);
+        }
+    }
+    let mut result = chosen;
+    for node in 0..n {
+        if cycle_of[node].is_some() && !entered.contains(&node) {
+            result.push(best_in[node].unwrap());
+        }
+    }
+    result
+}
+
+/// Minimum spanning arborescence rooted in `root` with the Chu-Liu/Edmonds algorithm.
+///
+/// The arborescence spans all nodes that can be reached from `root`; every one of them has exactly
+/// one incoming tree edge. Runs in O(N * E).
+///
+/// # Returns
+///
+/// `None` if `root` is not a node of the graph.
+pub fn min_arborescence<T, G: GraphView<T> + ?Sized>(g: &G, root: NodeId) -> Option<SpanningTree> {
+    g.get_node_val(root)?;
+
+    // restrict the graph to the part that can be reached from root and use dense indices
+    let mut index: HashMap<NodeId, usize> = HashMap::from([(root, 0)]);
+    let mut todo = vec![root];
+    while let Some(node) = todo.pop() {
+        for edge_id in g.find_edges_from(node).unwrap_or_default() {
+            if let Some(edge) = g.get_edge(edge_id)
+                && g.get_node_val(edge.1).is_some()
+                && !index.contains_key(&edge.1)
+            {
+                index.insert(edge.1, index.len());
+                todo.push(edge.1);
+            }
+        }
+    }
+    let edges: Vec<(EdgeId, NodeId, NodeId, i32)> = valid_edges(g)
+        .into_iter()
+        .filter(|(_, from, to, _)| index.contains_key(from) && index.contains_key(to))
+        .collect();
+    let arcs: Vec<ArcEdge> = edges
+        .iter()
+        .enumerate()
+        .map(|(idx, (_, from, to, weight))| ArcEdge {
+            from: index[from],
+            to: index[to],
+            weight: *weight as i64,
+            source: idx,
+        })
+        .collect();
+
+    let tree = edmonds(index.len(), 0, &arcs)
+        .into_iter()
+        .map(|idx| edges[arcs[idx].source].0)
+        .collect();
+    Some(SpanningTree::from_edges(g, tree))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphAlgo;
+    use crate::graphtraits::GraphCrud;
+
+    #[test]
+    fn test_kruskal_and_prim() {
+        let mut g: Graph<i32> = Graph::new();
+        let n: Vec<NodeId> = (0..5).map(|v| g.new_node(v)).collect();
+        let ab = g.new_edge(n[0], n[1], 4);
+        let _ac = g.new_edge(n[0], n[2], 8);
+        let bc = g.new_edge(n[1], n[2], 2);
+        // the direction of the edge does not matter for the undirected view
+        let dc = g.new_edge(n[3], n[2], 3);
+        let _bd = g.new_edge(n[1], n[3], 9);
+        let _loop = g.new_edge(n[3], n[3], -5);
+        // n[4] has no edges at all
+
+        let expected = SpanningTree {
+            edges: vec![ab, bc, dc],
+            weight: 9,
+        };
+        assert_eq!(g.kruskal_mst(), expected);
+        assert_eq!(g.prim_mst(), expected);
+    }
+
+    #[test]
+    fn test_spanning_forest() {
+        let mut g: Graph<i32> = Graph::new();
+        let n: Vec<NodeId> = (0..4).map(|v| g.new_node(v)).collect();
+        let e1 = g.new_edge(n[0], n[1], 1);
+        let e2 = g.new_edge(n[2], n[3], 2);
+        assert_eq!(kruskal_mst(&g).edges, vec![e1, e2]);
+        assert_eq!(prim_mst(&g).weight, 3);
+    }
+
+    #[test]
+    fn test_min_arborescence_with_cycle() {
+        let mut g: Graph<&str> = Graph::new();
+        let r = g.new_node("root");
+        let a = g.new_node("a");
+        let b = g.new_node("b");
+        let c = g.new_node("c");
+        let unreachable = g.new_node("unreachable");
+        let ra = g.new_edge(r, a, 10);
+        let _rb = g.new_edge(r, b, 12);
+        let ab = g.new_edge(a, b, 1);
+        let _ba = g.new_edge(b, a, 1);
+        let bc = g.new_edge(b, c, 2);
+        let _cr = g.new_edge(c, r, 1);
+        let _ua = g.new_edge(unreachable, a, 0);
+
+        let tree = g.min_arborescence(r).unwrap();
+        assert_eq!(
+            tree,
+            SpanningTree {
+                edges: vec![ra, ab, bc],
+                weight: 13
+            }
+        );
+    }
+
+    #[test]
+    fn test_min_arborescence_nested_cycles() {
+        // a cycle a -> b -> c -> a of cheap edges plus a cheap 2-cycle c <-> d
+        let mut g: Graph<&str> = Graph::new();
+        let r = g.new_node("root");
+        let a = g.new_node("a");
+        let b = g.new_node("b");
+        let c = g.new_node("c");
+        let d = g.new_node("d");
+        g.new_edge(r, a, 20);
+        g.new_edge(r, d, 15);
+        g.new_edge(a, b, 1);
+        g.new_edge(b, c, 1);
+        g.new_edge(c, a, 1);
+        g.new_edge(c, d, 1);
+        g.new_edge(d, c, 1);
+
+        let tree = g.min_arborescence(r).unwrap();
+        assert_eq!(tree.edges.len(), 4);
+        // r -> d (15), d -> c, c -> a, a -> b
+        assert_eq!(tree.weight, 18);
+        assert_eq!(g.min_arborescence(1000), None);
+    }
+}
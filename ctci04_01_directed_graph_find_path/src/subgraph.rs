@@ -0,0 +1,304 @@
+// -----------------------------------------------------------------------------------------------
+// Module subgraph
+//
+// Parts of a graph: a filtered view that hides nodes and edges without copying anything, a view
+// with different edge weights, and the induced subgraph which copies a set of nodes with the
+// edges between them into a new graph.
+//
+// A view for routing on fast roads inside a bounding box of an OSM graph:
+//
+//   let view = FilteredGraph::new(&osmgraph, |n| bbox.contains(n.lat, n.lon), |e| e.2 < 500);
+//   let path = view.shortest_path(from, to);
+//
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::graph::Graph;
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphAlgo, GraphCrud, GraphView, NodeId, Path};
+use crate::shortest_path::{all_simple_paths, bfs, dijkstra};
+
+/// A read-only view of a graph that only shows the nodes and edges accepted by the filters. An
+/// edge is only visible if both of its nodes are.
+pub struct FilteredGraph<'a, T, G: GraphView<T> + ?Sized, N, E> {
+    graph: &'a G,
+    node_filter: N,
+    edge_filter: E,
+    _values: PhantomData<T>,
+}
+
+impl<'a, T, G, N, E> FilteredGraph<'a, T, G, N, E>
+where
+    G: GraphView<T> + ?Sized,
+    N: Fn(&T) -> bool,
+    E: Fn(&EdgeTriplet) -> bool,
+{
+    /// A view of `graph` with the nodes whose value passes `node_filter` and the edges that pass
+    /// `edge_filter`. Use `|_| true` to keep all nodes or edges.
+    pub fn new(graph: &'a G, node_filter: N, edge_filter: E) -> Self {
+        FilteredGraph {
+            graph,
+            node_filter,
+            edge_filter,
+            _values: PhantomData,
+        }
+    }
+
+    fn has_node(&self, nodeid: NodeId) -> bool {
+        self.graph
+            .get_node_val(nodeid)
+            .is_some_and(|val| (self.node_filter)(val))
+    }
+
+    fn visible_edge(&self, edgeid: EdgeId) -> Option<EdgeTriplet> {
+        let edge = self.graph.get_edge(edgeid)?;
+        (self.has_node(edge.0) && self.has_node(edge.1) && (self.edge_filter)(&edge))
+            .then_some(edge)
+    }
+}
+
+impl<T, G, N, E> GraphView<T> for FilteredGraph<'_, T, G, N, E>
+where
+    G: GraphView<T> + ?Sized,
+    N: Fn(&T) -> bool,
+    E: Fn(&EdgeTriplet) -> bool,
+{
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        self.graph
+            .get_node_val(nodeid)
+            .filter(|val| (self.node_filter)(val))
+    }
+
+    fn get_edge(&self, edge: EdgeId) -> Option<EdgeTriplet> {
+        self.visible_edge(edge)
+    }
+
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        if !self.has_node(from) {
+            return None;
+        }
+        let edges = self.graph.find_edges_from(from)?;
+        Some(
+            edges
+                .into_iter()
+                .filter(|e| self.visible_edge(*e).is_some())
+                .collect(),
+        )
+    }
+
+    fn find_edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        if !self.has_node(to) {
+            return None;
+        }
+        let edges = self.graph.find_edges_to(to)?;
+        Some(
+            edges
+                .into_iter()
+                .filter(|e| self.visible_edge(*e).is_some())
+                .collect(),
+        )
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        self.graph
+            .node_ids()
+            .into_iter()
+            .filter(|id| self.has_node(*id))
+            .collect()
+    }
+
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        self.graph
+            .edge_ids()
+            .into_iter()
+            .filter(|id| self.visible_edge(*id).is_some())
+            .collect()
+    }
+}
+
+impl<T, G, N, E> GraphAlgo<T> for FilteredGraph<'_, T, G, N, E>
+where
+    G: GraphView<T> + ?Sized,
+    N: Fn(&T) -> bool,
+    E: Fn(&EdgeTriplet) -> bool,
+{
+    fn path_exists(&self, from: NodeId, to: NodeId) -> bool {
+        bfs(self, from, to).is_some()
+    }
+
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        dijkstra(self, from, to).map(|path| path.edges)
+    }
+
+    fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
+        all_simple_paths(self, from, to)
+    }
+}
+
+/// A read-only view of a graph with other edge weights, e.g. travel times instead of distances.
+/// Edges without a weight in the map are hidden, the nodes are the same as in the graph.
+pub struct ReweightedGraph<'a, T, G: GraphView<T> + ?Sized> {
+    graph: &'a G,
+    weights: &'a HashMap<EdgeId, i32>,
+    _values: PhantomData<T>,
+}
+
+impl<'a, T, G: GraphView<T> + ?Sized> ReweightedGraph<'a, T, G> {
+    pub fn new(graph: &'a G, weights: &'a HashMap<EdgeId, i32>) -> Self {
+        ReweightedGraph {
+            graph,
+            weights,
+            _values: PhantomData,
+        }
+    }
+
+    fn visible_edges(&self, edges: Option<Vec<EdgeId>>) -> Option<Vec<EdgeId>> {
+        Some(
+            edges?
+                .into_iter()
+                .filter(|e| self.weights.contains_key(e))
+                .collect(),
+        )
+    }
+}
+
+impl<T, G: GraphView<T> + ?Sized> GraphView<T> for ReweightedGraph<'_, T, G> {
+    fn get_node_val(&self, nodeid: NodeId) -> Option<&T> {
+        self.graph.get_node_val(nodeid)
+    }
+
+    fn get_edge(&self, edge: EdgeId) -> Option<EdgeTriplet> {
+        let weight = *self.weights.get(&edge)?;
+        let EdgeTriplet(from, to, _) = self.graph.get_edge(edge)?;
+        Some(EdgeTriplet(from, to, weight))
+    }
+
+    fn find_edges_from(&self, from: NodeId) -> Option<Vec<EdgeId>> {
+        self.visible_edges(self.graph.find_edges_from(from))
+    }
+
+    fn find_edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        self.visible_edges(self.graph.find_edges_to(to))
+    }
+
+    fn node_ids(&self) -> Vec<NodeId> {
+        self.graph.node_ids()
+    }
+
+    fn edge_ids(&self) -> Vec<EdgeId> {
+        self.visible_edges(Some(self.graph.edge_ids()))
+            .unwrap_or_default()
+    }
+}
+
+impl<T, G: GraphView<T> + ?Sized> GraphAlgo<T> for ReweightedGraph<'_, T, G> {
+    fn path_exists(&self, from: NodeId, to: NodeId) -> bool {
+        bfs(self, from, to).is_some()
+    }
+
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Path> {
+        dijkstra(self, from, to).map(|path| path.edges)
+    }
+
+    fn all_paths(&self, from: NodeId, to: NodeId) -> Option<Vec<Path>> {
+        all_simple_paths(self, from, to)
+    }
+}
+
+/// Copies the given nodes and all edges between them into a new graph. Node IDs are kept, the
+/// edges get new IDs. Node IDs that do not exist in `g` are ignored.
+pub fn induced_subgraph<T: Clone, G: GraphView<T> + ?Sized>(
+    g: &G,
+    node_ids: &[NodeId],
+) -> Graph<T> {
+    let mut result: Graph<T> = Graph::new();
+    for node_id in node_ids {
+        if let Some(val) = g.get_node_val(*node_id) {
+            result.set_node(val.clone(), *node_id);
+        }
+    }
+    for edge_id in g.edge_ids() {
+        if let Some(edge) = g.get_edge(edge_id)
+            && result.get_node_val(edge.0).is_some()
+            && result.get_node_val(edge.1).is_some()
+        {
+            result.new_edge(edge.0, edge.1, edge.2);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::sample_graph;
+
+    #[test]
+    fn test_node_filter() {
+        let (g, n, e) = sample_graph::<Graph<i32>>();
+        // without node 3 there is no way from 1 to 6
+        let view = FilteredGraph::new(&g, |v: &i32| *v != 3, |_: &EdgeTriplet| true);
+        assert_eq!(view.node_ids().len(), 5);
+        assert_eq!(view.get_node_val(n[2]), None);
+        assert_eq!(view.get_node_val(n[0]), Some(&1));
+        assert!(view.get_edge(e[1]).is_none());
+        assert_eq!(view.find_edges_from(n[1]), Some(vec![e[7]]));
+        assert_eq!(view.find_edges_to(n[2]), None);
+        assert!(!view.path_exists(n[0], n[5]));
+        assert_eq!(view.shortest_path(n[0], n[5]), None);
+        assert!(view.path_exists(n[0], n[1]));
+        assert_eq!(view.edge_ids().len(), 6);
+        // the generic algorithms run on the view
+        assert_eq!(view.strongly_connected_components().len(), 3);
+    }
+
+    #[test]
+    fn test_edge_filter() {
+        let (g, n, e) = sample_graph::<Graph<i32>>();
+        // without the cheap 1 -> 2 edge the way to 6 goes through 5
+        let view = FilteredGraph::new(&g, |_: &i32| true, |t: &EdgeTriplet| t.2 != 1);
+        assert_eq!(
+            view.shortest_path(n[0], n[5]),
+            Some(vec![e[6], e[8], e[1], e[2], e[4]])
+        );
+        assert_eq!(view.all_paths(n[0], n[5]).unwrap().len(), 1);
+        assert_eq!(g.shortest_path(n[0], n[5]).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_reweighted_graph() {
+        let (g, n, e) = sample_graph::<Graph<i32>>();
+        // all edges weigh 1 except 1 -> 2, 4 -> 6 is gone
+        let mut weights: HashMap<EdgeId, i32> = g.edge_ids().into_iter().map(|e| (e, 1)).collect();
+        weights.insert(e[0], 10);
+        weights.remove(&e[4]);
+        let view = ReweightedGraph::new(&g, &weights);
+        assert_eq!(view.get_edge(e[0]), Some(EdgeTriplet(n[0], n[1], 10)));
+        assert_eq!(view.get_edge(e[4]), None);
+        assert_eq!(view.edge_ids().len(), 9);
+        assert_eq!(view.find_edges_from(n[3]), Some(vec![]));
+        assert_eq!(view.shortest_path(n[0], n[1]), Some(vec![e[6], e[8]]));
+        assert!(!view.path_exists(n[0], n[5]));
+        // the graph keeps its weights
+        assert_eq!(g.shortest_path(n[0], n[1]), Some(vec![e[0]]));
+    }
+
+    #[test]
+    fn test_induced_subgraph() {
+        let (g, n, _) = sample_graph::<Graph<i32>>();
+        let sub = g.induced_subgraph(&[n[0], n[1], n[2], 1000]);
+        assert_eq!(sub.node_ids(), vec![n[0], n[1], n[2]]);
+        // 1 -> 2, 2 -> 3 and 3 -> 1
+        assert_eq!(sub.edge_ids().len(), 3);
+        assert!(sub.path_exists(n[2], n[1]));
+        assert_eq!(sub.get_node_val(n[1]), Some(&2));
+
+        // new nodes do not overwrite the copied ones
+        let mut sub = g.induced_subgraph(&[n[3], n[4], n[5]]);
+        for v in 7..10 {
+            sub.new_node(v);
+        }
+        assert_eq!(sub.node_ids().len(), 6);
+        assert_eq!(sub.get_node_val(n[3]), Some(&4));
+    }
+}
@@ -0,0 +1,197 @@
+// -----------------------------------------------------------------------------------------------
+// Module toposort
+//
+// Utilities for directed acyclic graphs, e.g. task dependency graphs where an edge `a -> b`
+// means "a has to be done before b".
+//
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::graphtraits::{GraphView, NodeId, Path};
+
+/// A cycle found in a graph that was expected to be acyclic.
+///
+/// `edges[i]` goes from `nodes[i]` to `nodes[i + 1]`, the last edge closes the cycle and goes
+/// back to `nodes[0]`. A self loop is a cycle with one node and one edge.
+#[derive(Debug, PartialEq)]
+pub struct Cycle {
+    pub nodes: Vec<NodeId>,
+    pub edges: Path,
+}
+
+/// Runs Kahn's algorithm. Returns the nodes in topological order and the remaining in degree of
+/// all nodes. Nodes that are part of or behind a cycle are never released and are missing from
+/// the order.
+fn kahn<T, G: GraphView<T> + ?Sized>(g: &G) -> (Vec<NodeId>, HashMap<NodeId, usize>) {
+    let mut in_degree: HashMap<NodeId, usize> =
+        g.node_ids().into_iter().map(|id| (id, 0)).collect();
+    for edge_id in g.edge_ids() {
+        if let Some(edge) = g.get_edge(edge_id)
+            && in_degree.contains_key(&edge.0)
+            && let Some(degree) = in_degree.get_mut(&edge.1)
+        {
+            *degree += 1;
+        }
+    }
+
+    // a BTreeSet instead of a plain queue, so that the order is deterministic: whenever there is
+    // a choice the node with the smallest ID comes first
+    let mut ready: BTreeSet<NodeId> = in_degree
+        .iter()
+        .filter(|(_, degree)| **degree == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    let mut order = Vec::with_capacity(in_degree.len());
+    while let Some(node) = ready.pop_first() {
+        order.push(node);
+        for edge_id in g.find_edges_from(node).unwrap_or_default() {
+            let Some(edge) = g.get_edge(edge_id) else {
+                continue;
+            };
+            if let Some(degree) = in_degree.get_mut(&edge.1) {
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.insert(edge.1);
+                }
+            }
+        }
+    }
+    (order, in_degree)
+}
+
+/// Sorts the nodes of a graph topologically with Kahn's algorithm.
+///
+/// # Returns
+///
+/// * `Ok(order)`  - all nodes, every edge goes from an earlier to a later node in `order`
+/// * `Err(cycle)` - a cycle that prevents a topological order
+pub fn topological_sort<T, G: GraphView<T> + ?Sized>(g: &G) -> Result<Vec<NodeId>, Cycle> {
+    let (order, in_degree) = kahn(g);
+    if order.len() == in_degree.len() {
+        return Ok(order);
+    }
+
+    // Every node that was not released still has an in edge from another node that was not
+    // released. Walking these in edges backwards must therefore run into a cycle.
+    let blocked = |id: &NodeId| in_degree.get(id).is_some_and(|degree| *degree > 0);
+    let start = *in_degree.keys().filter(|id| blocked(id)).min().unwrap();
+    let mut position: HashMap<NodeId, usize> = HashMap::new();
+    let mut nodes: Vec<NodeId> = Vec::new();
+    let mut edges: Path = Vec::new();
+    let mut node = start;
+    while !position.contains_key(&node) {
+        position.insert(node, nodes.len());
+        nodes.push(node);
+        let mut in_edges = g.find_edges_to(node).unwrap_or_default();
+        in_edges.sort_unstable();
+        let (edge_id, prev) = in_edges
+            .iter()
+            .filter_map(|edge_id| g.get_edge(*edge_id).map(|edge| (*edge_id, edge.0)))
+            .find(|(_, from)| blocked(from))
+            .unwrap();
+        edges.push(edge_id);
+        node = prev;
+    }
+
+    // `node` is the first node seen twice, everything before it only leads into the cycle.
+    // The walk went against the edge direction, so turn it around.
+    let first = position[&node];
+    let mut cycle_nodes = nodes.split_off(first);
+    let mut cycle_edges = edges.split_off(first);
+    cycle_nodes.reverse();
+    cycle_edges.reverse();
+    // reversed, nodes[i] is the target of edges[i]; rotate so that nodes[i] is its source
+    cycle_nodes.rotate_right(1);
+    Err(Cycle {
+        nodes: cycle_nodes,
+        edges: cycle_edges,
+    })
+}
+
+/// Returns true if the graph contains at least one directed cycle (self loops included).
+pub fn has_cycle<T, G: GraphView<T> + ?Sized>(g: &G) -> bool {
+    let (order, in_degree) = kahn(g);
+    order.len() < in_degree.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphAlgo;
+    use crate::graphtraits::GraphCrud;
+
+    /// Checks that the cycle is closed and consistent with the edges of the graph
+    fn assert_valid_cycle(g: &Graph<&str>, cycle: &Cycle) {
+        assert_eq!(cycle.nodes.len(), cycle.edges.len());
+        for (i, edge_id) in cycle.edges.iter().enumerate() {
+            let edge = g.get_edge(*edge_id).unwrap();
+            assert_eq!(edge.0, cycle.nodes[i]);
+            assert_eq!(edge.1, cycle.nodes[(i + 1) % cycle.nodes.len()]);
+        }
+    }
+
+    #[test]
+    fn test_topological_sort_dag() {
+        let mut g: Graph<&str> = Graph::new();
+        let dress = g.new_node("dress");
+        let shoes = g.new_node("shoes");
+        let socks = g.new_node("socks");
+        let trousers = g.new_node("trousers");
+        g.new_edge(socks, shoes, 1);
+        g.new_edge(trousers, shoes, 1);
+        g.new_edge(dress, trousers, 1);
+
+        let order = g.topological_sort().unwrap();
+        assert_eq!(order, vec![dress, socks, trousers, shoes]);
+        assert!(!g.has_cycle());
+    }
+
+    #[test]
+    fn test_topological_sort_finds_cycle() {
+        let mut g: Graph<&str> = Graph::new();
+        let a = g.new_node("a");
+        let b = g.new_node("b");
+        let c = g.new_node("c");
+        let d = g.new_node("d");
+        let e = g.new_node("e");
+        g.new_edge(a, b, 1);
+        g.new_edge(b, c, 1);
+        g.new_edge(c, d, 1);
+        g.new_edge(d, b, 1);
+        g.new_edge(d, e, 1);
+
+        assert!(g.has_cycle());
+        let cycle = g.topological_sort().unwrap_err();
+        assert_valid_cycle(&g, &cycle);
+        let mut nodes = cycle.nodes.clone();
+        nodes.sort_unstable();
+        assert_eq!(nodes, vec![b, c, d]);
+    }
+
+    #[test]
+    fn test_self_loop_is_cycle() {
+        let mut g: Graph<&str> = Graph::new();
+        let a = g.new_node("a");
+        let b = g.new_node("b");
+        g.new_edge(a, b, 1);
+        let self_loop = g.new_edge(b, b, 1);
+
+        assert!(g.has_cycle());
+        let cycle = g.topological_sort().unwrap_err();
+        assert_eq!(
+            cycle,
+            Cycle {
+                nodes: vec![b],
+                edges: vec![self_loop]
+            }
+        );
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let g: Graph<&str> = Graph::new();
+        assert_eq!(g.topological_sort(), Ok(vec![]));
+        assert!(!g.has_cycle());
+    }
+}
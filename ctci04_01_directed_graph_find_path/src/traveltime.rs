@@ -0,0 +1,375 @@
+// -----------------------------------------------------------------------------------------------
+// Module traveltime
+//
+// Travel time weights for OSM graphs. The import weighs edges by their length; here the time to
+// drive an edge is estimated from the `maxspeed` tag of its way, or from a default speed for the
+// `highway` class when there is no usable maxspeed. Ways whose class is not in the speed profile
+// (footways, steps, ...) cannot be used.
+//
+// A Router keeps both weightings of a loaded graph, so a query can ask for the shortest or the
+// fastest route without loading the file again. Both metrics only use the edges the profile allows. Its routes obey the turn restrictions:
+//
+//   let router = Router::new(osmgraph, tags);
+//   let shortest = router.route(from, to, Metric::Shortest);
+//   let fastest = router.route(from, to, Metric::Fastest);
+//
+
+use std::collections::HashMap;
+
+use derive_more::Display;
+
+use crate::graph::Graph;
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphView, NodeId};
+use crate::isochrone::{Isochrone, distance_cost, isochrone};
+use crate::osmgraph::{OsmNode, OsmTags, Tags};
+use crate::shortest_path::WeightedPath;
+use crate::subgraph::ReweightedGraph;
+use crate::turns::{TurnRules, turn_aware_dijkstra};
+
+/// Travel time weights are in milliseconds.
+pub const MILLIS_PER_SECOND: f64 = 1000.0;
+
+/// Speeds in km/h.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedProfile {
+    /// the speed for every usable highway class
+    pub highway: HashMap<String, f64>,
+    /// the speed of edges whose way has no highway tag or no tags at all
+    pub untagged: Option<f64>,
+}
+
+impl Default for SpeedProfile {
+    /// A car profile.
+    fn default() -> Self {
+        let highway = [
+            ("motorway", 110.0),
+            ("motorway_link", 60.0),
+            ("trunk", 90.0),
+            ("trunk_link", 50.0),
+            ("primary", 70.0),
+            ("primary_link", 40.0),
+            ("secondary", 60.0),
+            ("secondary_link", 40.0),
+            ("tertiary", 50.0),
+            ("tertiary_link", 30.0),
+            ("unclassified", 40.0),
+            ("residential", 30.0),
+            ("living_street", 10.0),
+            ("service", 20.0),
+            ("road", 30.0),
+        ]
+        .into_iter()
+        .map(|(class, speed)| (class.to_string(), speed))
+        .collect();
+        SpeedProfile {
+            highway,
+            untagged: Some(30.0),
+        }
+    }
+}
+
+/// Parses an OSM maxspeed value into km/h: plain numbers are km/h, "mph", "km/h" and "knots"
+/// units are understood, of several values separated by ';' the first one counts.
+///
+/// # Returns
+///
+/// The speed or `None` for values without a number like "none" or "signals".
+pub fn parse_maxspeed(value: &str) -> Option<f64> {
+    let value = value.split(';').next()?.trim();
+    if value == "walk" {
+        return Some(5.0);
+    }
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let speed: f64 = value[..number_end].parse().ok()?;
+    let factor = match value[number_end..].trim() {
+        "" | "km/h" | "kmh" | "kph" => 1.0,
+        "mph" => 1.609344,
+        "knots" => 1.852,
+        _ => return None,
+    };
+    (speed > 0.0).then_some(speed * factor)
+}
+
+/// The speed for an edge of a way with the given tags, `None` if the way cannot be used.
+fn way_speed(profile: &SpeedProfile, tags: Option<&Tags>) -> Option<f64> {
+    let Some(class) = tags.and_then(|tags| tags.get("highway")) else {
+        return profile.untagged;
+    };
+    let default = *profile.highway.get(class)?;
+    let maxspeed = tags
+        .and_then(|tags| tags.get("maxspeed"))
+        .and_then(|value| parse_maxspeed(value));
+    Some(maxspeed.unwrap_or(default))
+}
+
+/// The travel time of every usable edge in milliseconds. The edge weights of the graph have to
+/// be the distances from the OSM import (in centimeters).
+pub fn travel_time_weights<G: GraphView<OsmNode> + ?Sized>(
+    g: &G,
+    tags: &OsmTags,
+    profile: &SpeedProfile,
+) -> HashMap<EdgeId, i32> {
+    let mut weights = HashMap::new();
+    for edge_id in g.edge_ids() {
+        let Some(EdgeTriplet(_, _, centimeters)) = g.get_edge(edge_id) else {
+            continue;
+        };
+        let way_tags = tags
+            .edge_way
+            .get(&edge_id)
+            .and_then(|way| tags.ways.get(way));
+        let Some(speed) = way_speed(profile, way_tags) else {
+            continue;
+        };
+        let meters = centimeters as f64 / 100.0;
+        let millis = meters / (speed / 3.6) * MILLIS_PER_SECOND;
+        weights.insert(edge_id, millis.round().min(i32::MAX as f64) as i32);
+    }
+    weights
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// by distance, weights in centimeters
+    #[display("shortest")]
+    Shortest,
+    /// by travel time, weights in milliseconds
+    #[display("fastest")]
+    Fastest,
+}
+
+/// A loaded OSM graph with distance and travel time weights.
+pub struct Router {
+    graph: Graph<OsmNode>,
+    tags: OsmTags,
+    travel_times: HashMap<EdgeId, i32>,
+    /// the distances of the edges that have a travel time
+    distances: HashMap<EdgeId, i32>,
+    turn_rules: TurnRules,
+}
+
+/// The distance weights of the edges with a travel time.
+fn usable_distances(
+    g: &Graph<OsmNode>,
+    travel_times: &HashMap<EdgeId, i32>,
+) -> HashMap<EdgeId, i32> {
+    travel_times
+        .keys()
+        .filter_map(|edge_id| Some((*edge_id, g.get_edge(*edge_id)?.2)))
+        .collect()
+}
+
+impl Router {
+    /// A router with the default (car) speed profile.
+    pub fn new(graph: Graph<OsmNode>, tags: OsmTags) -> Self {
+        Router::with_profile(graph, tags, &SpeedProfile::default())
+    }
+
+    pub fn with_profile(graph: Graph<OsmNode>, tags: OsmTags, profile: &SpeedProfile) -> Self {
+        let travel_times = travel_time_weights(&graph, &tags, profile);
+        let distances = usable_distances(&graph, &travel_times);
+        let turn_rules = TurnRules::new(&tags.restrictions);
+        Router {
+            graph,
+            tags,
+            travel_times,
+            distances,
+            turn_rules,
+        }
+    }
+
+    pub fn graph(&self) -> &Graph<OsmNode> {
+        &self.graph
+    }
+
+    pub fn tags(&self) -> &OsmTags {
+        &self.tags
+    }
+
+    /// Recomputes the travel times, e.g. for another vehicle.
+    pub fn set_profile(&mut self, profile: &SpeedProfile) {
+        self.travel_times = travel_time_weights(&self.graph, &self.tags, profile);
+        self.distances = usable_distances(&self.graph, &self.travel_times);
+    }
+
+    /// The graph with its distances, only the usable edges are visible.
+    pub fn distance_graph(&self) -> ReweightedGraph<'_, OsmNode, Graph<OsmNode>> {
+        ReweightedGraph::new(&self.graph, &self.distances)
+    }
+
+    /// The graph with the travel times as weights, only the usable edges are visible.
+    pub fn travel_time_graph(&self) -> ReweightedGraph<'_, OsmNode, Graph<OsmNode>> {
+        ReweightedGraph::new(&self.graph, &self.travel_times)
+    }
+
+    /// The shortest or fastest route that makes no forbidden turns.
+    ///
+    /// # Returns
+    ///
+    /// The path and its weight in centimeters (shortest) or milliseconds (fastest), `None` if
+    /// there is no route.
+    pub fn route(&self, from: NodeId, to: NodeId, metric: Metric) -> Option<WeightedPath> {
+        match metric {
+            Metric::Shortest => turn_aware_dijkstra(
+                &self.distance_graph(),
+                &self.tags.edge_way,
+                &self.turn_rules,
+                from,
+                to,
+            ),
+            Metric::Fastest => turn_aware_dijkstra(
+                &self.travel_time_graph(),
+                &self.tags.edge_way,
+                &self.turn_rules,
+                from,
+                to,
+            ),
+        }
+    }
+
+    /// The area reachable within `budget` meters (shortest) or seconds (fastest).
+    pub fn isochrone(&self, start: NodeId, budget: f64, metric: Metric) -> Option<Isochrone> {
+        match metric {
+            Metric::Shortest => isochrone(&self.graph, start, budget, |edge_id, edge| {
+                self.travel_times.get(&edge_id)?;
+                distance_cost(edge_id, edge)
+            }),
+            Metric::Fastest => isochrone(&self.graph, start, budget, |edge_id, _| {
+                Some(*self.travel_times.get(&edge_id)? as f64 / MILLIS_PER_SECOND)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::graphtraits::GraphCrud;
+    use crate::osmgraph::{create_osm_graph, parse_osm_with_tags};
+
+    fn node(id: NodeId, lat: f64, lon: f64) -> OsmNode {
+        OsmNode {
+            id,
+            lat,
+            lon,
+            version: 1,
+        }
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_maxspeed() {
+        assert_eq!(parse_maxspeed("50"), Some(50.0));
+        assert_eq!(parse_maxspeed("30 km/h"), Some(30.0));
+        assert!((parse_maxspeed("50 mph").unwrap() - 80.4672).abs() < 1e-9);
+        assert!((parse_maxspeed("10 knots").unwrap() - 18.52).abs() < 1e-9);
+        assert_eq!(parse_maxspeed("60;40"), Some(60.0));
+        assert_eq!(parse_maxspeed("walk"), Some(5.0));
+        assert_eq!(parse_maxspeed("none"), None);
+        assert_eq!(parse_maxspeed("DE:urban"), None);
+        assert_eq!(parse_maxspeed("0"), None);
+    }
+
+    #[test]
+    fn test_shortest_and_fastest() {
+        // 1 -> 2 -> 4 is 2 km of residential road, 1 -> 3 -> 4 is 3 km of motorway and
+        // 1 -> 4 is a 500 m footway
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        for (id, lat) in [(1, 0.0), (2, 0.01), (3, 0.02), (4, 0.03)] {
+            g.set_node(node(id, lat, 0.0), id);
+        }
+        let mut osm_tags = OsmTags::default();
+        osm_tags
+            .ways
+            .insert(10, tags(&[("highway", "residential")]));
+        osm_tags
+            .ways
+            .insert(20, tags(&[("highway", "motorway"), ("maxspeed", "90")]));
+        osm_tags.ways.insert(30, tags(&[("highway", "footway")]));
+        for (from, to, meters, way) in [
+            (1, 2, 1000, 10),
+            (2, 4, 1000, 10),
+            (1, 3, 1500, 20),
+            (3, 4, 1500, 20),
+            (1, 4, 500, 30),
+        ] {
+            let edge_id = g.new_edge(from, to, meters * 100);
+            osm_tags.edge_way.insert(edge_id, way);
+        }
+        let router = Router::new(g, osm_tags);
+
+        // the footway is not usable by car for either metric
+        let shortest = router.route(1, 4, Metric::Shortest).unwrap();
+        assert_eq!(shortest.edges, vec![1, 2]);
+        assert_eq!(shortest.weight, 200000);
+        // 3 km at 90 km/h take 120 s, 2 km at 30 km/h 240 s
+        let fastest = router.route(1, 4, Metric::Fastest).unwrap();
+        assert_eq!(fastest.edges, vec![3, 4]);
+        assert_eq!(fastest.weight, 120000);
+        assert_eq!(router.travel_time_graph().get_edge(1).unwrap().2, 120000);
+        assert_eq!(router.travel_time_graph().get_edge(5), None);
+
+        let area = router.isochrone(1, 150.0, Metric::Fastest).unwrap();
+        assert_eq!(area.node_ids(), vec![1, 2, 3, 4]);
+        let area = router.isochrone(1, 100.0, Metric::Fastest).unwrap();
+        assert_eq!(area.node_ids(), vec![1, 3]);
+        let area = router.isochrone(1, 1200.0, Metric::Shortest).unwrap();
+        assert_eq!(area.node_ids(), vec![1, 2]);
+
+        // a profile for walking
+        let mut router = router;
+        let walking = SpeedProfile {
+            highway: [
+                ("footway".to_string(), 5.0),
+                ("residential".to_string(), 5.0),
+            ]
+            .into_iter()
+            .collect(),
+            untagged: None,
+        };
+        router.set_profile(&walking);
+        assert_eq!(router.route(1, 4, Metric::Fastest).unwrap().edges, vec![5]);
+        assert_eq!(router.route(1, 4, Metric::Shortest).unwrap().edges, vec![5]);
+        assert_eq!(router.route(1, 3, Metric::Shortest), None);
+        assert_eq!(router.route(1, 3, Metric::Fastest), None);
+    }
+
+    #[test]
+    fn test_osm() {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let mut osm_tags = OsmTags::default();
+        let mut reader = EventReader::new(BufReader::new(File::open("./test.osm").unwrap()));
+        parse_osm_with_tags(&mut reader, &mut g, &mut osm_tags);
+        let router = Router::new(g, osm_tags);
+        let times = router.travel_time_graph();
+        for edge_id in router.graph().edge_ids() {
+            let way = router.tags().ways.get(&router.tags().edge_way[&edge_id]);
+            let meters = router.graph().get_edge(edge_id).unwrap().2 as f64 / 100.0;
+            match way
+                .and_then(|way| way.get("highway"))
+                .map(|class| class.as_str())
+            {
+                // the motorway has "maxspeed" = "50 mph"
+                Some("motorway") => {
+                    let expected = meters / (80.4672 / 3.6) * 1000.0;
+                    assert!((times.get_edge(edge_id).unwrap().2 as f64 - expected).abs() <= 0.5);
+                }
+                Some("footway") => assert_eq!(times.get_edge(edge_id), None),
+                _ => assert!(times.get_edge(edge_id).is_some()),
+            }
+        }
+    }
+}
@@ -0,0 +1,433 @@
+// -----------------------------------------------------------------------------------------------
+// Module turns
+//
+// Shortest paths that obey the turn restrictions of an OSM graph. Whether a turn is allowed
+// depends on the edge a node was reached by, so the search runs on edges instead of nodes: a
+// state is the last edge driven, and going on from edge e to edge f is only possible if the turn
+// from the way of e into the way of f is allowed at the node between them. A via node rule whose
+// from and to way are the same (no_u_turn) is a U-turn rule, it only concerns going back to the
+// node one came from; driving straight through along the way is not affected.
+//
+// Restrictions with a via way (from way A over way V into way B) are followed with a little
+// extra state: after turning from A into V the search remembers the pair (A, V) until it leaves V
+// again, and then checks every restriction from A over V.
+//
+//   let rules = TurnRules::new(&tags.restrictions);
+//   let path = turn_aware_dijkstra(&osmgraph, &tags.edge_way, &rules, from, to);
+//
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::graphtraits::{EdgeId, EdgeTriplet, GraphView, NodeId, Path};
+use crate::osmgraph::{TurnRestriction, Via, WayId};
+use crate::shortest_path::WeightedPath;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    from: WayId,
+    to: WayId,
+    only: bool,
+    /// the rule is about turning back into the same way
+    u_turn: bool,
+}
+
+impl Rule {
+    fn matches(&self, to: WayId, u_turn: bool) -> bool {
+        self.to == to && (u_turn || !self.u_turn)
+    }
+}
+
+/// The turn restrictions indexed for the search.
+#[derive(Debug, Default, Clone)]
+pub struct TurnRules {
+    /// via node -> rules
+    at_node: HashMap<NodeId, Vec<Rule>>,
+    /// (from way, via way) -> rules
+    via_way: HashMap<(WayId, WayId), Vec<Rule>>,
+}
+
+/// The from way and via way of the via way restrictions that are being followed.
+type Pending = Option<(WayId, WayId)>;
+
+/// A search state: the last edge and the pending via way restrictions.
+type State = (EdgeId, Pending);
+
+impl TurnRules {
+    pub fn new(restrictions: &[TurnRestriction]) -> Self {
+        let mut rules = TurnRules::default();
+        for restriction in restrictions {
+            let rule = Rule {
+                from: restriction.from,
+                to: restriction.to,
+                only: restriction.is_only(),
+                // going on along the same way is straight on, not a U-turn
+                u_turn: restriction.from == restriction.to
+                    && !restriction.restriction.ends_with("straight_on"),
+            };
+            match restriction.via {
+                Via::Node(node) => rules.at_node.entry(node).or_default().push(rule),
+                Via::Way(way) => rules
+                    .via_way
+                    .entry((restriction.from, way))
+                    .or_default()
+                    .push(rule),
+            }
+        }
+        rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.at_node.is_empty() && self.via_way.is_empty()
+    }
+
+    /// Whether the turn from way `from` into way `to` at `node` is allowed by the via node
+    /// restrictions. `u_turn` tells whether the turn leads back to the node before `node`. Edges
+    /// without a way are never restricted.
+    pub fn turn_allowed(
+        &self,
+        node: NodeId,
+        from: Option<WayId>,
+        to: Option<WayId>,
+        u_turn: bool,
+    ) -> bool {
+        let (Some(from), Some(to)) = (from, to) else {
+            return true;
+        };
+        self.at_node.get(&node).is_none_or(|rules| {
+            rules
+                .iter()
+                .filter(|rule| rule.from == from)
+                .all(|rule| rule.matches(to, u_turn) == rule.only)
+        })
+    }
+
+    /// The pending restrictions after going from way `from` into way `to`, `None` if that is not
+    /// allowed.
+    fn next(
+        &self,
+        node: NodeId,
+        from: Option<WayId>,
+        to: Option<WayId>,
+        u_turn: bool,
+        pending: Pending,
+    ) -> Option<Pending> {
+        if !self.turn_allowed(node, from, to, u_turn) {
+            return None;
+        }
+        if let Some((from_way, via)) = pending {
+            if to == Some(via) {
+                return Some(pending);
+            }
+            // leaving the via way, every restriction from `from_way` over `via` applies
+            if !self.via_way[&(from_way, via)]
+                .iter()
+                .all(|rule| (to == Some(rule.to)) == rule.only)
+            {
+                return None;
+            }
+        }
+        let (Some(from), Some(to)) = (from, to) else {
+            return Some(None);
+        };
+        Some(self.via_way.contains_key(&(from, to)).then_some((from, to)))
+    }
+}
+
+/// Dijkstra from `from` to `to` that only makes turns allowed by `rules`.
+///
+/// # Arguments
+///
+/// * `g` - the graph, the edges weights are used
+/// * `edge_way` - the way of every edge, from the OSM import
+/// * `rules` - the turn restrictions
+/// * `from`, `to` - the start and end node
+///
+/// # Returns
+///
+/// The shortest legal path or `None` if there is none. The path from a node to itself is the
+/// empty path.
+pub fn turn_aware_dijkstra<T, G: GraphView<T> + ?Sized>(
+    g: &G,
+    edge_way: &HashMap<EdgeId, WayId>,
+    rules: &TurnRules,
+    from: NodeId,
+    to: NodeId,
+) -> Option<WeightedPath> {
+    g.get_node_val(from)?;
+    g.get_node_val(to)?;
+    if from == to {
+        return Some(WeightedPath {
+            weight: 0,
+            edges: Vec::new(),
+        });
+    }
+
+    let mut dist: HashMap<State, i64> = HashMap::new();
+    let mut pred: HashMap<State, State> = HashMap::new();
+    let mut heap = BinaryHeap::new();
+    for edge_id in g.find_edges_from(from).unwrap_or_default() {
+        let Some(edge) = g.get_edge(edge_id) else {
+            continue;
+        };
+        let state = (edge_id, None);
+        let d = edge.2 as i64;
+        if dist.get(&state).is_none_or(|old| d < *old) {
+            dist.insert(state, d);
+            heap.push(Reverse((d, state)));
+        }
+    }
+
+    while let Some(Reverse((d, state))) = heap.pop() {
+        if d > dist[&state] {
+            continue;
+        }
+        let (edge_id, pending) = state;
+        let EdgeTriplet(previous, node, _) = g.get_edge(edge_id)?;
+        if node == to {
+            let mut edges: Path = vec![edge_id];
+            let mut state = state;
+            while let Some(previous) = pred.get(&state) {
+                edges.push(previous.0);
+                state = *previous;
+            }
+            edges.reverse();
+            return Some(WeightedPath { weight: d, edges });
+        }
+        let way = edge_way.get(&edge_id).copied();
+        for next_id in g.find_edges_from(node).unwrap_or_default() {
+            let Some(next) = g.get_edge(next_id) else {
+                continue;
+            };
+            if g.get_node_val(next.1).is_none() {
+                continue;
+            }
+            let u_turn = next.1 == previous;
+            let Some(next_pending) =
+                rules.next(node, way, edge_way.get(&next_id).copied(), u_turn, pending)
+            else {
+                continue;
+            };
+            let next_state = (next_id, next_pending);
+            let nd = d + next.2 as i64;
+            // ties are broken by the previous edge so that results are reproducible, the first
+            // edges of the path have no previous edge and are never replaced on a tie
+            let better = match dist.get(&next_state) {
+                None => true,
+                Some(old) => {
+                    nd < *old
+                        || (nd == *old && pred.get(&next_state).is_some_and(|p| edge_id < p.0))
+                }
+            };
+            if better {
+                dist.insert(next_state, nd);
+                pred.insert(next_state, state);
+                heap.push(Reverse((nd, next_state)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use xml::EventReader;
+
+    use crate::conformance::sample_graph;
+    use crate::graph::Graph;
+    use crate::graphtraits::GraphCrud;
+    use crate::osmgraph::{OsmNode, OsmTags, create_osm_graph, parse_osm_with_tags};
+    use crate::shortest_path::dijkstra;
+
+    // turns.osm: a crossing at node 1 with North Street (way 100, 3 -> 1 -> 2), East Street
+    // (way 101, 5 -> 1 -> 4) and West Street (way 102, 1 -> 5), and a detour 4 -> 6 -> 5
+    // (way 105) and 6 -> 2 (way 106). No left turn from North into West Street, only straight on
+    // from East Street.
+    fn turns_osm() -> (Graph<OsmNode>, OsmTags) {
+        let mut g: Graph<OsmNode> = create_osm_graph();
+        let mut tags = OsmTags::default();
+        let mut reader = EventReader::new(BufReader::new(File::open("./turns.osm").unwrap()));
+        parse_osm_with_tags(&mut reader, &mut g, &mut tags);
+        (g, tags)
+    }
+
+    #[test]
+    fn test_parse_restrictions() {
+        let (_, tags) = turns_osm();
+        assert_eq!(
+            tags.restrictions,
+            vec![
+                TurnRestriction {
+                    id: 200,
+                    restriction: "no_left_turn".to_string(),
+                    from: 100,
+                    via: Via::Node(1),
+                    to: 102,
+                },
+                TurnRestriction {
+                    id: 201,
+                    restriction: "only_straight_on".to_string(),
+                    from: 101,
+                    via: Via::Node(1),
+                    to: 101,
+                },
+                TurnRestriction {
+                    id: 202,
+                    restriction: "no_u_turn".to_string(),
+                    from: 105,
+                    via: Via::Way(106),
+                    to: 100,
+                },
+            ]
+        );
+        assert!(tags.restrictions[1].is_only());
+    }
+
+    #[test]
+    fn test_turn_restrictions() {
+        let (g, tags) = turns_osm();
+        let rules = TurnRules::new(&tags.restrictions);
+        let route = |from, to| turn_aware_dijkstra(&g, &tags.edge_way, &rules, from, to);
+        // edges: 3 -> 1, 1 -> 2, 5 -> 1, 1 -> 4, 1 -> 5, 4 -> 6, 6 -> 5, 6 -> 2
+
+        // the left turn is the shortest way, but forbidden
+        assert_eq!(dijkstra(&g, 3, 5).unwrap().edges, vec![1, 5]);
+        assert_eq!(route(3, 5).unwrap().edges, vec![1, 4, 6, 7]);
+        // going straight on is fine
+        assert_eq!(route(3, 2).unwrap().edges, vec![1, 2]);
+        // from East Street only straight on, so not left into North Street
+        assert_eq!(dijkstra(&g, 5, 2).unwrap().edges, vec![3, 2]);
+        assert_eq!(route(5, 2).unwrap().edges, vec![3, 4, 6, 8]);
+        assert_eq!(route(1, 1).unwrap().edges, vec![]);
+        assert_eq!(route(2, 1), None);
+
+        // without rules it is Dijkstra
+        let (g, n, _) = sample_graph::<Graph<i32>>();
+        let no_rules = TurnRules::default();
+        assert!(no_rules.is_empty());
+        for from in n.iter() {
+            for to in n.iter() {
+                assert_eq!(
+                    turn_aware_dijkstra(&g, &HashMap::new(), &no_rules, *from, *to)
+                        .map(|p| p.weight),
+                    dijkstra(&g, *from, *to).map(|p| p.weight)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_via_way() {
+        // a -> b (way 1), b -> c (way 2), c -> d (way 3) and a shortcut a -> c (way 4)
+        let mut g: Graph<i32> = Graph::new();
+        for id in 1..=4 {
+            g.set_node(id as i32, id);
+        }
+        let edge_way: HashMap<EdgeId, WayId> = [
+            (g.new_edge(1, 2, 1), 1),
+            (g.new_edge(2, 3, 1), 2),
+            (g.new_edge(3, 4, 1), 3),
+            (g.new_edge(1, 3, 5), 4),
+        ]
+        .into_iter()
+        .collect();
+        let restriction = |name: &str, to| TurnRestriction {
+            id: 1,
+            restriction: name.to_string(),
+            from: 1,
+            via: Via::Way(2),
+            to,
+        };
+        let rules = TurnRules::new(&[restriction("no_straight_on", 3)]);
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 1, 4).unwrap();
+        assert_eq!(path.edges, vec![4, 3]);
+        // the via way itself may still be used
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 1, 3).unwrap();
+        assert_eq!(path.edges, vec![1, 2]);
+
+        let rules = TurnRules::new(&[restriction("only_straight_on", 3)]);
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 1, 4).unwrap();
+        assert_eq!(path.edges, vec![1, 2, 3]);
+        let rules = TurnRules::new(&[restriction("only_straight_on", 4)]);
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 1, 4).unwrap();
+        assert_eq!(path.edges, vec![4, 3]);
+
+        // two restrictions with the same from and via way both apply, so c -> e (way 5) is only
+        // reached over the shortcut
+        let mut edge_way = edge_way;
+        g.set_node(5, 5);
+        let ce = g.new_edge(3, 5, 1);
+        edge_way.insert(ce, 5);
+        let rules = TurnRules::new(&[
+            restriction("no_straight_on", 3),
+            restriction("no_right_turn", 5),
+        ]);
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 1, 5).unwrap();
+        assert_eq!(path.edges, vec![4, ce]);
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 1, 4).unwrap();
+        assert_eq!(path.edges, vec![4, 3]);
+    }
+
+    #[test]
+    fn test_zero_weight_detour_back_to_start() {
+        let mut g: Graph<i32> = Graph::new();
+        for id in 1..=3 {
+            g.set_node(id as i32, id);
+        }
+        g.new_edge(1, 2, 0);
+        g.new_edge(2, 1, 0);
+        let e13 = g.new_edge(1, 3, 5);
+        let path = turn_aware_dijkstra(&g, &HashMap::new(), &TurnRules::default(), 1, 3).unwrap();
+        assert_eq!(path.edges, vec![e13]);
+        assert_eq!(path.weight, 5);
+    }
+
+    #[test]
+    fn test_u_turn() {
+        // 0 -> 1 (way 9), then way 7 in both directions 1 <-> 2 and on to 4, and 1 -> 3 (way 8).
+        // Turning right from 9 into 8 at node 1 is forbidden, so 3 is only reached by turning
+        // back at node 2.
+        let mut g: Graph<i32> = Graph::new();
+        for id in 0..=4 {
+            g.set_node(id as i32, id);
+        }
+        let edges = [(0, 1, 9), (1, 2, 7), (2, 1, 7), (1, 3, 8), (2, 4, 7)];
+        let mut edge_way: HashMap<EdgeId, WayId> = HashMap::new();
+        let ids: Vec<EdgeId> = edges
+            .iter()
+            .map(|(from, to, way)| {
+                let edge_id = g.new_edge(*from, *to, 1);
+                edge_way.insert(edge_id, *way);
+                edge_id
+            })
+            .collect();
+        let restriction = |name: &str, from, via, to| TurnRestriction {
+            id: 1,
+            restriction: name.to_string(),
+            from,
+            via: Via::Node(via),
+            to,
+        };
+        let no_right = restriction("no_right_turn", 9, 1, 8);
+        let rules = TurnRules::new(std::slice::from_ref(&no_right));
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 0, 3).unwrap();
+        assert_eq!(path.edges, vec![ids[0], ids[1], ids[2], ids[3]]);
+
+        let rules = TurnRules::new(&[no_right, restriction("no_u_turn", 7, 2, 7)]);
+        assert!(!rules.turn_allowed(2, Some(7), Some(7), true));
+        assert!(rules.turn_allowed(2, Some(7), Some(7), false));
+        assert_eq!(turn_aware_dijkstra(&g, &edge_way, &rules, 0, 3), None);
+        // driving through node 2 along way 7 is no U-turn
+        let path = turn_aware_dijkstra(&g, &edge_way, &rules, 0, 4).unwrap();
+        assert_eq!(path.edges, vec![ids[0], ids[1], ids[4]]);
+
+        // only straight on along the same way allows going through but not turning back
+        let rules = TurnRules::new(&[restriction("only_straight_on", 7, 2, 7)]);
+        assert!(rules.turn_allowed(2, Some(7), Some(7), false));
+        assert!(!rules.turn_allowed(2, Some(7), Some(8), false));
+    }
+}